@@ -1,40 +1,125 @@
 use anyhow::{anyhow, Result};
+use log::*;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use cgmath::{point3, vec2, vec3, vec4, Vector2, Deg, EuclideanSpace, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix};
 
 use vulkanalia::vk::{ExtDebugUtilsExtension, KhrSurfaceExtension, KhrSwapchainExtension};
 use vulkanalia::loader::{LibloadingLoader, LIBRARY};
 use vulkanalia::prelude::v1_4::*;
 
+use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::window::Window;
 
-use crate::instance::VALIDATION_ENABLED;
 use crate::app::data;
+use crate::camera::Camera;
+use crate::config::Config;
+use crate::core::debug_utils::{
+    cmd_begin_label, cmd_end_label, cmd_insert_label, queue_begin_label, queue_end_label, COMPUTE_COLOR, POST_COLOR, SCENE_COLOR,
+    SHADOW_COLOR,
+};
+use crate::core::messages::MessageSink;
+use crate::debug_draw::{self, DebugDraw, ShapeOptions};
+use crate::camera::controller::{CameraController, OrbitController};
+use crate::frame::uniforms::{self, FrameUniforms};
+use crate::gui::{self, Gui};
+use crate::input::Input;
+use crate::input::bindings::{Action, Bindings, DEFAULT_BINDINGS_PATH};
+use crate::light::{GpuLight, Light, ShadingModel, DEFAULT_MAX_LIGHTS};
+use crate::material::MaterialData;
+use crate::mesh::MeshData;
+use crate::model::gltf::load_gltf;
+use crate::scene::occlusion::OcclusionQueries;
+use crate::scene::{self, LightItem, MaterialId, MeshId, Scene, Transform};
+use crate::screenshot::{self, Screenshot};
+use crate::post::{self, PostChain, PostEffect, PostPass, Tonemapper};
+use crate::profiler::frame_stats::{FrameStats, FrameTiming, TITLE_INTERVAL};
+use crate::profiler::{self, gpu::GpuProfiler, AVERAGE_FRAMES};
+use crate::shadow::{self, ShadowPlan, ShadowSettings, ShadowView};
+use crate::skybox;
+use crate::text::{self, Align, Font, TextBatch, TextStyle};
+use crate::texture::cubemap::CubemapData;
+use crate::frame::sync::{self as frame_sync, MAX_FRAMES_IN_FLIGHT};
+use crate::pipeline::graphics::ObjectPushConstants;
+use crate::pipeline::push_constants::cmd_push_constants;
 use crate::{
     device,
     swapchain::{
         swapchain,
-        image_views
+        image_views,
     },
     pipeline,
     instance,
     frame,
-    command
+    command,
+    instancing,
+    particles
 };
 
 // Our Vulkan app.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct App {
     entry: Entry,
     instance: Instance,
     data: data::AppData,
     device: Device,
+    frame: usize,
+    pub resized: bool,
+    /// Whether swapchain settings changed and it needs recreating.
+    swapchain_outdated: bool,
+    start: Instant,
+    last_frame: Instant,
+    camera: Camera,
+    controller: CameraController,
+    input: Input,
+    wireframe: bool,
+    shading_model: ShadingModel,
+    /// A screenshot being copied out of the frame that is being rendered.
+    screenshot: Option<Screenshot>,
+    scene: Scene,
+    /// Lines drawn over the scene, see `debug_draw_mut`.
+    debug_draw: DebugDraw,
+    /// Whether lights and shadow frustums are drawn with `debug_draw`.
+    gizmos: bool,
+    /// Text drawn over the scene, see `text_mut`.
+    text: TextBatch,
+    /// Whether frame timings are drawn with `text`.
+    stats_overlay: bool,
+    gui: Gui,
+    /// Whether the settings panel is shown.
+    gui_visible: bool,
+    /// Actions the GUI's buttons triggered, applied with the next frame's
+    /// input.
+    gui_actions: Vec<Action>,
+    frame_stats: FrameStats,
+    /// Where to write every frame's timings when the app is destroyed.
+    frame_stats_csv: Option<String>,
+    /// The window title without the statistics.
+    title: String,
+    last_title_update: Instant,
 }
 
 impl App {
-    /// Creates our Vulkan app.
-    pub unsafe fn create(window: &Window) -> Result<Self> {
+    /// Creates our Vulkan app for `window`, made with `config`.
+    pub unsafe fn create(window: &Window, config: &Config) -> Result<Self> {
         let loader = LibloadingLoader::new(LIBRARY)?;
         let entry = Entry::new(loader).map_err(|b| anyhow!("{}", b))?;
-        let mut data = data::AppData::default();
+        let mut data = data::AppData {
+            validation: config.validation,
+            messages: Arc::new(MessageSink::new(
+                config.validation_ignore.clone(),
+                config.validation_panic,
+                config.validation_collect,
+            )),
+            device_selection: config.device.clone(),
+            msaa_samples: config.msaa_samples,
+            surface_format_preferences: config.surface_formats.clone(),
+            present_policy: config.present_policy,
+            image_count_strategy: config.image_count_strategy,
+            ..data::AppData::default()
+        };
         let instance = instance::create_instance(window, &entry, &mut data)?;
 
         data.surface = vulkanalia::window::create_surface(&instance, &window, &window)?;
@@ -46,38 +131,688 @@ impl App {
         image_views::create_swapchain_image_views(&device, &mut data)?;
 
         pipeline::render_pass::create_render_pass(&instance, &device, &mut data)?;
-        pipeline::graphics::create_pipeline(&device, &mut data)?;
-        frame::create_framebuffers(&device, &mut data)?;
+        pipeline::graphics::create_pipeline(&instance, &device, &mut data)?;
+        frame::color::create_color_objects(&instance, &device, &mut data)?;
+        frame::depth::create_depth_objects(&instance, &device, &mut data)?;
+        frame::create_framebuffer(&device, &mut data)?;
         command::pool::create_command_pool(&instance, &device, &mut data)?;
+        command::buffers::create_command_buffers(&device, &mut data)?;
+        frame_sync::create_sync_objects(&device, &mut data)?;
+        uniforms::create_frame_uniforms(&instance, &device, &mut data, DEFAULT_MAX_LIGHTS)?;
+        shadow::maps::create_shadow_maps(&instance, &device, &mut data, ShadowSettings::default())?;
+        skybox::create_skybox(&instance, &device, &mut data, &gradient_sky())?;
+        post::processor::create_post_processor(&instance, &device, &mut data, PostChain::standard())?;
+        debug_draw::renderer::create_debug_draw_renderer(&instance, &device, &mut data)?;
+        let (font, atlas) = Font::default_font()?;
+        gui::renderer::create_gui_renderer(&instance, &device, &mut data)?;
+        let limits = instance.get_physical_device_properties(data.physical_device).limits;
+        let gui = Gui::new(window, limits.max_image_dimension_2d as usize);
+        text::renderer::create_text_renderer(&instance, &device, &mut data, font, &atlas)?;
+        profiler::gpu::create_gpu_profiler(&instance, &device, &mut data)?;
+        profiler::statistics::create_statistics_queries(&device, &mut data)?;
+        scene::occlusion::create_occlusion_queries(&device, &mut data)?;
+
+        particles::create_particle_system(&instance, &device, &mut data)?;
+        instancing::create_instancing_demo(&instance, &device, &mut data)?;
+
+        scene::draw::create_scene_renderer(&instance, &device, &mut data)?;
+        let cube = scene::draw::add_mesh(&instance, &device, &mut data, &MeshData::cube())?;
+        let quad = scene::draw::add_mesh(&instance, &device, &mut data, &MeshData::quad(vec3(1.0, 1.0, 1.0)))?;
+        let materials = solar_system_materials(&instance, &device, &mut data)?;
+        let mut scene = solar_system(cube, quad, materials);
+
+        // A glTF model from the config is placed at the origin.
+        if let Some(path) = &config.model {
+            info!("Loading model `{}`.", path);
+            let model = load_gltf(path)?;
+            scene::draw::add_model(&instance, &device, &mut data, &mut scene, &model, None)?;
+        }
+
+        let mut camera = Camera::default();
+        camera.set_viewport(data.swapchain_extent);
+        let mut orbit = OrbitController::new(point3(0.0, 0.0, 0.0), 120.0);
+        orbit.pitch = Rad(-0.6);
+        let controller = CameraController::Orbit(orbit);
+        let input = Input::new(Bindings::load_or_default(DEFAULT_BINDINGS_PATH)?);
+        let frame_stats_csv = config.frame_stats_csv.clone();
 
         println!("App created");
-        Ok(Self { entry, instance, data, device })
+        Ok(Self {
+            entry,
+            instance,
+            data,
+            device,
+            frame: 0,
+            resized: false,
+            swapchain_outdated: false,
+            start: Instant::now(),
+            last_frame: Instant::now(),
+            camera,
+            controller,
+            input,
+            wireframe: false,
+            shading_model: ShadingModel::default(),
+            screenshot: None,
+            scene,
+            debug_draw: DebugDraw::default(),
+            gizmos: false,
+            text: TextBatch::default(),
+            stats_overlay: false,
+            gui,
+            gui_visible: false,
+            gui_actions: Vec::new(),
+            frame_stats: FrameStats::new(frame_stats_csv.is_some()),
+            frame_stats_csv,
+            title: config.title.clone(),
+            last_title_update: Instant::now(),
+        })
     }
 
     /// Renders a frame for our Vulkan app.
     pub unsafe fn render(&mut self, window: &Window) -> Result<()> {
+        let render_start = Instant::now();
+        let in_flight_fence = self.data.in_flight_fences[self.frame];
+        self.device.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+        profiler::gpu::read_frame(&self.device, &mut self.data.profiler, self.frame)?;
+        profiler::statistics::read_frame(&self.device, &mut self.data.pipeline_statistics, self.frame)?;
+        scene::occlusion::read_frame(&self.device, &mut self.data.occlusion, self.frame)?;
+
+        let result = self.device.acquire_next_image_khr(
+            self.data.swapchain,
+            u64::MAX,
+            self.data.image_available_semaphores[self.frame],
+            vk::Fence::null(),
+        );
+
+        let image_index = match result {
+            Ok((image_index, _)) => image_index as usize,
+            Err(vk::ErrorCode::OUT_OF_DATE_KHR) => return self.recreate_swapchain(window),
+            Err(e) => return Err(anyhow!(e)),
+        };
+
+        let image_in_flight = self.data.images_in_flight[image_index];
+        if !image_in_flight.is_null() {
+            self.device.wait_for_fences(&[image_in_flight], true, u64::MAX)?;
+        }
+        self.data.images_in_flight[image_index] = in_flight_fence;
+        let acquire_ms = render_start.elapsed().as_secs_f64() * 1000.0;
+
+        let now = Instant::now();
+        let delta_time = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        self.update(delta_time);
+        self.scene.update_world_transforms();
+        let frame_uniforms = FrameUniforms::new(&self.camera);
+        uniforms::update_frame_uniforms(&self.device, &self.data, self.frame, &frame_uniforms)?;
+
+        let light_list = self.scene.light_list();
+        let shadow_plan = ShadowPlan::new(&light_list, &self.camera, &self.data.shadows.settings);
+        shadow::maps::update_shadow_uniforms(&self.device, &self.data.shadows, self.frame, &shadow_plan.uniforms)?;
+        let lights = light_list
+            .iter()
+            .zip(&shadow_plan.light_layers)
+            .map(|(l, layer)| GpuLight { shadow_layer: *layer, ..GpuLight::new(&l.light, l.world) })
+            .collect::<Vec<_>>();
+        uniforms::update_frame_lights(&self.device, &self.data, self.frame, self.shading_model, &lights)?;
+
+        if self.gizmos {
+            draw_gizmos(&mut self.debug_draw, &light_list, &shadow_plan.views);
+            label_lights(&mut self.text, &self.data.text.font, &self.scene, &light_list, self.camera.view());
+        }
+        debug_draw::renderer::update_debug_draw(&self.device, &mut self.data.debug_draw, self.frame, &self.debug_draw)?;
+        self.debug_draw.end_frame();
+
+        if self.stats_overlay {
+            let stats = stats_text(&self.frame_stats, &self.data.profiler, &self.data.occlusion);
+            draw_shadowed(&mut self.text, &self.data.text.font, &stats, vec2(10.0, 10.0));
+        }
+        text::renderer::update_text(&self.device, &mut self.data.text, self.frame, &self.text)?;
+        self.text.clear();
+
+        self.gui.begin_frame(window);
+        if self.gui_visible {
+            self.settings_panel();
+        }
+        let gui_output = self.gui.end_frame(window);
+        gui::renderer::update_gui(&self.instance, &self.device, &mut self.data, self.frame, &gui_output)?;
+
+        self.update_command_buffer(image_index, delta_time, &shadow_plan.views)?;
+
+        let wait_semaphores = &[self.data.image_available_semaphores[self.frame]];
+        let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let command_buffers = &[self.data.command_buffers[self.frame]];
+        let signal_semaphores = &[self.data.render_finished_semaphores[image_index]];
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(wait_semaphores)
+            .wait_dst_stage_mask(wait_stages)
+            .command_buffers(command_buffers)
+            .signal_semaphores(signal_semaphores);
+
+        self.device.reset_fences(&[in_flight_fence])?;
+        queue_begin_label(&self.data, self.data.graphics_queue, &format!("frame {}", self.frame));
+        let submit_start = Instant::now();
+        let submitted = self.device.queue_submit(self.data.graphics_queue, &[submit_info], in_flight_fence);
+        let submit_ms = submit_start.elapsed().as_secs_f64() * 1000.0;
+        queue_end_label(&self.data, self.data.graphics_queue);
+        submitted?;
+
+        if let Some(mut screenshot) = self.screenshot.take() {
+            self.device.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+            let path = format!(
+                "screenshot-{}.png",
+                std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_millis(),
+            );
+            if let Err(e) = screenshot::save_screenshot(&self.device, &screenshot, path) {
+                error!("Failed to save screenshot: {}", e);
+            }
+            screenshot::destroy_screenshot(&self.device, &mut screenshot);
+        }
+
+        let swapchains = &[self.data.swapchain];
+        let image_indices = &[image_index as u32];
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(signal_semaphores)
+            .swapchains(swapchains)
+            .image_indices(image_indices);
+
+        let present_start = Instant::now();
+        let result = self.device.queue_present_khr(self.data.present_queue, &present_info);
+        let present_ms = present_start.elapsed().as_secs_f64() * 1000.0;
+        let changed = result == Ok(vk::SuccessCode::SUBOPTIMAL_KHR)
+            || result == Err(vk::ErrorCode::OUT_OF_DATE_KHR);
+
+        if self.resized || self.swapchain_outdated || changed {
+            self.resized = false;
+            self.swapchain_outdated = false;
+            self.recreate_swapchain(window)?;
+        } else if let Err(e) = result {
+            return Err(anyhow!(e));
+        }
+
+        self.frame_stats.push(FrameTiming {
+            frame_ms: delta_time as f64 * 1000.0,
+            cpu_ms: render_start.elapsed().as_secs_f64() * 1000.0,
+            acquire_ms,
+            submit_ms,
+            present_ms,
+            ..FrameTiming::default()
+        });
+        if self.last_title_update.elapsed() >= TITLE_INTERVAL {
+            self.last_title_update = Instant::now();
+            window.set_title(&format!("{} | {}", self.title, self.frame_stats.display()));
+        }
+
+        self.frame = (self.frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.input.state.end_frame();
+
         Ok(())
     }
 
+    /// Applies this frame's input.
+    unsafe fn update(&mut self, delta_time: f32) {
+        self.controller.update(&mut self.camera, &self.input, delta_time);
+        animate_solar_system(&mut self.scene, self.start.elapsed().as_secs_f32());
+
+        if self.triggered(Action::ToggleWireframe) {
+            if self.data.instancing.wireframe_pipeline.is_null() {
+                warn!("Wireframe rendering needs the `fillModeNonSolid` feature.");
+            } else {
+                self.wireframe = !self.wireframe;
+            }
+        }
+
+        if self.triggered(Action::ToggleShadingModel) {
+            self.shading_model = self.shading_model.toggled();
+            info!("Shading with {:?}.", self.shading_model);
+        }
+
+        if self.triggered(Action::CycleTonemapper)
+            && let Some(pass) = self.data.post.chain.pass_mut("tonemap")
+        {
+            let operator = match (pass.enabled, pass.effect) {
+                (true, PostEffect::Tonemap { operator: Tonemapper::Aces }) => Some(Tonemapper::Reinhard),
+                (true, _) => None,
+                (false, _) => Some(Tonemapper::Aces),
+            };
+            pass.enabled = operator.is_some();
+            if let Some(operator) = operator {
+                pass.effect = PostEffect::Tonemap { operator };
+            }
+            info!("Tonemapping with {:?}.", operator);
+        }
+
+        if self.triggered(Action::ToggleFxaa)
+            && let Some(pass) = self.data.post.chain.pass_mut("fxaa")
+        {
+            pass.enabled = !pass.enabled;
+            info!("FXAA {}.", if pass.enabled { "enabled" } else { "disabled" });
+        }
+
+        let exposure_step = match (self.triggered(Action::ExposureUp), self.triggered(Action::ExposureDown)) {
+            (true, false) => 0.5,
+            (false, true) => -0.5,
+            _ => 0.0,
+        };
+        if exposure_step != 0.0
+            && let Some(PostPass { effect: PostEffect::Exposure { stops }, .. }) = self.data.post.chain.pass_mut("exposure")
+        {
+            *stops += exposure_step;
+            info!("Exposure {:+} stops.", stops);
+        }
+
+        if self.triggered(Action::CyclePresentMode) {
+            self.data.present_policy = self.data.present_policy.next();
+            self.swapchain_outdated = true;
+        }
+
+        if self.triggered(Action::SaveGpuTrace) {
+            let profiler = &self.data.profiler;
+            if !profiler.supported {
+                warn!("GPU profiling is unavailable on this device.");
+            } else {
+                info!("GPU timings over the last {} frames: {}", AVERAGE_FRAMES, profiler.averages.summary());
+                let path = format!(
+                    "gpu-trace-{}.json",
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_millis())
+                        .unwrap_or_default(),
+                );
+                match profiler::gpu::save_trace(profiler, &path) {
+                    Ok(()) => info!("Saved a trace of {} frames to `{}`.", profiler.history.len(), path),
+                    Err(e) => error!("Failed to save GPU trace: {}", e),
+                }
+            }
+            if let Some(statistics) = &self.data.pipeline_statistics.latest {
+                info!("Pipeline statistics: {}.", statistics);
+            }
+            let (occluded, objects) = self.data.occlusion.occluded_count();
+            info!("{} of {} objects were occluded.", occluded, objects);
+        }
+
+        if self.triggered(Action::ToggleGizmos) {
+            self.gizmos = !self.gizmos;
+        }
+
+        if self.triggered(Action::ToggleStats) {
+            self.stats_overlay = !self.stats_overlay;
+        }
+
+        if self.triggered(Action::ToggleGui) {
+            self.gui_visible = !self.gui_visible;
+        }
+
+        if self.triggered(Action::ToggleOcclusionCulling) {
+            let occlusion = &mut self.data.occlusion;
+            occlusion.culling = !occlusion.culling;
+            info!("Occlusion culling {}.", if occlusion.culling { "enabled" } else { "disabled" });
+        }
+
+        if self.triggered(Action::Screenshot) {
+            if !self.data.swapchain_usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+                warn!("The swapchain images can't be copied from, so screenshots are unavailable.");
+            } else {
+                match screenshot::create_screenshot(&self.instance, &self.device, &self.data) {
+                    Ok(screenshot) => self.screenshot = Some(screenshot),
+                    Err(e) => warn!("{}", e),
+                }
+            }
+        }
+
+        self.gui_actions.clear();
+    }
+
+    /// Whether `action` was pressed this frame or triggered from the GUI.
+    fn triggered(&self, action: Action) -> bool {
+        self.input.pressed(action) || self.gui_actions.contains(&action)
+    }
+
+    /// Declares the settings window in the top right, for `gui_visible`.
+    fn settings_panel(&mut self) {
+        let context = self.gui.context.clone();
+        let wireframe_supported = !self.data.instancing.wireframe_pipeline.is_null();
+        let (shading_model, present_policy) = (self.shading_model, self.data.present_policy);
+        let mut actions = Vec::new();
+        egui::Window::new("Renderer")
+            .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+            .resizable(false)
+            .show(&context, |ui| {
+                if wireframe_supported {
+                    ui.checkbox(&mut self.wireframe, "Wireframe");
+                }
+                ui.checkbox(&mut self.gizmos, "Lights and frustums");
+                ui.checkbox(&mut self.stats_overlay, "Frame timings");
+                ui.checkbox(&mut self.data.occlusion.culling, "Occlusion culling");
+
+                ui.separator();
+                let chain = &mut self.data.post.chain;
+                for (name, label) in [("tonemap", "Tonemapping"), ("fxaa", "FXAA")] {
+                    if let Some(pass) = chain.pass_mut(name) {
+                        ui.checkbox(&mut pass.enabled, label);
+                    }
+                }
+                if let Some(PostPass { effect: PostEffect::Exposure { stops }, .. }) = chain.pass_mut("exposure") {
+                    ui.add(egui::Slider::new(stops, -4.0..=4.0).text("Exposure"));
+                }
+
+                ui.separator();
+                if ui.button(format!("Shading: {:?}", shading_model)).clicked() {
+                    actions.push(Action::ToggleShadingModel);
+                }
+                if ui.button(format!("Presenting: {:?}", present_policy)).clicked() {
+                    actions.push(Action::CyclePresentMode);
+                }
+                if ui.button("Save GPU trace").clicked() {
+                    actions.push(Action::SaveGpuTrace);
+                }
+                if ui.button("Screenshot").clicked() {
+                    actions.push(Action::Screenshot);
+                }
+            });
+        self.gui_actions = actions;
+    }
+
+    /// Records this frame's command buffer to render `shadow_views`, draw the
+    /// scene and post-process it into swapchain image `image_index`.
+    unsafe fn update_command_buffer(
+        &mut self,
+        image_index: usize,
+        delta_time: f32,
+        shadow_views: &[ShadowView],
+    ) -> Result<()> {
+        let command_buffer = self.data.command_buffers[self.frame];
+        self.device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+
+        let info = vk::CommandBufferBeginInfo::builder();
+        self.device.begin_command_buffer(command_buffer, &info)?;
+
+        profiler::gpu::cmd_begin_frame(&self.device, command_buffer, &mut self.data.profiler, self.frame);
+        scene::occlusion::cmd_begin_frame(&self.device, command_buffer, &mut self.data.occlusion, self.frame);
+        profiler::statistics::cmd_begin_frame(&self.device, command_buffer, &mut self.data.pipeline_statistics, self.frame);
+        gui::renderer::cmd_upload_gui_textures(&self.device, command_buffer, &self.data.gui, self.frame);
+
+        self.cmd_begin_pass(command_buffer, "particles", COMPUTE_COLOR);
+        particles::cmd_simulate(&self.device, command_buffer, &self.data.particles, delta_time);
+        self.cmd_end_pass(command_buffer);
+
+        let draw_list = self.scene.draw_list();
+        self.cmd_begin_pass(command_buffer, "shadows", SHADOW_COLOR);
+        shadow::maps::cmd_render_shadows(
+            &self.device,
+            command_buffer,
+            &self.data.shadows,
+            &self.data.scene_renderer,
+            shadow_views,
+            &draw_list,
+        );
+        self.cmd_end_pass(command_buffer);
+
+        let render_area = vk::Rect2D::builder()
+            .offset(vk::Offset2D::default())
+            .extent(self.data.swapchain_extent);
+        let color_clear_value = vk::ClearValue {
+            color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] },
+        };
+        let depth_clear_value = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+        };
+        let clear_values = &[color_clear_value, depth_clear_value];
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.data.render_pass)
+            .framebuffer(self.data.framebuffer)
+            .render_area(render_area)
+            .clear_values(clear_values);
+
+        self.cmd_begin_pass(command_buffer, "scene", SCENE_COLOR);
+        self.device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+        pipeline::graphics::cmd_set_viewport_and_scissor(
+            &self.device,
+            command_buffer,
+            self.data.swapchain_extent,
+        );
+
+        instancing::cmd_draw_demo(
+            &self.device,
+            command_buffer,
+            &self.data.instancing,
+            self.camera.view_projection(),
+            self.wireframe,
+        );
+
+        scene::draw::cmd_draw_scene(
+            &self.device,
+            command_buffer,
+            &self.data.scene_renderer,
+            self.data.frame_descriptor_sets[self.frame],
+            self.data.shadows.sets[self.frame],
+            self.data.skybox.set,
+            &draw_list,
+            self.wireframe,
+            &mut self.data.occlusion,
+        );
+
+        skybox::cmd_draw_skybox(
+            &self.device,
+            command_buffer,
+            &self.data.skybox,
+            self.data.frame_descriptor_sets[self.frame],
+        );
+
+        self.device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.data.pipeline);
+        let time = self.start.elapsed().as_secs_f32();
+        let push_constants = ObjectPushConstants {
+            model: Matrix4::from_angle_z(Deg(90.0) * time),
+            material_index: (time as u32) % 4,
+        };
+        cmd_push_constants(&self.device, command_buffer, self.data.pipeline_layout, &push_constants);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        particles::cmd_draw(&self.device, command_buffer, &self.data.particles);
+
+        debug_draw::renderer::cmd_draw_debug(
+            &self.device,
+            command_buffer,
+            &self.data.debug_draw,
+            self.frame,
+            self.camera.view_projection(),
+        );
+
+        text::renderer::cmd_draw_world_text(
+            &self.device,
+            command_buffer,
+            &self.data.text,
+            self.frame,
+            self.camera.view_projection(),
+        );
+
+        self.device.cmd_end_render_pass(command_buffer);
+        self.cmd_end_pass(command_buffer);
+
+        self.cmd_begin_pass(command_buffer, "post-processing", POST_COLOR);
+        post::processor::cmd_post_process(
+            &self.device,
+            command_buffer,
+            &self.data.post,
+            image_index,
+            self.data.swapchain_extent,
+        );
+        self.cmd_end_pass(command_buffer);
+
+        self.cmd_begin_pass(command_buffer, "overlay", POST_COLOR);
+        post::processor::cmd_begin_overlay(
+            &self.device,
+            command_buffer,
+            &self.data.post,
+            image_index,
+            self.data.swapchain_extent,
+        );
+        text::renderer::cmd_draw_screen_text(
+            &self.device,
+            command_buffer,
+            &self.data.text,
+            self.frame,
+            &self.data.post,
+            self.data.swapchain_extent,
+        );
+        gui::renderer::cmd_draw_gui(
+            &self.device,
+            command_buffer,
+            &self.data.gui,
+            self.frame,
+            &self.data.post,
+            self.data.swapchain_extent,
+        );
+        self.device.cmd_end_render_pass(command_buffer);
+        self.cmd_end_pass(command_buffer);
+
+        if let Some(screenshot) = &self.screenshot {
+            cmd_insert_label(&self.data, command_buffer, "screenshot", POST_COLOR);
+            screenshot::cmd_copy_swapchain_image(
+                &self.device,
+                command_buffer,
+                self.data.swapchain_images[image_index],
+                screenshot,
+            );
+        }
+
+        profiler::statistics::cmd_end_frame(&self.device, command_buffer, &mut self.data.pipeline_statistics);
+        profiler::gpu::cmd_end_frame(&self.device, command_buffer, &mut self.data.profiler);
+        self.device.end_command_buffer(command_buffer)?;
+
+        Ok(())
+    }
+
+    /// Labels and times the commands until `cmd_end_pass`.
+    unsafe fn cmd_begin_pass(&mut self, command_buffer: vk::CommandBuffer, name: &'static str, color: [f32; 4]) {
+        cmd_begin_label(&self.data, command_buffer, name, color);
+        profiler::gpu::cmd_begin_scope(&self.device, command_buffer, &mut self.data.profiler, name);
+    }
+
+    unsafe fn cmd_end_pass(&mut self, command_buffer: vk::CommandBuffer) {
+        profiler::gpu::cmd_end_scope(&self.device, command_buffer, &mut self.data.profiler);
+        cmd_end_label(&self.data, command_buffer);
+    }
+
+    /// Lines to draw over the next frame rendered, e.g. to show bounds or
+    /// directions while debugging.
+    pub fn debug_draw_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    /// Text to draw over the next frame rendered, laid out with `font`.
+    pub fn text_mut(&mut self) -> (&mut TextBatch, &Font) {
+        (&mut self.text, &self.data.text.font)
+    }
+
+    /// CPU timings of recent frames.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// GPU timings of recent frames, see `GpuProfiler`.
+    pub fn gpu_profiler(&self) -> &GpuProfiler {
+        &self.data.profiler
+    }
+
+    /// Where validation messages go, e.g. to collect and check them after a frame.
+    pub fn messages(&self) -> &MessageSink {
+        &self.data.messages
+    }
+
+    /// Records window input for the next frame. Input the GUI uses, e.g.
+    /// clicks on its window, only reaches the GUI. Releases always reach the
+    /// app, so nothing stays held.
+    pub fn handle_window_event(&mut self, window: &Window, event: &WindowEvent) {
+        let released = matches!(
+            event,
+            WindowEvent::MouseInput { state: ElementState::Released, .. }
+                | WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Released, .. }, .. }
+        );
+        if !self.gui.handle_event(window, event) || released {
+            self.input.state.handle_window_event(event);
+        }
+    }
+
+    /// Records raw mouse motion for the next frame.
+    pub fn handle_mouse_motion(&mut self, delta: (f64, f64)) {
+        self.input.state.handle_mouse_motion(delta);
+    }
+
+    /// Recreates the swapchain and everything that depends on its images.
+    unsafe fn recreate_swapchain(&mut self, window: &Window) -> Result<()> {
+        self.device.device_wait_idle()?;
+        self.destroy_swapchain();
+
+        swapchain::create_swapchain(window, &self.instance, &self.device, &mut self.data)?;
+        image_views::create_swapchain_image_views(&self.device, &mut self.data)?;
+        frame::color::create_color_objects(&self.instance, &self.device, &mut self.data)?;
+        frame::depth::create_depth_objects(&self.instance, &self.device, &mut self.data)?;
+        frame::create_framebuffer(&self.device, &mut self.data)?;
+        post::processor::create_post_targets(&self.instance, &self.device, &mut self.data)?;
+        text::renderer::create_text_screen_pipeline(&self.device, &mut self.data)?;
+        gui::renderer::create_gui_pipeline(&self.device, &mut self.data)?;
+        frame_sync::create_swapchain_sync_objects(&self.device, &mut self.data)?;
+
+        self.camera.set_viewport(self.data.swapchain_extent);
+
+        Ok(())
+    }
+
+    /// Destroys the swapchain and everything that depends on its images.
+    unsafe fn destroy_swapchain(&mut self) {
+        frame_sync::destroy_swapchain_sync_objects(&self.device, &mut self.data);
+
+        post::processor::destroy_post_targets(&self.device, &mut self.data.post);
+        self.device.destroy_framebuffer(self.data.framebuffer, None);
+        frame::depth::destroy_depth_objects(&self.device, &mut self.data);
+        frame::color::destroy_color_objects(&self.device, &mut self.data);
+
+        self.data.swapchain_image_views.iter()
+            .for_each(|v| self.device.destroy_image_view(*v, None));
+        self.device.destroy_swapchain_khr(self.data.swapchain, None);
+    }
+
     /// Destroys our Vulkan app.
     pub unsafe fn destroy(&mut self) {
-        self.data.framebuffers
-            .iter()
-            .for_each(|i| self.device.destroy_framebuffer(*i, None));
+        self.device.device_wait_idle().unwrap();
+
+        if let Some(path) = &self.frame_stats_csv {
+            match self.frame_stats.save_csv(path) {
+                Ok(()) => info!("Saved frame timings to `{}`.", path),
+                Err(e) => error!("Failed to save frame timings: {}", e),
+            }
+        }
+
+        self.destroy_swapchain();
+
+        scene::occlusion::destroy_occlusion_queries(&self.device, &mut self.data.occlusion);
+        debug_draw::renderer::destroy_debug_draw_renderer(&self.device, &mut self.data.debug_draw);
+        text::renderer::destroy_text_renderer(&self.device, &mut self.data.text);
+        gui::renderer::destroy_gui_renderer(&self.device, &mut self.data.gui);
+        profiler::statistics::destroy_statistics_queries(&self.device, &mut self.data.pipeline_statistics);
+        profiler::gpu::destroy_gpu_profiler(&self.device, &mut self.data.profiler);
+        post::processor::destroy_post_processor(&self.device, &mut self.data.post);
+        scene::draw::destroy_scene_renderer(&self.device, &mut self.data.scene_renderer);
+        shadow::maps::destroy_shadow_maps(&self.device, &mut self.data.shadows);
+        skybox::destroy_skybox(&self.device, &mut self.data.skybox);
+        instancing::destroy_instancing_demo(&self.device, &mut self.data.instancing);
+        particles::destroy_particle_system(&self.device, &mut self.data.particles);
+
+        uniforms::destroy_frame_uniforms(&self.device, &mut self.data);
+        frame_sync::destroy_sync_objects(&self.device, &mut self.data);
+        self.device.destroy_command_pool(self.data.transfer_command_pool, None);
+        self.device.destroy_command_pool(self.data.command_pool, None);
 
         self.device.destroy_pipeline(self.data.pipeline, None);
         self.device.destroy_pipeline_layout(self.data.pipeline_layout, None);
         self.device.destroy_render_pass(self.data.render_pass, None);
 
-        self.data.swapchain_image_views.iter()
-            .for_each(|v| self.device.destroy_image_view(*v, None));
-        self.device.destroy_swapchain_khr(self.data.swapchain, None);
-
         self.instance.destroy_surface_khr(self.data.surface, None);
 
         self.device.destroy_device(None);
 
-        if VALIDATION_ENABLED {
+        if self.data.validation.enabled {
             self.instance.destroy_debug_utils_messenger_ext(self.data.messenger, None);
         }
 
@@ -85,3 +820,184 @@ impl App {
 
     }
 }
+
+/// World axes over everything, each light as a sphere in its color with its
+/// axes, and the volume each shadow map covers.
+fn draw_gizmos(debug_draw: &mut DebugDraw, lights: &[LightItem], shadow_views: &[ShadowView]) {
+    debug_draw.with(ShapeOptions { depth_test: false, ..ShapeOptions::default() }, |d| {
+        d.axes(Matrix4::identity(), 10.0);
+    });
+    for item in lights {
+        let position = Point3::from_vec(item.world.w.truncate());
+        let color = item.light.color.extend(1.0);
+        debug_draw.sphere(position, 1.0, color);
+        debug_draw.axes(item.world, 3.0);
+    }
+    for view in shadow_views {
+        debug_draw.frustum(view.view_projection, vec4(1.0, 0.8, 0.2, 1.0));
+    }
+}
+
+/// Each light's name above it, facing the camera.
+fn label_lights(text: &mut TextBatch, font: &Font, scene: &Scene, lights: &[LightItem], view: Matrix4<f32>) {
+    // World units per font pixel.
+    let pixel_size = 0.05;
+    let style = TextStyle { align: Align::Center, ..TextStyle::default() };
+    for item in lights {
+        // Text hangs below its anchor, so this puts it just above the sphere.
+        let above = 1.2 + font.line_height * pixel_size;
+        let position = Point3::from_vec(item.world.w.truncate()) + vec3(0.0, above, 0.0);
+        let name = &scene.node(item.node).name;
+        text.world(font, name, text::billboard(position, view, pixel_size), style);
+    }
+}
+
+/// `stats` in white with a dark shadow, readable over anything.
+fn draw_shadowed(text: &mut TextBatch, font: &Font, stats: &str, position: Vector2<f32>) {
+    let shadow = TextStyle { color: vec4(0.0, 0.0, 0.0, 0.8), ..TextStyle::default() };
+    text.screen(font, stats, position + vec2(1.0, 1.0), shadow);
+    text.screen(font, stats, position, TextStyle::default());
+}
+
+/// Recent frame rate and CPU and GPU timings, one per line.
+fn stats_text(frame_stats: &FrameStats, profiler: &GpuProfiler, occlusion: &OcclusionQueries) -> String {
+    let mut lines = Vec::new();
+    if let Some(frame) = frame_stats.summary(|t| t.frame_ms) {
+        let fps = if frame.avg > 0.0 { 1000.0 / frame.avg } else { 0.0 };
+        lines.push(format!("{:.1} fps, {:.2} ms (p99 {:.2} ms)", fps, frame.avg, frame.p99));
+    }
+    if let Some(cpu) = frame_stats.summary(|t| t.cpu_ms) {
+        lines.push(format!("cpu {:.2} ms", cpu.avg));
+    }
+    for (name, ms) in profiler.averages.iter() {
+        lines.push(format!("gpu {} {:.2} ms", name, ms));
+    }
+    if occlusion.culling {
+        let (occluded, objects) = occlusion.occluded_count();
+        lines.push(format!("{} of {} objects occluded", occluded, objects));
+    }
+    lines.join("\n")
+}
+
+/// A sky fading from blue overhead to pale at the horizon, over a dark ground.
+fn gradient_sky() -> CubemapData {
+    let mix = |a: [f32; 3], b: [f32; 3], t: f32| -> [u8; 4] {
+        let channel = |i: usize| ((a[i] + (b[i] - a[i]) * t) * 255.0).round() as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let zenith = [0.2, 0.4, 0.8];
+    let horizon = [0.75, 0.85, 0.95];
+    let ground = [0.2, 0.18, 0.16];
+    CubemapData::from_fn(64, |direction| {
+        if direction.y >= 0.0 {
+            mix(horizon, zenith, direction.y.sqrt())
+        } else {
+            mix(horizon, ground, (-direction.y * 8.0).min(1.0))
+        }
+    })
+}
+
+/// Materials for the sun, planet and moon.
+unsafe fn solar_system_materials(
+    instance: &Instance,
+    device: &Device,
+    data: &mut data::AppData,
+) -> Result<[MaterialId; 3]> {
+    let sun = MaterialData {
+        name: "sun".into(),
+        base_color_factor: vec4(1.0, 0.8, 0.3, 1.0),
+        metallic_factor: 0.0,
+        emissive_factor: vec3(1.0, 0.6, 0.1),
+        ..MaterialData::default()
+    };
+    let planet = MaterialData {
+        name: "planet".into(),
+        base_color_factor: vec4(0.3, 0.6, 1.0, 1.0),
+        metallic_factor: 0.0,
+        roughness_factor: 0.4,
+        ..MaterialData::default()
+    };
+    let moon = MaterialData {
+        name: "moon".into(),
+        base_color_factor: vec4(0.7, 0.7, 0.7, 1.0),
+        metallic_factor: 0.8,
+        roughness_factor: 0.3,
+        ..MaterialData::default()
+    };
+
+    Ok([
+        scene::draw::add_material(instance, device, data, &sun)?,
+        scene::draw::add_material(instance, device, data, &planet)?,
+        scene::draw::add_material(instance, device, data, &moon)?,
+    ])
+}
+
+/// A sun with an orbiting planet that has an orbiting moon, hovering over the
+/// instancing demo above a platform that catches their shadows.
+fn solar_system(
+    cube: MeshId,
+    quad: MeshId,
+    [sun_material, planet_material, moon_material]: [MaterialId; 3],
+) -> Scene {
+    let mut scene = Scene::new();
+
+    let system = scene.add_node(None, "system", Transform::from_translation(vec3(0.0, 25.0, 0.0)));
+    let sun = scene.add_node(Some(system), "sun", Transform {
+        scale: vec3(6.0, 6.0, 6.0),
+        ..Transform::default()
+    });
+    scene.set_mesh(sun, cube, Some(sun_material));
+    scene.set_light(sun, Some(Light::point(vec3(1.0, 0.8, 0.5), 600.0, Some(200.0))));
+
+    // Daylight from above and a spotlight shining down on the sun.
+    let daylight = scene.add_node(None, "daylight", Transform {
+        rotation: Quaternion::from_angle_x(Deg(-60.0)) * Quaternion::from_angle_y(Deg(30.0)),
+        ..Transform::default()
+    });
+    scene.set_light(daylight, Some(Light::directional(vec3(1.0, 1.0, 1.0), 1.5).with_shadows()));
+    let spotlight = scene.add_node(Some(system), "spotlight", Transform {
+        translation: vec3(0.0, 20.0, 0.0),
+        rotation: Quaternion::from_angle_x(Deg(-90.0)),
+        ..Transform::default()
+    });
+    scene.set_light(spotlight, Some(Light::spot(vec3(0.4, 0.6, 1.0), 1000.0, Some(60.0), Deg(10.0).into(), Deg(20.0).into()).with_shadows()));
+
+    let planet_orbit = scene.add_node(Some(system), "planet orbit", Transform::default());
+    let planet = scene.add_node(Some(planet_orbit), "planet", Transform {
+        translation: vec3(15.0, 0.0, 0.0),
+        scale: vec3(2.0, 2.0, 2.0),
+        ..Transform::default()
+    });
+    scene.set_mesh(planet, cube, Some(planet_material));
+
+    // The moon orbits the planet, so it hangs off the planet's orbit rather
+    // than the scaled planet itself.
+    let moon_orbit = scene.add_node(Some(planet_orbit), "moon orbit", Transform::from_translation(vec3(15.0, 0.0, 0.0)));
+    let moon = scene.add_node(Some(moon_orbit), "moon", Transform {
+        translation: vec3(4.0, 0.0, 0.0),
+        scale: vec3(0.7, 0.7, 0.7),
+        ..Transform::default()
+    });
+    scene.set_mesh(moon, cube, Some(moon_material));
+
+    let platform = scene.add_node(Some(system), "platform", Transform {
+        translation: vec3(0.0, -12.0, 0.0),
+        rotation: Quaternion::from_angle_x(Deg(-90.0)),
+        scale: vec3(50.0, 50.0, 1.0),
+    });
+    scene.set_mesh(platform, quad, None);
+
+    scene
+}
+
+fn animate_solar_system(scene: &mut Scene, time: f32) {
+    let mut spin = |name: &str, degrees_per_second: f32| {
+        if let Some(id) = scene.find(name) {
+            scene.update_local(id, |t| t.rotation = Quaternion::from_angle_y(Deg(degrees_per_second * time)));
+        }
+    };
+    spin("sun", 10.0);
+    spin("planet orbit", 20.0);
+    spin("planet", 90.0);
+    spin("moon orbit", 60.0);
+}
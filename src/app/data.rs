@@ -1,25 +1,117 @@
+use std::sync::Arc;
+
 use vulkanalia::vk;
+use vulkanalia::Instance;
+
+use crate::core::messages::MessageSink;
+use crate::debug_draw::renderer::DebugDrawRenderer;
+
+use crate::device::physical::DeviceSelection;
+use crate::device::queues::{QueueFamilyIndices, QueueLayout};
+use crate::gui::renderer::GuiRenderer;
+use crate::instance::ValidationSettings;
+use crate::instancing::InstancingDemo;
+use crate::particles::ParticleSystem;
+use crate::post::processor::PostProcessor;
+use crate::profiler::gpu::GpuProfiler;
+use crate::profiler::statistics::StatisticsQueries;
+use crate::scene::draw::SceneRenderer;
+use crate::scene::occlusion::OcclusionQueries;
+use crate::shadow::maps::ShadowMaps;
+use crate::skybox::Skybox;
+use crate::swapchain::support::{ImageCountStrategy, OutputEncoding, PresentPolicy};
+use crate::text::renderer::TextRenderer;
 
 /// The Vulkan handles and associated properties used by our Vulkan app.
 #[derive(Clone, Debug, Default)]
 pub struct AppData {
+    /// What validation was requested, and then actually enabled.
+    pub validation: ValidationSettings,
+    /// Receives the messenger's messages; must outlive the instance.
+    pub messages: Arc<MessageSink>,
     pub messenger: vk::DebugUtilsMessengerEXT,
+    /// The instance when `VK_EXT_debug_utils` is enabled, for naming objects
+    /// and labeling commands, see `core::debug_utils`.
+    pub debug_utils: Option<Instance>,
+    /// Which physical device to pick.
+    pub device_selection: DeviceSelection,
     pub physical_device: vk::PhysicalDevice,
     pub graphics_queue: vk::Queue,
     pub present_queue: vk::Queue,
+    pub transfer_queue: vk::Queue,
+    pub compute_queue: vk::Queue,
+    pub queue_families: QueueFamilyIndices,
+    pub queue_layout: QueueLayout,
     pub surface: vk::SurfaceKHR,
+    /// Features enabled on the logical device.
+    pub features: vk::PhysicalDeviceFeatures,
 
+    /// Ranked surface formats to pick the swapchain's from, see
+    /// `swapchain::support::DEFAULT_SURFACE_FORMATS` if empty.
+    pub surface_format_preferences: Vec<vk::SurfaceFormatKHR>,
+    pub present_policy: PresentPolicy,
+    pub image_count_strategy: ImageCountStrategy,
     pub swapchain_format: vk::Format,
+    pub swapchain_color_space: vk::ColorSpaceKHR,
+    /// How post-processing encodes colors for the swapchain format.
+    pub output_encoding: OutputEncoding,
+    pub present_mode: vk::PresentModeKHR,
     pub swapchain_extent: vk::Extent2D,
+    pub swapchain_usage: vk::ImageUsageFlags,
     pub swapchain: vk::SwapchainKHR,
     pub swapchain_images: Vec<vk::Image>,
     pub swapchain_image_views: Vec<vk::ImageView>,
 
+    /// Samples per pixel in the scene pass, `_1` without MSAA. Lowered to
+    /// what the device supports when it's picked.
+    pub msaa_samples: vk::SampleCountFlags,
+    pub color_image: vk::Image,
+    pub color_image_memory: vk::DeviceMemory,
+    pub color_image_view: vk::ImageView,
+    /// What the scene is drawn into with MSAA, resolved into `color_image`.
+    /// Null without MSAA.
+    pub msaa_color_image: vk::Image,
+    pub msaa_color_image_memory: vk::DeviceMemory,
+    pub msaa_color_image_view: vk::ImageView,
+
+    pub depth_image: vk::Image,
+    pub depth_image_memory: vk::DeviceMemory,
+    pub depth_image_view: vk::ImageView,
+
     pub render_pass: vk::RenderPass,
     pub pipeline_layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
 
-    pub framebuffers: Vec<vk::Framebuffer>,
+    pub framebuffer: vk::Framebuffer,
     pub command_pool: vk::CommandPool,
+    pub transfer_command_pool: vk::CommandPool,
+    pub command_buffers: Vec<vk::CommandBuffer>,
+
+    pub frame_set_layout: vk::DescriptorSetLayout,
+    pub frame_descriptor_pool: vk::DescriptorPool,
+    pub frame_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub frame_uniform_buffers: Vec<vk::Buffer>,
+    pub frame_uniform_buffers_memory: Vec<vk::DeviceMemory>,
+    pub frame_light_buffers: Vec<vk::Buffer>,
+    pub frame_light_buffers_memory: Vec<vk::DeviceMemory>,
+    /// How many lights fit in each frame light buffer.
+    pub max_lights: u32,
+
+    pub image_available_semaphores: Vec<vk::Semaphore>,
+    pub render_finished_semaphores: Vec<vk::Semaphore>,
+    pub in_flight_fences: Vec<vk::Fence>,
+    pub images_in_flight: Vec<vk::Fence>,
 
+    pub particles: ParticleSystem,
+    pub instancing: InstancingDemo,
+    pub scene_renderer: SceneRenderer,
+    pub shadows: ShadowMaps,
+    pub skybox: Skybox,
+    pub post: PostProcessor,
+    pub debug_draw: DebugDrawRenderer,
+    pub text: TextRenderer,
+    pub gui: GuiRenderer,
+    pub profiler: GpuProfiler,
+    pub pipeline_statistics: StatisticsQueries,
+    pub occlusion: OcclusionQueries,
 }
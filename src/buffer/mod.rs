@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Result};
+
+use std::mem::size_of_val;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::command::single_time::{begin_single_time_commands, end_single_time_commands};
+
+
+/// Views a slice of `#[repr(C)]` values as the bytes to upload.
+///
+/// # Safety
+///
+/// `T` must have no padding bytes, since they may be uninitialized.
+pub unsafe fn slice_as_bytes<T: Copy>(values: &[T]) -> &[u8] {
+    std::slice::from_raw_parts(values.as_ptr().cast::<u8>(), size_of_val(values))
+}
+
+pub unsafe fn get_memory_type_index(
+    instance: &Instance,
+    data: &AppData,
+    properties: vk::MemoryPropertyFlags,
+    requirements: vk::MemoryRequirements,
+) -> Result<u32> {
+    let memory = instance.get_physical_device_memory_properties(data.physical_device);
+    (0..memory.memory_type_count)
+        .find(|i| {
+            let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
+            let memory_type = memory.memory_types[*i as usize];
+            suitable && memory_type.property_flags.contains(properties)
+        })
+        .ok_or_else(|| anyhow!("Failed to find suitable memory type."))
+}
+
+pub unsafe fn create_buffer(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+    let buffer_info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let buffer = device.create_buffer(&buffer_info, None)?;
+
+    let requirements = device.get_buffer_memory_requirements(buffer);
+    let memory_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(get_memory_type_index(instance, data, properties, requirements)?);
+    let buffer_memory = device.allocate_memory(&memory_info, None)?;
+
+    device.bind_buffer_memory(buffer, buffer_memory, 0)?;
+
+    Ok((buffer, buffer_memory))
+}
+
+/// Where an uploaded resource will be consumed, so the upload can hand it
+/// over to the right queue family and make the write visible there.
+#[derive(Copy, Clone, Debug)]
+pub struct UploadTarget {
+    pub queue_family: u32,
+    pub queue: vk::Queue,
+    pub pool: vk::CommandPool,
+    pub stage: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+impl UploadTarget {
+    /// Consumption by the graphics queue, e.g. vertex, index or uniform buffers.
+    pub fn graphics(data: &AppData, stage: vk::PipelineStageFlags, access: vk::AccessFlags) -> Self {
+        Self {
+            queue_family: data.queue_families.graphics,
+            queue: data.graphics_queue,
+            pool: data.command_pool,
+            stage,
+            access,
+        }
+    }
+}
+
+/// Creates a device local buffer filled with `bytes`.
+///
+/// The copy runs on the transfer queue. When the transfer family differs from
+/// `target.queue_family` the buffer is released by the transfer queue and
+/// acquired by the target queue, ordered with a semaphore.
+pub unsafe fn create_device_local_buffer(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    bytes: &[u8],
+    usage: vk::BufferUsageFlags,
+    target: UploadTarget,
+) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+    let size = bytes.len() as vk::DeviceSize;
+
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+
+    let memory = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(bytes.as_ptr(), memory.cast(), bytes.len());
+    device.unmap_memory(staging_buffer_memory);
+
+    let (buffer, buffer_memory) = create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        usage | vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+
+    copy_buffer(device, data, staging_buffer, buffer, size, target)?;
+
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+
+    Ok((buffer, buffer_memory))
+}
+
+/// Copies `size` bytes between buffers on the transfer queue and hands
+/// `destination` over to `target`.
+pub unsafe fn copy_buffer(
+    device: &Device,
+    data: &AppData,
+    source: vk::Buffer,
+    destination: vk::Buffer,
+    size: vk::DeviceSize,
+    target: UploadTarget,
+) -> Result<()> {
+    let transfer_family = data.queue_families.transfer;
+    let ownership_transfer = transfer_family != target.queue_family;
+
+    let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
+
+    let region = vk::BufferCopy::builder().size(size);
+    device.cmd_copy_buffer(command_buffer, source, destination, &[region]);
+
+    let barrier = |src_access, dst_access| {
+        vk::BufferMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(if ownership_transfer { transfer_family } else { vk::QUEUE_FAMILY_IGNORED })
+            .dst_queue_family_index(if ownership_transfer { target.queue_family } else { vk::QUEUE_FAMILY_IGNORED })
+            .buffer(destination)
+            .offset(0)
+            .size(vk::WHOLE_SIZE as vk::DeviceSize)
+    };
+
+    if !ownership_transfer {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            target.stage,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[barrier(vk::AccessFlags::TRANSFER_WRITE, target.access)],
+            &[] as &[vk::ImageMemoryBarrier],
+        );
+        return end_single_time_commands(
+            device,
+            data.transfer_command_pool,
+            data.transfer_queue,
+            command_buffer,
+            None,
+            None,
+        );
+    }
+
+    // Release on the transfer queue; the destination access is ignored here.
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[barrier(vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty())],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+
+    let semaphore = device.create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)?;
+    end_single_time_commands(
+        device,
+        data.transfer_command_pool,
+        data.transfer_queue,
+        command_buffer,
+        None,
+        Some(semaphore),
+    )?;
+
+    // Acquire on the target queue; the source access is ignored here.
+    let command_buffer = begin_single_time_commands(device, target.pool)?;
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TOP_OF_PIPE,
+        target.stage,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[barrier(vk::AccessFlags::empty(), target.access)],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+    end_single_time_commands(
+        device,
+        target.pool,
+        target.queue,
+        command_buffer,
+        Some((semaphore, target.stage)),
+        None,
+    )?;
+
+    device.destroy_semaphore(semaphore, None);
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::f32::consts::FRAC_PI_2;
+
+use cgmath::{vec3, InnerSpace, Rad};
+
+use crate::camera::Camera;
+use crate::input::Input;
+use crate::input::bindings::Action;
+
+type Point3 = cgmath::Point3<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+
+/// Keeps the camera from flipping over when looking straight up or down.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Moves a camera in response to input.
+///
+/// `update` runs once per frame with that frame's input, scaling movement
+/// that is a rate (e.g. held movement keys) by the frame time.
+#[derive(Clone, Debug)]
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+impl CameraController {
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, delta_time: f32) {
+        if input.pressed(Action::ToggleCameraMode) {
+            self.toggle(camera);
+        }
+
+        match self {
+            CameraController::Orbit(c) => c.update(camera, input, delta_time),
+            CameraController::Fly(c) => c.update(camera, input, delta_time),
+        }
+    }
+
+    /// Switches between orbiting and flying, keeping the current view.
+    pub fn toggle(&mut self, camera: &Camera) {
+        *self = match self {
+            CameraController::Orbit(_) => CameraController::Fly(FlyController::looking_from(camera)),
+            CameraController::Fly(_) => CameraController::Orbit(OrbitController::looking_from(camera)),
+        };
+    }
+}
+
+/// The unit vector for a yaw around +Y (0 looks down -Z) and a pitch above the horizon.
+fn direction(yaw: Rad<f32>, pitch: Rad<f32>) -> Vec3 {
+    let (sin_yaw, cos_yaw) = yaw.0.sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.0.sin_cos();
+    vec3(sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch)
+}
+
+/// The inverse of `direction`.
+fn yaw_pitch(direction: Vec3) -> (Rad<f32>, Rad<f32>) {
+    let direction = direction.normalize();
+    (Rad(direction.x.atan2(-direction.z)), Rad(direction.y.clamp(-1.0, 1.0).asin()))
+}
+
+/// Orbits around a target point: drag while `Action::Orbit` is held to
+/// rotate, scroll to zoom.
+#[derive(Clone, Debug)]
+pub struct OrbitController {
+    pub target: Point3,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    /// Radians per pixel of mouse movement.
+    pub rotate_speed: f32,
+    /// Fraction of the distance per scrolled line.
+    pub zoom_speed: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: Point3, distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            min_distance: 0.5,
+            max_distance: 500.0,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            rotate_speed: 0.005,
+            zoom_speed: 0.1,
+        }
+    }
+
+    pub fn looking_from(camera: &Camera) -> Self {
+        let offset = camera.target - camera.eye;
+        let (yaw, pitch) = yaw_pitch(offset);
+        Self { yaw, pitch, ..Self::new(camera.target, offset.magnitude()) }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, _delta_time: f32) {
+        if input.held(Action::Orbit) {
+            let (dx, dy) = input.mouse_delta();
+            self.yaw += Rad(dx as f32 * self.rotate_speed);
+            self.pitch = Rad((self.pitch.0 - dy as f32 * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH));
+        }
+        self.distance = (self.distance * (1.0 - input.scroll() * self.zoom_speed))
+            .clamp(self.min_distance, self.max_distance);
+
+        camera.target = self.target;
+        camera.eye = self.target - direction(self.yaw, self.pitch) * self.distance;
+    }
+}
+
+/// First person flying with the movement actions, looking around while
+/// `Action::Look` is held and scrolling to change speed.
+#[derive(Clone, Debug)]
+pub struct FlyController {
+    pub position: Point3,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    /// World units per second.
+    pub speed: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+}
+
+impl FlyController {
+    pub fn new(position: Point3) -> Self {
+        Self {
+            position,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            speed: 10.0,
+            sensitivity: 0.003,
+        }
+    }
+
+    pub fn looking_from(camera: &Camera) -> Self {
+        let (yaw, pitch) = yaw_pitch(camera.target - camera.eye);
+        Self { yaw, pitch, ..Self::new(camera.eye) }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, delta_time: f32) {
+        if input.held(Action::Look) {
+            let (dx, dy) = input.mouse_delta();
+            self.yaw += Rad(dx as f32 * self.sensitivity);
+            self.pitch = Rad((self.pitch.0 - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH));
+        }
+        self.speed = (self.speed * 1.1f32.powf(input.scroll())).max(0.1);
+
+        let forward = direction(self.yaw, self.pitch);
+        let right = forward.cross(camera.up).normalize();
+        let movement = forward * input.axis(Action::MoveForward, Action::MoveBackward)
+            + right * input.axis(Action::MoveRight, Action::MoveLeft)
+            + camera.up * input.axis(Action::MoveUp, Action::MoveDown);
+        if movement.magnitude2() > 0.0 {
+            self.position += movement.normalize() * self.speed * delta_time;
+        }
+
+        camera.eye = self.position;
+        camera.target = self.position + forward;
+    }
+}
@@ -0,0 +1,194 @@
+pub mod controller;
+
+use cgmath::{point3, vec3, Deg, Matrix4};
+use vulkanalia::vk;
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Point3 = cgmath::Point3<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+
+/// Converts OpenGL clip space (which cgmath's projections produce) to Vulkan
+/// clip space: Y points down and depth goes from 0 to 1 instead of -1 to 1.
+#[rustfmt::skip]
+pub const OPENGL_TO_VULKAN: Mat4 = Matrix4::new(
+    1.0,  0.0, 0.0, 0.0,
+    0.0, -1.0, 0.0, 0.0,
+    0.0,  0.0, 0.5, 0.0,
+    0.0,  0.0, 0.5, 1.0,
+);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective { fovy: Deg<f32>, near: f32, far: f32 },
+    /// `height` is the world space height of the view volume; the width
+    /// follows from the aspect ratio.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    /// The projection to Vulkan clip space for a viewport of `aspect` (width / height).
+    pub fn matrix(&self, aspect: f32) -> Mat4 {
+        let projection = match *self {
+            Projection::Perspective { fovy, near, far } => cgmath::perspective(fovy, aspect, near, far),
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect;
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        };
+        OPENGL_TO_VULKAN * projection
+    }
+
+    /// The view space distances of the near and far planes.
+    pub fn depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { near, far, .. } | Projection::Orthographic { near, far, .. } => (near, far),
+        }
+    }
+
+    /// The same projection with different near and far planes, e.g. to
+    /// cover a slice of the view volume.
+    pub fn with_depth_range(self, near: f32, far: f32) -> Self {
+        match self {
+            Projection::Perspective { fovy, .. } => Projection::Perspective { fovy, near, far },
+            Projection::Orthographic { height, .. } => Projection::Orthographic { height, near, far },
+        }
+    }
+}
+
+/// A right-handed camera looking from `eye` at `target`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub eye: Point3,
+    pub target: Point3,
+    pub up: Vec3,
+    pub projection: Projection,
+    /// Viewport width / height, see `set_viewport`.
+    pub aspect: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: point3(0.0, 0.0, 5.0),
+            target: point3(0.0, 0.0, 0.0),
+            up: vec3(0.0, 1.0, 0.0),
+            projection: Projection::Perspective { fovy: Deg(45.0), near: 0.1, far: 1000.0 },
+            aspect: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    /// Updates the aspect ratio, e.g. after the swapchain was recreated.
+    pub fn set_viewport(&mut self, extent: vk::Extent2D) {
+        if extent.height != 0 {
+            self.aspect = extent.width as f32 / extent.height as f32;
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        self.projection.matrix(self.aspect)
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, vec4, SquareMatrix, Transform};
+
+    #[test]
+    fn perspective_matches_known_matrix() {
+        let projection = Projection::Perspective { fovy: Deg(90.0), near: 1.0, far: 3.0 };
+
+        // f = 1 / tan(45°) = 1; z' = far / (near - far) * z + near * far / (near - far).
+        #[rustfmt::skip]
+        let expected = Matrix4::new(
+            0.5,  0.0,  0.0,  0.0,
+            0.0, -1.0,  0.0,  0.0,
+            0.0,  0.0, -1.5, -1.0,
+            0.0,  0.0, -1.5,  0.0,
+        );
+        assert_abs_diff_eq!(projection.matrix(2.0), expected, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_vulkan_depth_range() {
+        let projection = Projection::Perspective { fovy: Deg(60.0), near: 0.5, far: 50.0 }.matrix(1.5);
+
+        let near = projection * vec4(0.0, 0.0, -0.5, 1.0);
+        let far = projection * vec4(0.0, 0.0, -50.0, 1.0);
+        assert_abs_diff_eq!(near.z / near.w, 0.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(far.z / far.w, 1.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn orthographic_matches_known_matrix() {
+        let projection = Projection::Orthographic { height: 4.0, near: 0.0, far: 10.0 };
+
+        #[rustfmt::skip]
+        let expected = Matrix4::new(
+            0.25,  0.0,  0.0,  0.0,
+            0.0,  -0.5,  0.0,  0.0,
+            0.0,   0.0, -0.1,  0.0,
+            0.0,   0.0,  0.0,  1.0,
+        );
+        assert_abs_diff_eq!(projection.matrix(2.0), expected, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn up_in_world_is_up_on_screen() {
+        // Vulkan's framebuffer Y points down, so world up must end up negative.
+        let camera = Camera::default();
+        let clip = camera.view_projection() * vec4(0.0, 1.0, 0.0, 1.0);
+        assert!(clip.y / clip.w < 0.0);
+    }
+
+    #[test]
+    fn view_from_default_position_is_translation() {
+        let camera = Camera::default();
+        assert_abs_diff_eq!(
+            camera.view(),
+            Matrix4::from_translation(vec3(0.0, 0.0, -5.0)),
+            epsilon = 1e-6,
+        );
+    }
+
+    #[test]
+    fn view_moves_eye_to_origin() {
+        let camera = Camera {
+            eye: point3(3.0, 4.0, -2.0),
+            target: point3(1.0, 0.0, 7.0),
+            ..Camera::default()
+        };
+        let eye = camera.view().transform_point(camera.eye);
+        assert_abs_diff_eq!(eye, point3(0.0, 0.0, 0.0), epsilon = 1e-5);
+
+        // The target lies straight ahead, down -Z.
+        let target = camera.view().transform_point(camera.target);
+        assert_abs_diff_eq!(target.x, 0.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(target.y, 0.0, epsilon = 1e-5);
+        assert!(target.z < 0.0);
+    }
+
+    #[test]
+    fn set_viewport_updates_aspect() {
+        let mut camera = Camera::default();
+        camera.set_viewport(vk::Extent2D { width: 1920, height: 1080 });
+        assert_abs_diff_eq!(camera.aspect, 16.0 / 9.0, epsilon = 1e-6);
+
+        // A minimized window keeps the last aspect instead of dividing by zero.
+        camera.set_viewport(vk::Extent2D { width: 0, height: 0 });
+        assert_abs_diff_eq!(camera.aspect, 16.0 / 9.0, epsilon = 1e-6);
+        assert!(camera.projection().is_invertible());
+    }
+}
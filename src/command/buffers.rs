@@ -0,0 +1,27 @@
+use anyhow::Result;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_names;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+
+
+/// Allocates one primary command buffer per frame in flight.
+///
+/// They are re-recorded every frame, see `App::update_command_buffer`.
+pub unsafe fn create_command_buffers(
+    device: &Device,
+    data: &mut AppData
+) -> Result<()> {
+    let info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(data.command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(MAX_FRAMES_IN_FLIGHT as u32);
+
+    data.command_buffers = device.allocate_command_buffers(&info)?;
+    set_object_names(device, data, &data.command_buffers, "frame command buffer");
+
+    Ok(())
+}
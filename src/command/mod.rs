@@ -1 +1,3 @@
+pub mod buffers;
 pub mod pool;
+pub mod single_time;
@@ -4,7 +4,7 @@ use vulkanalia::vk;
 use vulkanalia::prelude::v1_0::*;
 
 use crate::app::data::AppData;
-use crate::device::queues::QueueFamilyIndices;
+use crate::core::debug_utils::set_object_name;
 
 
 pub unsafe fn create_command_pool(
@@ -12,10 +12,19 @@ pub unsafe fn create_command_pool(
     device: &Device,
     data: &mut AppData
 ) -> Result<()> {
-    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+        .queue_family_index(data.queue_families.graphics);
+    data.command_pool = device.create_command_pool(&info, None)?;
 
+    // Short-lived command buffers for uploads on the transfer queue.
     let info = vk::CommandPoolCreateInfo::builder()
-        .flags(vk::CommandPoolCreateFlags::empty())
-        .queue_family_index(indices.graphics);
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(data.queue_families.transfer);
+    data.transfer_command_pool = device.create_command_pool(&info, None)?;
+
+    set_object_name(device, data, data.command_pool, "graphics command pool");
+    set_object_name(device, data, data.transfer_command_pool, "transfer command pool");
+
     Ok(())
 }
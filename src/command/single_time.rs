@@ -0,0 +1,55 @@
+use anyhow::Result;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+
+/// Allocates and begins a command buffer that is submitted once.
+pub unsafe fn begin_single_time_commands(
+    device: &Device,
+    pool: vk::CommandPool,
+) -> Result<vk::CommandBuffer> {
+    let info = vk::CommandBufferAllocateInfo::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(pool)
+        .command_buffer_count(1);
+    let command_buffer = device.allocate_command_buffers(&info)?[0];
+
+    let info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    device.begin_command_buffer(command_buffer, &info)?;
+
+    Ok(command_buffer)
+}
+
+/// Ends, submits and waits for a command buffer from `begin_single_time_commands`.
+///
+/// The submission waits on `wait` (if any) at `wait_stage` and signals `signal`
+/// (if any), which is how queue family ownership transfers are ordered.
+pub unsafe fn end_single_time_commands(
+    device: &Device,
+    pool: vk::CommandPool,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+    wait: Option<(vk::Semaphore, vk::PipelineStageFlags)>,
+    signal: Option<vk::Semaphore>,
+) -> Result<()> {
+    device.end_command_buffer(command_buffer)?;
+
+    let command_buffers = &[command_buffer];
+    let wait_semaphores = wait.iter().map(|(s, _)| *s).collect::<Vec<_>>();
+    let wait_stages = wait.iter().map(|(_, s)| *s).collect::<Vec<_>>();
+    let signal_semaphores = signal.into_iter().collect::<Vec<_>>();
+    let info = vk::SubmitInfo::builder()
+        .wait_semaphores(&wait_semaphores)
+        .wait_dst_stage_mask(&wait_stages)
+        .command_buffers(command_buffers)
+        .signal_semaphores(&signal_semaphores);
+
+    device.queue_submit(queue, &[info], vk::Fence::null())?;
+    device.queue_wait_idle(queue)?;
+
+    device.free_command_buffers(pool, command_buffers);
+
+    Ok(())
+}
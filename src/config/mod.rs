@@ -0,0 +1,443 @@
+use anyhow::{anyhow, Result};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use log::LevelFilter;
+use vulkanalia::vk;
+
+use crate::device::physical::{parse_msaa_samples, DeviceSelection};
+use crate::instance::ValidationSettings;
+use crate::swapchain::support::{self, ImageCountStrategy, PresentPolicy};
+
+/// The config file read when `--config` isn't given. It's fine for it to be
+/// missing.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// An option that can be set in the config file as `key = value`, on the
+/// command line as `--key value` (with `-` for `_`), and for some, with an
+/// environment variable.
+struct Setting {
+    key: &'static str,
+    env: Option<&'static str>,
+    /// What the value looks like, for the usage text.
+    value: &'static str,
+    help: &'static str,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting { key: "model", env: None, value: "PATH", help: "glTF model to show, also taken as the first argument" },
+    Setting { key: "width", env: None, value: "PIXELS", help: "window width (1024)" },
+    Setting { key: "height", env: None, value: "PIXELS", help: "window height (768)" },
+    Setting { key: "title", env: None, value: "TEXT", help: "window title" },
+    Setting { key: "fullscreen", env: None, value: "", help: "borderless fullscreen on the current monitor" },
+    Setting { key: "device", env: None, value: "auto|INDEX|NAME", help: "physical device, by index or part of its name" },
+    Setting { key: "msaa", env: None, value: "1|2|4|8|16|32|64", help: "samples per pixel in the scene (1)" },
+    Setting {
+        key: "validation",
+        env: Some("VALIDATION"),
+        value: "off|on|CHECKS",
+        help: "validation layer, with `sync`, `best_practices`, `gpu_assisted` or `debug_printf`",
+    },
+    Setting { key: "validation_ignore", env: Some("VALIDATION_IGNORE"), value: "IDS", help: "validation message IDs to drop" },
+    Setting { key: "validation_panic", env: Some("VALIDATION_PANIC"), value: "", help: "abort on validation errors" },
+    Setting {
+        key: "validation_collect",
+        env: Some("VALIDATION_COLLECT"),
+        value: "",
+        help: "keep validation warnings and errors for `App::messages`",
+    },
+    Setting { key: "surface_formats", env: Some("SURFACE_FORMATS"), value: "FORMATS", help: "ranked swapchain formats" },
+    Setting {
+        key: "present_mode",
+        env: Some("PRESENT_MODE"),
+        value: "vsync|off|adaptive|low_latency",
+        help: "how frames are presented (low_latency)",
+    },
+    Setting { key: "swapchain_images", env: Some("SWAPCHAIN_IMAGES"), value: "min|extra|N", help: "swapchain image count (extra)" },
+    Setting { key: "frame_stats_csv", env: Some("FRAME_STATS_CSV"), value: "PATH", help: "where to save frame timings on exit" },
+    Setting { key: "log", env: Some("RUST_LOG"), value: "LEVEL", help: "log level, or `module=level` list (info)" },
+];
+
+fn setting(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|s| s.key == key)
+}
+
+fn flag(key: &str) -> String {
+    format!("--{}", key.replace('_', "-"))
+}
+
+/// Where a setting's value came from, to point at it in errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Source {
+    CommandLine(String),
+    Env(&'static str),
+    File(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::CommandLine(flag) => write!(f, "`{}`", flag),
+            Source::Env(var) => write!(f, "the `{}` environment variable", var),
+            Source::File(path) => write!(f, "`{}`", path),
+        }
+    }
+}
+
+/// The command line: settings, the config file to read them from too, and
+/// whether to just print the usage.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandLine {
+    pub config: Option<String>,
+    pub help: bool,
+    values: HashMap<&'static str, (String, Source)>,
+}
+
+impl CommandLine {
+    /// Parses arguments without the program name: `--key value`,
+    /// `--key=value`, `--flag` for switches, and a model path.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut command_line = CommandLine::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                if command_line.values.contains_key("model") {
+                    return Err(anyhow!("Unexpected argument `{}`, only one model can be given.", arg));
+                }
+                command_line.values.insert("model", (arg, Source::CommandLine("MODEL".into())));
+                continue;
+            };
+            let (name, inline) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (name, None),
+            };
+
+            if name == "help" {
+                command_line.help = true;
+                continue;
+            }
+            let key = name.replace('-', "_");
+            let value = if name == "config" || setting(&key).is_some_and(|s| !s.value.is_empty()) {
+                inline.or_else(|| args.next()).ok_or_else(|| anyhow!("`--{}` needs a value.", name))?
+            } else {
+                inline.unwrap_or_else(|| "true".into())
+            };
+            if name == "config" {
+                command_line.config = Some(value);
+                continue;
+            }
+            let setting = setting(&key)
+                .ok_or_else(|| anyhow!("Unknown option `--{}`, see `--help`.", name))?;
+            command_line.values.insert(setting.key, (value, Source::CommandLine(flag(setting.key))));
+        }
+        Ok(command_line)
+    }
+}
+
+/// Reads a TOML config file's settings. Lists become comma separated.
+fn parse_file(text: &str, path: &str) -> Result<HashMap<&'static str, (String, Source)>> {
+    let table = text.parse::<toml::Table>().map_err(|e| anyhow!("Invalid config file `{}`: {}", path, e))?;
+    let mut values = HashMap::new();
+    for (key, value) in table {
+        let setting = setting(&key).ok_or_else(|| anyhow!(
+            "Unknown setting `{}` in `{}`, expected one of {:?}.",
+            key, path, SETTINGS.iter().map(|s| s.key).collect::<Vec<_>>(),
+        ))?;
+        let scalar = |value: &toml::Value| match value {
+            toml::Value::String(s) => Some(s.clone()),
+            toml::Value::Integer(i) => Some(i.to_string()),
+            toml::Value::Boolean(b) => Some(b.to_string()),
+            _ => None,
+        };
+        let value = match &value {
+            toml::Value::Array(items) => items.iter().map(scalar).collect::<Option<Vec<_>>>().map(|v| v.join(",")),
+            value => scalar(value),
+        };
+        let value = value.ok_or_else(|| anyhow!("`{}` in `{}` must be a string, number, boolean or list.", key, path))?;
+        values.insert(setting.key, (value, Source::File(path.to_string())));
+    }
+    Ok(values)
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "1" | "on" => Ok(true),
+        "false" | "0" | "off" => Ok(false),
+        _ => Err(anyhow!("Expected `true` or `false`, not `{}`.", value)),
+    }
+}
+
+fn parse_size(value: &str) -> Result<u32> {
+    match value.parse() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(anyhow!("Expected a positive number of pixels, not `{}`.", value)),
+    }
+}
+
+/// Checks a `log` filter: a level, or comma separated `module=level`s and
+/// levels.
+fn parse_log_filter(value: &str) -> Result<String> {
+    for directive in value.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let level = directive.split_once('=').map_or(directive, |(_, level)| level);
+        if level.parse::<LevelFilter>().is_err() {
+            return Err(anyhow!(
+                "Unknown log level `{}`, expected `off`, `error`, `warn`, `info`, `debug` or `trace`.",
+                level,
+            ));
+        }
+    }
+    Ok(value.to_string())
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|i| !i.is_empty()).map(String::from).collect()
+}
+
+/// Everything that can be set before the app starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub model: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub fullscreen: bool,
+    pub device: DeviceSelection,
+    /// Lowered to what the device supports, see `pick_physical_device`.
+    pub msaa_samples: vk::SampleCountFlags,
+    pub validation: ValidationSettings,
+    /// Message IDs to drop, see `MessageSink`.
+    pub validation_ignore: Vec<String>,
+    pub validation_panic: bool,
+    /// Keeps warnings and errors for `MessageSink::take_messages`.
+    pub validation_collect: bool,
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+    pub present_policy: PresentPolicy,
+    pub image_count_strategy: ImageCountStrategy,
+    pub frame_stats_csv: Option<String>,
+    /// A filter for `pretty_env_logger`.
+    pub log: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            model: None,
+            width: 1024,
+            height: 768,
+            title: "Vulkan Tutorial (Rust)".into(),
+            fullscreen: false,
+            device: DeviceSelection::Auto,
+            msaa_samples: vk::SampleCountFlags::_1,
+            validation: ValidationSettings::for_build(),
+            validation_ignore: vec![],
+            validation_panic: false,
+            validation_collect: false,
+            surface_formats: support::DEFAULT_SURFACE_FORMATS.to_vec(),
+            present_policy: PresentPolicy::default(),
+            image_count_strategy: ImageCountStrategy::default(),
+            frame_stats_csv: None,
+            log: "info".into(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config from this process's arguments, environment and the
+    /// config file. `None` if the usage was asked for instead.
+    pub fn load() -> Result<Option<Self>> {
+        let command_line = CommandLine::parse(std::env::args().skip(1))?;
+        if command_line.help {
+            return Ok(None);
+        }
+
+        let path = command_line.config.as_deref().unwrap_or(DEFAULT_CONFIG_PATH);
+        let file = if command_line.config.is_some() || Path::new(path).exists() {
+            let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read `{}`: {}", path, e))?;
+            Some((path, text))
+        } else {
+            None
+        };
+
+        let file = file.as_ref().map(|(path, text)| (*path, text.as_str()));
+        Self::resolve(&command_line, |var| std::env::var(var).ok(), file).map(Some)
+    }
+
+    /// Combines the settings, with the command line over the environment
+    /// (read with `env`) over the config `file` (path and text) over the
+    /// defaults.
+    pub fn resolve(
+        command_line: &CommandLine,
+        env: impl Fn(&str) -> Option<String>,
+        file: Option<(&str, &str)>,
+    ) -> Result<Self> {
+        let file = match file {
+            Some((path, text)) => parse_file(text, path)?,
+            None => HashMap::new(),
+        };
+        let mut values = file;
+        for setting in SETTINGS {
+            if let Some(var) = setting.env
+                && let Some(value) = env(var)
+            {
+                values.insert(setting.key, (value, Source::Env(var)));
+            }
+        }
+        values.extend(command_line.values.clone());
+
+        let mut config = Config::default();
+        for (key, (value, source)) in values {
+            let value = value.trim();
+            config.set(key, value).map_err(|e| anyhow!("Invalid {} from {}: {}", key, source, e))?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "model" => self.model = Some(value.to_string()),
+            "width" => self.width = parse_size(value)?,
+            "height" => self.height = parse_size(value)?,
+            "title" => self.title = value.to_string(),
+            "fullscreen" => self.fullscreen = parse_bool(value)?,
+            "device" => self.device = value.parse()?,
+            "msaa" => self.msaa_samples = parse_msaa_samples(value)?,
+            "validation" => self.validation = ValidationSettings::parse(value)?,
+            "validation_ignore" => self.validation_ignore = parse_list(value),
+            "validation_panic" => self.validation_panic = parse_bool(value)?,
+            "validation_collect" => self.validation_collect = parse_bool(value)?,
+            "surface_formats" => self.surface_formats = support::parse_surface_formats(value)?,
+            "present_mode" => self.present_policy = value.parse()?,
+            "swapchain_images" => self.image_count_strategy = value.parse()?,
+            "frame_stats_csv" => self.frame_stats_csv = Some(value.to_string()).filter(|p| !p.is_empty()),
+            "log" => self.log = parse_log_filter(value)?,
+            _ => unreachable!("`{}` isn't in `SETTINGS`", key),
+        }
+        Ok(())
+    }
+}
+
+/// The `--help` text.
+pub fn usage() -> String {
+    let mut usage = format!(
+        "Usage: vulkan-tutorial [MODEL] [--config PATH] [OPTIONS]\n\n\
+         Options are also read from `{}` (or `--config`) as `key = value`, with `_` for `-`,\n\
+         and some from environment variables. The command line wins over the environment,\n\
+         which wins over the file.\n\n",
+        DEFAULT_CONFIG_PATH,
+    );
+    for setting in SETTINGS {
+        let name = format!("{} {}", flag(setting.key), setting.value);
+        let env = setting.env.map(|v| format!(" [{}]", v)).unwrap_or_default();
+        usage += &format!("  {:<46} {}{}\n", name.trim_end(), setting.help, env);
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> CommandLine {
+        CommandLine::parse(args.iter().map(|a| a.to_string())).unwrap()
+    }
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn command_line_parses() {
+        let command_line = args(&["model.glb", "--width", "800", "--msaa=4", "--fullscreen", "--config", "other.toml"]);
+        assert_eq!(command_line.config.as_deref(), Some("other.toml"));
+        let config = Config::resolve(&command_line, no_env, None).unwrap();
+        assert_eq!(config.model.as_deref(), Some("model.glb"));
+        assert_eq!((config.width, config.height), (800, 768));
+        assert_eq!(config.msaa_samples, vk::SampleCountFlags::_4);
+        assert!(config.fullscreen);
+
+        assert!(args(&["--help"]).help);
+        assert!(CommandLine::parse(["--wdth".to_string(), "1".to_string()]).is_err());
+        assert!(CommandLine::parse(["--width".to_string()]).is_err());
+        assert!(CommandLine::parse(["a.glb".to_string(), "b.glb".to_string()]).is_err());
+    }
+
+    #[test]
+    fn file_parses() {
+        let file = r#"
+            title = "Test"
+            height = 600
+            validation_panic = true
+            surface_formats = ["hdr10", "bgra8_srgb"]
+            validation_ignore = ["VUID-a", "12"]
+        "#;
+        let config = Config::resolve(&CommandLine::default(), no_env, Some(("config.toml", file))).unwrap();
+        assert_eq!(config.title, "Test");
+        assert_eq!(config.height, 600);
+        assert!(config.validation_panic);
+        assert_eq!(config.surface_formats.len(), 2);
+        assert_eq!(config.validation_ignore, ["VUID-a", "12"]);
+
+        let unknown = Config::resolve(&CommandLine::default(), no_env, Some(("config.toml", "colour = 1")));
+        assert!(unknown.unwrap_err().to_string().contains("Unknown setting `colour`"));
+        let table = Config::resolve(&CommandLine::default(), no_env, Some(("config.toml", "[window]\nwidth = 1")));
+        assert!(table.is_err());
+    }
+
+    #[test]
+    fn command_line_beats_env_beats_file() {
+        let file = "present_mode = \"vsync\"\nlog = \"warn\"\nswapchain_images = 3";
+        let env = |var: &str| match var {
+            "PRESENT_MODE" => Some("off".to_string()),
+            "RUST_LOG" => Some("debug".to_string()),
+            _ => None,
+        };
+        let command_line = args(&["--present-mode", "adaptive"]);
+        let config = Config::resolve(&command_line, env, Some(("config.toml", file))).unwrap();
+        assert_eq!(config.present_policy, PresentPolicy::Adaptive);
+        assert_eq!(config.log, "debug");
+        assert_eq!(config.image_count_strategy, ImageCountStrategy::Exact(3));
+        assert_eq!(config.width, Config::default().width);
+    }
+
+    #[test]
+    fn collecting_messages_can_be_turned_on() {
+        use crate::core::messages::{DebugMessage, MessageSink};
+        use vulkanalia::vk::{DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessageTypeFlagsEXT};
+
+        let env = |var: &str| (var == "VALIDATION_COLLECT").then(|| "1".to_string());
+        let config = Config::resolve(&CommandLine::default(), env, None).unwrap();
+        assert!(config.validation_collect);
+        assert!(!Config::default().validation_collect);
+
+        let sink = MessageSink::new(config.validation_ignore, config.validation_panic, config.validation_collect);
+        sink.handle(DebugMessage {
+            severity: DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            type_: DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            id_name: "VUID-collected".into(),
+            id_number: 1,
+            message: "Something is wrong.".into(),
+            objects: vec![],
+            queue_labels: vec![],
+            command_buffer_labels: vec![],
+        });
+        let messages = sink.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id_name, "VUID-collected");
+    }
+
+    #[test]
+    fn errors_name_the_source() {
+        let error = |command_line: &[&str], env: Option<&str>, file: &str| {
+            let env = |var: &str| env.filter(|_| var == "VALIDATION_PANIC").map(String::from);
+            Config::resolve(&args(command_line), env, Some(("config.toml", file))).unwrap_err().to_string()
+        };
+        assert!(error(&["--msaa", "3"], None, "").contains("`--msaa`"));
+        assert!(error(&[], Some("yes"), "").contains("`VALIDATION_PANIC`"));
+        assert!(error(&[], None, "width = 0").contains("`config.toml`"));
+        assert!(error(&[], None, "log = \"loud\"").contains("Unknown log level `loud`"));
+        assert!(error(&["--log", "app=verbose"], None, "").contains("`verbose`"));
+        assert!(parse_log_filter("warn,vulkan_tutorial=debug").is_ok());
+    }
+}
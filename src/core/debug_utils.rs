@@ -0,0 +1,100 @@
+use log::*;
+
+use std::ffi::CString;
+
+use vulkanalia::vk::{self, ExtDebugUtilsExtension, Handle};
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+
+/// Label colors, so passes are easy to tell apart in graphics debuggers.
+pub const SHADOW_COLOR: [f32; 4] = [0.3, 0.3, 0.6, 1.0];
+pub const SCENE_COLOR: [f32; 4] = [0.2, 0.6, 0.2, 1.0];
+pub const POST_COLOR: [f32; 4] = [0.7, 0.4, 0.1, 1.0];
+pub const COMPUTE_COLOR: [f32; 4] = [0.6, 0.2, 0.6, 1.0];
+
+fn c_string(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap_or_default()
+}
+
+/// Names `handle` in validation messages and graphics debuggers. Does
+/// nothing unless `VK_EXT_debug_utils` is enabled.
+pub unsafe fn set_object_name<H>(device: &Device, data: &AppData, handle: H, name: &str)
+where
+    H: Handle,
+    H::Repr: TryInto<u64>,
+{
+    let Some(instance) = &data.debug_utils else { return };
+    let Ok(raw) = handle.as_raw().try_into() else { return };
+    if raw == 0 {
+        return;
+    }
+
+    let name = c_string(name);
+    let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+        .object_type(H::TYPE)
+        .object_handle(raw)
+        .object_name(name.as_bytes_with_nul());
+    if let Err(e) = instance.set_debug_utils_object_name_ext(device.handle(), &info) {
+        warn!("Failed to name {:?} `{:?}`: {}", H::TYPE, name, e);
+    }
+}
+
+/// Names each of `handles` `name` followed by its index.
+pub unsafe fn set_object_names<H>(device: &Device, data: &AppData, handles: &[H], name: &str)
+where
+    H: Handle,
+    H::Repr: TryInto<u64>,
+{
+    if data.debug_utils.is_some() {
+        for (index, handle) in handles.iter().enumerate() {
+            set_object_name(device, data, *handle, &format!("{} {}", name, index));
+        }
+    }
+}
+
+/// Starts a labeled region of commands, which must be ended with
+/// `cmd_end_label` in the same command buffer.
+pub unsafe fn cmd_begin_label(data: &AppData, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+    if let Some(instance) = &data.debug_utils {
+        let name = c_string(name);
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(name.as_bytes_with_nul())
+            .color(color);
+        instance.cmd_begin_debug_utils_label_ext(command_buffer, &label);
+    }
+}
+
+pub unsafe fn cmd_end_label(data: &AppData, command_buffer: vk::CommandBuffer) {
+    if let Some(instance) = &data.debug_utils {
+        instance.cmd_end_debug_utils_label_ext(command_buffer);
+    }
+}
+
+/// Marks a single point in a command buffer.
+pub unsafe fn cmd_insert_label(data: &AppData, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+    if let Some(instance) = &data.debug_utils {
+        let name = c_string(name);
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(name.as_bytes_with_nul())
+            .color(color);
+        instance.cmd_insert_debug_utils_label_ext(command_buffer, &label);
+    }
+}
+
+/// Starts a labeled region of submissions to `queue`, which must be ended
+/// with `queue_end_label`.
+pub unsafe fn queue_begin_label(data: &AppData, queue: vk::Queue, name: &str) {
+    if let Some(instance) = &data.debug_utils {
+        let name = c_string(name);
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(name.as_bytes_with_nul());
+        instance.queue_begin_debug_utils_label_ext(queue, &label);
+    }
+}
+
+pub unsafe fn queue_end_label(data: &AppData, queue: vk::Queue) {
+    if let Some(instance) = &data.debug_utils {
+        instance.queue_end_debug_utils_label_ext(queue);
+    }
+}
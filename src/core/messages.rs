@@ -0,0 +1,218 @@
+use log::*;
+
+use std::ffi::{c_char, CStr};
+use std::fmt;
+use std::sync::Mutex;
+
+use vulkanalia::vk::{
+    self,
+    DebugUtilsMessageSeverityFlagsEXT,
+    DebugUtilsMessageTypeFlagsEXT,
+    DebugUtilsMessengerCallbackDataEXT,
+};
+
+/// How many messages a collecting sink keeps before dropping new ones.
+pub const MAX_COLLECTED_MESSAGES: usize = 1024;
+
+/// A message from the debug messenger, with the names of the objects and the
+/// queue and command buffer labels it came with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugMessage {
+    pub severity: DebugUtilsMessageSeverityFlagsEXT,
+    pub type_: DebugUtilsMessageTypeFlagsEXT,
+    /// E.g. `VUID-vkCmdDraw-None-02859`, empty if the layer gave none.
+    pub id_name: String,
+    pub id_number: i32,
+    pub message: String,
+    /// `<type> <handle>`, followed by the object's name if it has one.
+    pub objects: Vec<String>,
+    /// Innermost last.
+    pub queue_labels: Vec<String>,
+    pub command_buffer_labels: Vec<String>,
+}
+
+unsafe fn string(pointer: *const c_char) -> String {
+    if pointer.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(pointer).to_string_lossy().into_owned()
+    }
+}
+
+unsafe fn labels(labels: *const vk::DebugUtilsLabelEXT, count: u32) -> Vec<String> {
+    if labels.is_null() {
+        return vec![];
+    }
+    std::slice::from_raw_parts(labels, count as usize)
+        .iter()
+        .map(|l| string(l.label_name))
+        .collect()
+}
+
+impl DebugMessage {
+    /// Copies out of the data passed to the messenger callback.
+    pub unsafe fn from_callback(
+        severity: DebugUtilsMessageSeverityFlagsEXT,
+        type_: DebugUtilsMessageTypeFlagsEXT,
+        data: &DebugUtilsMessengerCallbackDataEXT,
+    ) -> Self {
+        let objects = if data.objects.is_null() {
+            vec![]
+        } else {
+            std::slice::from_raw_parts(data.objects, data.object_count as usize)
+                .iter()
+                .map(|o| {
+                    let name = string(o.object_name);
+                    if name.is_empty() {
+                        format!("{:?} {:#x}", o.object_type, o.object_handle)
+                    } else {
+                        format!("{:?} {:#x} `{}`", o.object_type, o.object_handle, name)
+                    }
+                })
+                .collect()
+        };
+
+        Self {
+            severity,
+            type_,
+            id_name: string(data.message_id_name),
+            id_number: data.message_id_number,
+            message: string(data.message),
+            objects,
+            queue_labels: labels(data.queue_labels, data.queue_label_count),
+            command_buffer_labels: labels(data.cmd_buf_labels, data.cmd_buf_label_count),
+        }
+    }
+
+    /// Whether `id` is this message's ID name, or its number in decimal or hex.
+    pub fn has_id(&self, id: &str) -> bool {
+        id == self.id_name
+            || id == self.id_number.to_string()
+            || id.eq_ignore_ascii_case(&format!("{:#x}", self.id_number as u32))
+    }
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({:?})", self.type_)?;
+        if !self.id_name.is_empty() {
+            write!(f, " [{}]", self.id_name)?;
+        }
+        write!(f, " {}", self.message)?;
+        if !self.objects.is_empty() {
+            write!(f, "\n    objects: {}", self.objects.join(", "))?;
+        }
+        if !self.queue_labels.is_empty() {
+            write!(f, "\n    queue: {}", self.queue_labels.join(" > "))?;
+        }
+        if !self.command_buffer_labels.is_empty() {
+            write!(f, "\n    command buffer: {}", self.command_buffer_labels.join(" > "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where debug messenger messages go: they're logged, and optionally kept
+/// for tests to check, or turned into panics if they're errors.
+///
+/// Shared with the messenger callback through its user data, so it has to
+/// outlive the instance.
+#[derive(Debug, Default)]
+pub struct MessageSink {
+    /// Message IDs (see `DebugMessage::has_id`) that are dropped entirely.
+    pub ignored_ids: Vec<String>,
+    /// Panics on error messages. The callback can't unwind, so this aborts,
+    /// which stops a debugger in the offending Vulkan call.
+    pub panic_on_error: bool,
+    /// Keeps warnings and errors until `take_messages`.
+    pub collect: bool,
+    messages: Mutex<Vec<DebugMessage>>,
+}
+
+impl MessageSink {
+    /// A sink that logs messages, see `ignored_ids`, `panic_on_error` and
+    /// `collect`.
+    pub fn new(ignored_ids: Vec<String>, panic_on_error: bool, collect: bool) -> Self {
+        Self { ignored_ids, panic_on_error, collect, ..Self::default() }
+    }
+
+    pub fn handle(&self, message: DebugMessage) {
+        if self.ignored_ids.iter().any(|id| message.has_id(id)) {
+            return;
+        }
+
+        let severity = message.severity;
+        if severity >= DebugUtilsMessageSeverityFlagsEXT::ERROR {
+            error!("{}", message);
+        } else if severity >= DebugUtilsMessageSeverityFlagsEXT::WARNING {
+            warn!("{}", message);
+        } else if severity >= DebugUtilsMessageSeverityFlagsEXT::INFO {
+            debug!("{}", message);
+        } else {
+            trace!("{}", message);
+        }
+
+        if self.panic_on_error && severity >= DebugUtilsMessageSeverityFlagsEXT::ERROR {
+            panic!("Validation error: {}", message);
+        }
+
+        if self.collect && severity >= DebugUtilsMessageSeverityFlagsEXT::WARNING {
+            let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+            if messages.len() < MAX_COLLECTED_MESSAGES {
+                messages.push(message);
+            }
+        }
+    }
+
+    /// The collected messages since the last call.
+    pub fn take_messages(&self) -> Vec<DebugMessage> {
+        std::mem::take(&mut *self.messages.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(severity: DebugUtilsMessageSeverityFlagsEXT, id_name: &str, id_number: i32) -> DebugMessage {
+        DebugMessage {
+            severity,
+            type_: DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            id_name: id_name.into(),
+            id_number,
+            message: "Something is wrong.".into(),
+            objects: vec!["IMAGE 0x2a `shadow maps`".into()],
+            queue_labels: vec![],
+            command_buffer_labels: vec!["frame".into(), "shadows".into()],
+        }
+    }
+
+    #[test]
+    fn collects_warnings_and_errors_that_are_not_ignored() {
+        let sink = MessageSink::new(vec!["VUID-ignored".into(), "0xff".into()], false, true);
+        sink.handle(message(DebugUtilsMessageSeverityFlagsEXT::ERROR, "VUID-ignored", 1));
+        sink.handle(message(DebugUtilsMessageSeverityFlagsEXT::ERROR, "VUID-hex", 255));
+        sink.handle(message(DebugUtilsMessageSeverityFlagsEXT::INFO, "VUID-info", 2));
+        sink.handle(message(DebugUtilsMessageSeverityFlagsEXT::WARNING, "VUID-kept", 3));
+
+        let messages = sink.take_messages();
+        assert_eq!(messages.iter().map(|m| m.id_name.as_str()).collect::<Vec<_>>(), ["VUID-kept"]);
+        assert!(sink.take_messages().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "VUID-fatal")]
+    fn errors_can_panic() {
+        let sink = MessageSink { panic_on_error: true, ..MessageSink::default() };
+        sink.handle(message(DebugUtilsMessageSeverityFlagsEXT::WARNING, "VUID-warning", 1));
+        sink.handle(message(DebugUtilsMessageSeverityFlagsEXT::ERROR, "VUID-fatal", 2));
+    }
+
+    #[test]
+    fn display_includes_objects_and_labels() {
+        let text = message(DebugUtilsMessageSeverityFlagsEXT::ERROR, "VUID-x", 1).to_string();
+        assert!(text.contains("[VUID-x]"), "{}", text);
+        assert!(text.contains("`shadow maps`"), "{}", text);
+        assert!(text.contains("frame > shadows"), "{}", text);
+    }
+}
@@ -1,37 +1,36 @@
+pub mod debug_utils;
+pub mod messages;
+
 use thiserror::Error;
-use std::ffi::CStr;
 use std::os::raw::c_void;
 
-use log::*;
-
 use vulkanalia::vk::{
     DebugUtilsMessageSeverityFlagsEXT,
     DebugUtilsMessageTypeFlagsEXT,
     DebugUtilsMessengerCallbackDataEXT,
     Bool32, FALSE
 };
+
+use crate::core::messages::{DebugMessage, MessageSink};
 #[derive(Debug, Error)]
 #[error("Missing {0}.")]
 pub struct SuitabilityError(pub &'static str);
 
 
+/// Forwards messages to the `MessageSink` passed as the messenger's user
+/// data, or just logs them if there is none.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn debug_callback(
     severity: DebugUtilsMessageSeverityFlagsEXT,
     type_: DebugUtilsMessageTypeFlagsEXT,
     data: *const DebugUtilsMessengerCallbackDataEXT,
-    _: *mut c_void,
+    user_data: *mut c_void,
 ) -> Bool32 {
-    let data = unsafe { *data };
-    let message = unsafe { CStr::from_ptr(data.message) }.to_string_lossy();
-
-    if severity >= DebugUtilsMessageSeverityFlagsEXT::ERROR {
-        error!("({:?}) {}", type_, message);
-    } else if severity >= DebugUtilsMessageSeverityFlagsEXT::WARNING {
-        warn!("({:?}) {}", type_, message);
-    } else if severity >= DebugUtilsMessageSeverityFlagsEXT::INFO {
-        debug!("({:?}) {}", type_, message);
-    } else {
-        trace!("({:?}) {}", type_, message);
+    let message = unsafe { DebugMessage::from_callback(severity, type_, &*data) };
+    let sink = unsafe { user_data.cast::<MessageSink>().as_ref() };
+    match sink {
+        Some(sink) => sink.handle(message),
+        None => MessageSink::default().handle(message),
     }
 
     FALSE
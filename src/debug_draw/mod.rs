@@ -0,0 +1,248 @@
+pub mod renderer;
+
+use std::f32::consts::TAU;
+use std::mem::size_of;
+
+use cgmath::{point3, vec4, EuclideanSpace, InnerSpace, SquareMatrix, Transform};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Point3 = cgmath::Point3<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// How many segments `sphere` draws each circle with.
+pub const SPHERE_SEGMENTS: usize = 24;
+
+pub const RED: Vec4 = vec4(1.0, 0.0, 0.0, 1.0);
+pub const GREEN: Vec4 = vec4(0.0, 1.0, 0.0, 1.0);
+pub const BLUE: Vec4 = vec4(0.0, 0.0, 1.0, 1.0);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DebugVertex {
+    pub position: Vec3,
+    /// Linear RGB and alpha; drawn into the HDR scene color.
+    pub color: Vec4,
+}
+
+impl DebugVertex {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<DebugVertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        let position = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset(size_of::<Vec3>() as u32)
+            .build();
+        [position, color]
+    }
+}
+
+/// How the shapes added in `DebugDraw::with` are drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ShapeOptions {
+    /// Hidden behind the scene, instead of drawn over it.
+    pub depth_test: bool,
+    /// How many frames the shapes are drawn for.
+    pub lifetime: u32,
+}
+
+impl Default for ShapeOptions {
+    fn default() -> Self {
+        Self { depth_test: true, lifetime: 1 }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Line {
+    a: Point3,
+    b: Point3,
+    color: Vec4,
+    options: ShapeOptions,
+}
+
+/// Collects lines to draw over the scene, immediate-mode style: shapes are
+/// added every frame they should be seen, unless given a longer lifetime.
+#[derive(Clone, Debug, Default)]
+pub struct DebugDraw {
+    /// Hides everything without forgetting it.
+    pub hidden: bool,
+    options: ShapeOptions,
+    lines: Vec<Line>,
+}
+
+impl DebugDraw {
+    /// Adds the shapes `shapes` adds with `options`, e.g. to keep them for
+    /// several frames.
+    pub fn with(&mut self, options: ShapeOptions, shapes: impl FnOnce(&mut Self)) {
+        let previous = std::mem::replace(&mut self.options, options);
+        shapes(self);
+        self.options = previous;
+    }
+
+    pub fn line(&mut self, a: Point3, b: Point3, color: Vec4) {
+        self.lines.push(Line { a, b, color, options: self.options });
+    }
+
+    /// An axis-aligned box between the corners `min` and `max`.
+    pub fn aabb(&mut self, min: Point3, max: Point3, color: Vec4) {
+        let corner = |i: usize| {
+            point3(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        self.box_edges(&std::array::from_fn(corner), color);
+    }
+
+    /// Three circles around the axes.
+    pub fn sphere(&mut self, center: Point3, radius: f32, color: Vec4) {
+        let axes = [(Vec3::unit_x(), Vec3::unit_y()), (Vec3::unit_y(), Vec3::unit_z()), (Vec3::unit_z(), Vec3::unit_x())];
+        for (u, v) in axes {
+            let point = |i: usize| {
+                let (sin, cos) = (i as f32 / SPHERE_SEGMENTS as f32 * TAU).sin_cos();
+                center + (u * cos + v * sin) * radius
+            };
+            for i in 0..SPHERE_SEGMENTS {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
+
+    /// The volume `view_projection` (to Vulkan clip space) sees, e.g. a
+    /// camera's or a shadow map's.
+    pub fn frustum(&mut self, view_projection: Mat4, color: Vec4) {
+        let Some(inverse) = view_projection.invert() else { return };
+        let corner = |i: usize| {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+            inverse.transform_point(point3(x, y, z))
+        };
+        self.box_edges(&std::array::from_fn(corner), color);
+    }
+
+    /// `transform`'s X, Y and Z axes in red, green and blue, `size` long.
+    pub fn axes(&mut self, transform: Mat4, size: f32) {
+        let origin = transform.transform_point(Point3::origin());
+        for (axis, color) in [(Vec3::unit_x(), RED), (Vec3::unit_y(), GREEN), (Vec3::unit_z(), BLUE)] {
+            let direction = transform.transform_vector(axis);
+            if direction.magnitude2() > 0.0 {
+                self.line(origin, origin + direction.normalize() * size, color);
+            }
+        }
+    }
+
+    /// The 12 edges between 8 corners indexed by bits (x, y, z).
+    fn box_edges(&mut self, corners: &[Point3; 8], color: Vec4) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// The line list vertices to draw this frame: depth tested, then not.
+    pub fn vertices(&self) -> (Vec<DebugVertex>, Vec<DebugVertex>) {
+        if self.hidden {
+            return (vec![], vec![]);
+        }
+        let (mut tested, mut overlay) = (Vec::new(), Vec::new());
+        for line in &self.lines {
+            let vertices = if line.options.depth_test { &mut tested } else { &mut overlay };
+            vertices.push(DebugVertex { position: line.a.to_vec(), color: line.color });
+            vertices.push(DebugVertex { position: line.b.to_vec(), color: line.color });
+        }
+        (tested, overlay)
+    }
+
+    /// Forgets shapes whose lifetime is over. Call after each frame is recorded.
+    pub fn end_frame(&mut self) {
+        self.lines.retain_mut(|l| {
+            l.options.lifetime = l.options.lifetime.saturating_sub(1);
+            l.options.lifetime > 0
+        });
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, vec3, Matrix4};
+
+    #[test]
+    fn shapes_have_the_expected_lines() {
+        let mut draw = DebugDraw::default();
+        draw.aabb(point3(0.0, 0.0, 0.0), point3(1.0, 2.0, 3.0), RED);
+        assert_eq!(draw.line_count(), 12);
+        draw.sphere(point3(0.0, 0.0, 0.0), 1.0, GREEN);
+        assert_eq!(draw.line_count(), 12 + 3 * SPHERE_SEGMENTS);
+        draw.axes(Matrix4::from_scale(2.0), 0.5);
+        assert_eq!(draw.line_count(), 12 + 3 * SPHERE_SEGMENTS + 3);
+
+        let (tested, overlay) = draw.vertices();
+        assert_eq!(tested.len(), draw.line_count() * 2);
+        assert!(overlay.is_empty());
+        // The axes are `size` long whatever the transform's scale.
+        let x = &tested[tested.len() - 6..tested.len() - 4];
+        assert_abs_diff_eq!(x[1].position, vec3(0.5, 0.0, 0.0));
+        assert_eq!(x[1].color, RED);
+    }
+
+    #[test]
+    fn frustum_corners_come_from_the_inverse() {
+        let mut draw = DebugDraw::default();
+        draw.frustum(Matrix4::from_scale(0.5), BLUE);
+        let (vertices, _) = draw.vertices();
+        assert_eq!(vertices.len(), 24);
+        let max_x = vertices.iter().map(|v| v.position.x).fold(f32::MIN, f32::max);
+        let max_z = vertices.iter().map(|v| v.position.z).fold(f32::MIN, f32::max);
+        assert_eq!((max_x, max_z), (2.0, 2.0));
+    }
+
+    #[test]
+    fn options_set_depth_testing_and_lifetime() {
+        let mut draw = DebugDraw::default();
+        draw.line(point3(0.0, 0.0, 0.0), point3(1.0, 0.0, 0.0), RED);
+        draw.with(ShapeOptions { depth_test: false, lifetime: 3 }, |d| {
+            d.line(point3(0.0, 0.0, 0.0), point3(0.0, 1.0, 0.0), GREEN);
+        });
+        draw.line(point3(0.0, 0.0, 0.0), point3(0.0, 0.0, 1.0), BLUE);
+
+        let (tested, overlay) = draw.vertices();
+        assert_eq!((tested.len(), overlay.len()), (4, 2));
+
+        draw.end_frame();
+        assert_eq!(draw.line_count(), 1);
+        draw.end_frame();
+        draw.end_frame();
+        assert_eq!(draw.line_count(), 0);
+
+        draw.line(point3(0.0, 0.0, 0.0), point3(1.0, 0.0, 0.0), RED);
+        draw.hidden = true;
+        assert_eq!(draw.vertices(), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use log::*;
+
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::create_buffer;
+use crate::core::debug_utils::{set_object_name, set_object_names};
+use crate::debug_draw::{DebugDraw, DebugVertex};
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::pipeline::graphics::{create_graphics_pipeline, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range, PushConstants};
+
+type Mat4 = cgmath::Matrix4<f32>;
+
+/// How many vertices (two per line) a frame can draw. Lines past this are
+/// dropped.
+pub const MAX_DEBUG_VERTICES: usize = 65_536;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DebugDrawPushConstants {
+    view_proj: Mat4,
+}
+
+const _: () = assert!(size_of::<DebugDrawPushConstants>() == 64);
+
+// SAFETY: `#[repr(C)]` and `Mat4` is all `f32`s, so there is no padding.
+unsafe impl PushConstants for DebugDrawPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+/// Draws a `DebugDraw`'s lines in the scene render pass, from a host visible
+/// vertex buffer per frame in flight.
+#[derive(Clone, Debug, Default)]
+pub struct DebugDrawRenderer {
+    pub vertex_buffers: Vec<vk::Buffer>,
+    pub vertex_buffers_memory: Vec<vk::DeviceMemory>,
+    /// How many depth tested and overlay vertices each frame's buffer holds,
+    /// in that order.
+    pub vertex_counts: Vec<(u32, u32)>,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub depth_tested_pipeline: vk::Pipeline,
+    pub overlay_pipeline: vk::Pipeline,
+    /// Whether dropping lines was already logged.
+    warned: bool,
+}
+
+pub unsafe fn create_debug_draw_renderer(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let mut renderer = DebugDrawRenderer::default();
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            (MAX_DEBUG_VERTICES * size_of::<DebugVertex>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        renderer.vertex_buffers.push(buffer);
+        renderer.vertex_buffers_memory.push(buffer_memory);
+        renderer.vertex_counts.push((0, 0));
+    }
+
+    let push_constant_ranges = &[push_constant_range::<DebugDrawPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .push_constant_ranges(push_constant_ranges);
+    renderer.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let bindings = &[DebugVertex::binding_description()];
+    let attributes = &DebugVertex::attribute_descriptions();
+    let info = GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        topology: vk::PrimitiveTopology::LINE_LIST,
+        cull_mode: vk::CullModeFlags::NONE,
+        samples: data.msaa_samples,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/debug_draw_vert.spv")[..],
+            &include_bytes!("../shaders/debug_draw_frag.spv")[..],
+            renderer.pipeline_layout,
+            data.render_pass,
+        )
+    };
+    renderer.overlay_pipeline = create_graphics_pipeline(device, &info)?;
+    renderer.depth_tested_pipeline = create_graphics_pipeline(device, &GraphicsPipelineInfo {
+        depth_test: true,
+        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        ..info
+    })?;
+
+    data.debug_draw = renderer;
+
+    let renderer = &data.debug_draw;
+    set_object_names(device, data, &renderer.vertex_buffers, "debug draw vertices");
+    set_object_name(device, data, renderer.pipeline_layout, "debug draw pipeline layout");
+    set_object_name(device, data, renderer.depth_tested_pipeline, "debug draw depth tested pipeline");
+    set_object_name(device, data, renderer.overlay_pipeline, "debug draw overlay pipeline");
+
+    Ok(())
+}
+
+/// Writes `debug_draw`'s lines for `frame`, whose previous submission must
+/// have completed.
+pub unsafe fn update_debug_draw(
+    device: &Device,
+    renderer: &mut DebugDrawRenderer,
+    frame: usize,
+    debug_draw: &DebugDraw,
+) -> Result<()> {
+    let (mut tested, mut overlay) = debug_draw.vertices();
+    if tested.len() + overlay.len() > MAX_DEBUG_VERTICES {
+        if !renderer.warned {
+            warn!("Too many debug lines to draw, dropping some.");
+            renderer.warned = true;
+        }
+        tested.truncate(MAX_DEBUG_VERTICES);
+        overlay.truncate(MAX_DEBUG_VERTICES - tested.len());
+    }
+
+    renderer.vertex_counts[frame] = (tested.len() as u32, overlay.len() as u32);
+    if tested.is_empty() && overlay.is_empty() {
+        return Ok(());
+    }
+
+    let memory = renderer.vertex_buffers_memory[frame];
+    let size = (tested.len() + overlay.len()) * size_of::<DebugVertex>();
+    let mapped = device.map_memory(memory, 0, size as vk::DeviceSize, vk::MemoryMapFlags::empty())?;
+    let mapped = mapped.cast::<DebugVertex>();
+    memcpy(tested.as_ptr(), mapped, tested.len());
+    memcpy(overlay.as_ptr(), mapped.add(tested.len()), overlay.len());
+    device.unmap_memory(memory);
+
+    Ok(())
+}
+
+/// Records drawing the lines written for `frame`. Must be recorded in the
+/// scene render pass, after the scene so overlay lines end up on top.
+pub unsafe fn cmd_draw_debug(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &DebugDrawRenderer,
+    frame: usize,
+    view_proj: Mat4,
+) {
+    let (tested, overlay) = renderer.vertex_counts[frame];
+    if tested == 0 && overlay == 0 {
+        return;
+    }
+
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[renderer.vertex_buffers[frame]], &[0]);
+    cmd_push_constants(device, command_buffer, renderer.pipeline_layout, &DebugDrawPushConstants { view_proj });
+    for (pipeline, count, first) in [
+        (renderer.depth_tested_pipeline, tested, 0),
+        (renderer.overlay_pipeline, overlay, tested),
+    ] {
+        if count > 0 {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_draw(command_buffer, count, 1, first, 0);
+        }
+    }
+}
+
+pub unsafe fn destroy_debug_draw_renderer(device: &Device, renderer: &mut DebugDrawRenderer) {
+    device.destroy_pipeline(renderer.overlay_pipeline, None);
+    device.destroy_pipeline(renderer.depth_tested_pipeline, None);
+    device.destroy_pipeline_layout(renderer.pipeline_layout, None);
+    renderer.vertex_buffers.iter().for_each(|b| device.destroy_buffer(*b, None));
+    renderer.vertex_buffers_memory.iter().for_each(|m| device.free_memory(*m, None));
+    *renderer = DebugDrawRenderer::default();
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+
+pub fn uniform_buffer_binding(
+    binding: u32,
+    stages: vk::ShaderStageFlags,
+) -> vk::DescriptorSetLayoutBinding {
+    vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(stages)
+        .build()
+}
+
+pub fn storage_buffer_binding(
+    binding: u32,
+    stages: vk::ShaderStageFlags,
+) -> vk::DescriptorSetLayoutBinding {
+    vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(stages)
+        .build()
+}
+
+pub fn storage_image_binding(
+    binding: u32,
+    stages: vk::ShaderStageFlags,
+) -> vk::DescriptorSetLayoutBinding {
+    vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .descriptor_count(1)
+        .stage_flags(stages)
+        .build()
+}
+
+pub fn sampled_image_binding(
+    binding: u32,
+    stages: vk::ShaderStageFlags,
+) -> vk::DescriptorSetLayoutBinding {
+    vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+        .descriptor_count(1)
+        .stage_flags(stages)
+        .build()
+}
+
+pub fn sampler_binding(
+    binding: u32,
+    stages: vk::ShaderStageFlags,
+) -> vk::DescriptorSetLayoutBinding {
+    vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(stages)
+        .build()
+}
+
+pub unsafe fn create_descriptor_set_layout(
+    device: &Device,
+    bindings: &[vk::DescriptorSetLayoutBinding],
+) -> Result<vk::DescriptorSetLayout> {
+    let info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(bindings);
+
+    Ok(device.create_descriptor_set_layout(&info, None)?)
+}
+
+/// Creates a pool that can hold `max_sets` sets using up to `sizes` descriptors.
+pub unsafe fn create_descriptor_pool(
+    device: &Device,
+    sizes: &[vk::DescriptorPoolSize],
+    max_sets: u32,
+) -> Result<vk::DescriptorPool> {
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(sizes)
+        .max_sets(max_sets);
+
+    Ok(device.create_descriptor_pool(&info, None)?)
+}
+
+pub unsafe fn allocate_descriptor_set(
+    device: &Device,
+    pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+) -> Result<vk::DescriptorSet> {
+    let layouts = &[layout];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(pool)
+        .set_layouts(layouts);
+
+    Ok(device.allocate_descriptor_sets(&info)?[0])
+}
+
+pub unsafe fn write_uniform_buffer(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    buffer: vk::Buffer,
+) {
+    let info = vk::DescriptorBufferInfo::builder()
+        .buffer(buffer)
+        .offset(0)
+        .range(vk::WHOLE_SIZE as vk::DeviceSize);
+
+    let buffer_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .buffer_info(buffer_info);
+
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+pub unsafe fn write_storage_buffer(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    buffer: vk::Buffer,
+) {
+    let info = vk::DescriptorBufferInfo::builder()
+        .buffer(buffer)
+        .offset(0)
+        .range(vk::WHOLE_SIZE as vk::DeviceSize);
+
+    let buffer_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(buffer_info);
+
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// Writes a storage image that compute shaders access in the `GENERAL` layout.
+pub unsafe fn write_storage_image(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    view: vk::ImageView,
+) {
+    let info = vk::DescriptorImageInfo::builder()
+        .image_view(view)
+        .image_layout(vk::ImageLayout::GENERAL);
+
+    let image_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .image_info(image_info);
+
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// Writes a sampled image in the `SHADER_READ_ONLY_OPTIMAL` layout.
+pub unsafe fn write_sampled_image(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    view: vk::ImageView,
+) {
+    let info = vk::DescriptorImageInfo::builder()
+        .image_view(view)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+    let image_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+        .image_info(image_info);
+
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+pub unsafe fn write_sampler(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    sampler: vk::Sampler,
+) {
+    let info = vk::DescriptorImageInfo::builder()
+        .sampler(sampler);
+
+    let image_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
+        .image_info(image_info);
+
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
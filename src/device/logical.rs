@@ -1,17 +1,15 @@
 use log::*;
 use anyhow::Result;
-use std::collections::HashSet;
 
 use vulkanalia::vk;
 use vulkanalia::prelude::v1_4::*;
 
 use crate::app::data::AppData;
 use crate::instance::{
-    VALIDATION_ENABLED,
     VALIDATION_LAYER,
     PORTABILITY_MACOS_VERSION
 };
-use crate::device::queues::QueueFamilyIndices;
+use crate::device::queues::{QueueFamilyIndices, QueueLayout};
 use crate::device::DEVICE_EXTENTIONS;
 
 
@@ -20,29 +18,32 @@ pub unsafe fn create_logical_device(
     instance: &Instance,
     data: &mut AppData
 ) -> Result<Device> {
-    let queue_priorities = &[1.0];
-
     let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
-    let unique_indices = HashSet::from([
-        indices.graphics, indices.present
-    ]);
+    let queue_counts = instance
+        .get_physical_device_queue_family_properties(data.physical_device)
+        .iter()
+        .map(|p| p.queue_count)
+        .collect::<Vec<_>>();
+    let layout = QueueLayout::new(&indices, &queue_counts);
+    info!("queue layout {:?}", layout);
 
-    let queue_infos = unique_indices.iter()
-        .map(|index| {
+    let queue_infos = layout.priorities.iter()
+        .map(|(family, priorities)| {
             vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(*index)
-                .queue_priorities(queue_priorities)
+                .queue_family_index(*family)
+                .queue_priorities(priorities)
         }).collect::<Vec<_>>();
 
-    let layers = if VALIDATION_ENABLED {
+    // Device layers are deprecated, but older implementations still expect
+    // them to match the instance's.
+    let layers = if data.validation.enabled {
         vec![VALIDATION_LAYER.as_ptr()]
     } else {
         vec![]
     };
-    info!(
-        "device layer names {:#?}",
-        vk::ExtensionName::from_ptr(layers[0]).to_string_lossy()
-    );
+    if data.validation.enabled {
+        info!("device layer names {:?}", VALIDATION_LAYER.to_string_lossy());
+    }
 
     let mut extensions = DEVICE_EXTENTIONS
         .iter()
@@ -53,7 +54,12 @@ pub unsafe fn create_logical_device(
         extensions.push(vk::KHR_PORTABILITY_SUBSET_EXTENSION.name.as_ptr());
     }
 
-    let features = vk::PhysicalDeviceFeatures::builder();
+    // Optional features, enabled when the device has them.
+    let supported = instance.get_physical_device_features(data.physical_device);
+    let features = vk::PhysicalDeviceFeatures::builder()
+        .fill_mode_non_solid(supported.fill_mode_non_solid == vk::TRUE)
+        .pipeline_statistics_query(supported.pipeline_statistics_query == vk::TRUE)
+        .occlusion_query_precise(supported.occlusion_query_precise == vk::TRUE);
 
     let device_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
@@ -62,9 +68,14 @@ pub unsafe fn create_logical_device(
         .enabled_features(&features);
 
     let device = instance.create_device(data.physical_device, &device_info, None)?;
+    data.features = features.build();
 
-    data.graphics_queue = device.get_device_queue(indices.graphics, 0);
-    data.present_queue = device.get_device_queue(indices.present, 0);
+    data.graphics_queue = device.get_device_queue(layout.graphics.family, layout.graphics.index);
+    data.present_queue = device.get_device_queue(layout.present.family, layout.present.index);
+    data.transfer_queue = device.get_device_queue(layout.transfer.family, layout.transfer.index);
+    data.compute_queue = device.get_device_queue(layout.compute.family, layout.compute.index);
+    data.queue_families = indices;
+    data.queue_layout = layout;
 
     Ok(device)
 }
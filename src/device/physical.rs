@@ -2,6 +2,7 @@
 use log::*;
 use anyhow::{anyhow, Result};
 use std::collections::HashSet;
+use std::str::FromStr;
 
 use vulkanalia::vk;
 use vulkanalia::prelude::v1_4::*;
@@ -12,6 +13,60 @@ use crate::device::queues::QueueFamilyIndices;
 use crate::swapchain::support::SwapchainSupport;
 use crate::device::DEVICE_EXTENTIONS;
 
+/// Which physical device `pick_physical_device` uses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSelection {
+    /// The first suitable one.
+    #[default]
+    Auto,
+    /// The one at this position in the order devices are logged at startup.
+    Index(usize),
+    /// The first suitable one whose name contains this, ignoring case.
+    Name(String),
+}
+
+impl DeviceSelection {
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            DeviceSelection::Auto => true,
+            DeviceSelection::Index(i) => *i == index,
+            DeviceSelection::Name(n) => name.to_lowercase().contains(&n.to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for DeviceSelection {
+    type Err = anyhow::Error;
+
+    /// Parses `auto`, an index or part of a name.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "" => Err(anyhow!("Empty device name, expected `auto`, an index or part of a name.")),
+            "auto" => Ok(DeviceSelection::Auto),
+            s => Ok(s.parse().map(DeviceSelection::Index).unwrap_or_else(|_| DeviceSelection::Name(s.to_string()))),
+        }
+    }
+}
+
+/// Parses a sample count for MSAA: 1 (off), 2, 4, 8, 16, 32 or 64.
+pub fn parse_msaa_samples(value: &str) -> Result<vk::SampleCountFlags> {
+    match value.trim().parse::<u32>() {
+        Ok(n) if n.is_power_of_two() && n <= 64 => Ok(vk::SampleCountFlags::from_bits_truncate(n)),
+        _ => Err(anyhow!("Invalid MSAA sample count `{}`, expected 1, 2, 4, 8, 16, 32 or 64.", value)),
+    }
+}
+
+/// The most samples up to `requested` that color and depth attachments
+/// support together with `limits`.
+pub fn supported_msaa_samples(limits: &vk::PhysicalDeviceLimits, requested: vk::SampleCountFlags) -> vk::SampleCountFlags {
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    let mut samples = requested.bits().max(1);
+    while samples > 1 && !supported.contains(vk::SampleCountFlags::from_bits_truncate(samples)) {
+        samples /= 2;
+    }
+    vk::SampleCountFlags::from_bits_truncate(samples)
+}
+
 unsafe fn check_physical_device(
     instance: &Instance,
     data: &AppData,
@@ -54,19 +109,64 @@ unsafe fn check_physical_device_extentions(instance: &Instance, physical_device:
 }
 
 
+/// Picks the first suitable physical device that `data.device_selection`
+/// matches, and lowers `data.msaa_samples` to what it supports.
 pub unsafe fn pick_physical_device(instance: &Instance, data: &mut AppData) -> Result<()> {
-    for physical_device in instance.enumerate_physical_devices()? {
+    for (index, physical_device) in instance.enumerate_physical_devices()?.into_iter().enumerate() {
         let properties = instance.get_physical_device_properties(physical_device);
-        info!("inferring Physical device (`{}`)", properties.device_name);
+        info!("inferring Physical device {} (`{}`)", index, properties.device_name);
 
-        if let Err(error) = check_physical_device(instance, data, physical_device) {
+        if !data.device_selection.matches(index, &properties.device_name.to_string()) {
+            info!("Skipping Physical device (`{}`), it wasn't selected.", properties.device_name);
+        } else if let Err(error) = check_physical_device(instance, data, physical_device) {
             warn!("Skipping Physical device (`{}`) {}", properties.device_name, error)
         } else {
             info!("Selected Physical device (`{}`)", properties.device_name);
             data.physical_device = physical_device;
+
+            let samples = supported_msaa_samples(&properties.limits, data.msaa_samples);
+            if samples != data.msaa_samples {
+                warn!("{:?} MSAA isn't supported, using {:?}.", data.msaa_samples, samples);
+            }
+            data.msaa_samples = samples;
             return Ok(());
         }
     }
 
-    Err(anyhow!("Failed to find suitable Physical device"))
+    match &data.device_selection {
+        DeviceSelection::Auto => Err(anyhow!("Failed to find suitable Physical device")),
+        selection => Err(anyhow!("Failed to find suitable Physical device matching {:?}", selection)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_selections_parse() {
+        assert_eq!("auto".parse::<DeviceSelection>().unwrap(), DeviceSelection::Auto);
+        assert_eq!("1".parse::<DeviceSelection>().unwrap(), DeviceSelection::Index(1));
+        let selection = " GeForce ".parse::<DeviceSelection>().unwrap();
+        assert_eq!(selection, DeviceSelection::Name("GeForce".to_string()));
+        assert!(selection.matches(3, "NVIDIA GEFORCE RTX 4070"));
+        assert!(!selection.matches(0, "AMD Radeon"));
+        assert!(DeviceSelection::Index(1).matches(1, "anything"));
+        assert!("".parse::<DeviceSelection>().is_err());
+    }
+
+    #[test]
+    fn msaa_falls_back_to_supported_counts() {
+        assert_eq!(parse_msaa_samples("4").unwrap(), vk::SampleCountFlags::_4);
+        assert!(parse_msaa_samples("3").is_err() && parse_msaa_samples("128").is_err());
+
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::_1 | vk::SampleCountFlags::_2 | vk::SampleCountFlags::_4 | vk::SampleCountFlags::_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::_1 | vk::SampleCountFlags::_2 | vk::SampleCountFlags::_4,
+            ..vk::PhysicalDeviceLimits::default()
+        };
+        assert_eq!(supported_msaa_samples(&limits, vk::SampleCountFlags::_8), vk::SampleCountFlags::_4);
+        assert_eq!(supported_msaa_samples(&limits, vk::SampleCountFlags::_2), vk::SampleCountFlags::_2);
+        assert_eq!(supported_msaa_samples(&limits, vk::SampleCountFlags::empty()), vk::SampleCountFlags::_1);
+    }
 }
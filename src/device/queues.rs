@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use log::*;
 
 use vulkanalia::prelude::v1_4::*;
 use vulkanalia::vk::KhrSurfaceExtension;
@@ -6,9 +7,21 @@ use vulkanalia::vk::KhrSurfaceExtension;
 use crate::app::data::AppData;
 use crate::core::SuitabilityError;
 
+/// Queue priorities for each queue role, used when the role gets its own queue.
+pub const GRAPHICS_QUEUE_PRIORITY: f32 = 1.0;
+pub const PRESENT_QUEUE_PRIORITY: f32 = 1.0;
+pub const COMPUTE_QUEUE_PRIORITY: f32 = 0.75;
+pub const TRANSFER_QUEUE_PRIORITY: f32 = 0.5;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct QueueFamilyIndices {
     pub graphics: u32,
     pub present: u32,
+    /// A transfer-only family if there is one, else a compute-only family,
+    /// else the graphics family.
+    pub transfer: u32,
+    /// A compute-only family if there is one, else the graphics family.
+    pub compute: u32,
 }
 
 impl QueueFamilyIndices {
@@ -23,31 +36,183 @@ impl QueueFamilyIndices {
             .iter()
             .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
             .map(|i| i as u32);
-        println!("queue graphics {}", graphics.unwrap());
-
-        // let mut present = None;
-        // for (index, properties) in properties.iter().enumerate() {
-        //     if instance.get_physical_device_surface_support_khr(physical_device, index as u32, data.surface)? {
-        //         present = Some(index as u32);
-        //         break;
-        //     }
-        // }
-        let present = properties
-            .iter().enumerate()
-            .position(|(index, _property)| {
-                instance.get_physical_device_surface_support_khr(
-                    physical_device,
-                    index as u32,
-                    data.surface,
-                ).unwrap_or(false)
-            }).map(|p| p as u32);
-        println!("queue present {}", present.unwrap());
-
-        // let present:u32 = 1;
+
+        // Prefer presenting from the graphics family so both roles can share a queue.
+        let supports_present = |index: u32| {
+            instance.get_physical_device_surface_support_khr(
+                physical_device,
+                index,
+                data.surface,
+            ).unwrap_or(false)
+        };
+        let present = graphics
+            .filter(|g| supports_present(*g))
+            .or_else(|| (0..properties.len() as u32).find(|i| supports_present(*i)));
+
         if let (Some(graphics), Some(present)) = (graphics, present) {
-            Ok(Self { graphics, present })
+            let compute = find_dedicated_family(
+                &properties,
+                vk::QueueFlags::COMPUTE,
+                vk::QueueFlags::GRAPHICS,
+            ).unwrap_or(graphics);
+
+            // Transfer-only families (DMA engines) first, then async compute
+            // families (which always support transfers), then graphics.
+            let transfer = find_dedicated_family(
+                &properties,
+                vk::QueueFlags::TRANSFER,
+                vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+            )
+            .or_else(|| find_dedicated_family(
+                &properties,
+                vk::QueueFlags::COMPUTE,
+                vk::QueueFlags::GRAPHICS,
+            ))
+            .unwrap_or(graphics);
+
+            debug!(
+                "queue families: graphics {} present {} transfer {} compute {}",
+                graphics, present, transfer, compute,
+            );
+            Ok(Self { graphics, present, transfer, compute })
         } else {
             Err(anyhow!(SuitabilityError("Missing required queue families.")))
         }
     }
 }
+
+/// Finds the first family that supports `required` and none of `excluded`.
+fn find_dedicated_family(
+    properties: &[vk::QueueFamilyProperties],
+    required: vk::QueueFlags,
+    excluded: vk::QueueFlags,
+) -> Option<u32> {
+    properties
+        .iter()
+        .position(|p| p.queue_flags.contains(required) && !p.queue_flags.intersects(excluded))
+        .map(|i| i as u32)
+}
+
+/// A queue within a queue family.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueSlot {
+    pub family: u32,
+    pub index: u32,
+}
+
+/// Which device queue each role uses, and the queues to request per family.
+///
+/// Roles that land in the same family get their own queue when the family
+/// has enough of them, and share the family's last queue otherwise. Present
+/// always shares the graphics queue when the families match.
+#[derive(Clone, Debug, Default)]
+pub struct QueueLayout {
+    pub graphics: QueueSlot,
+    pub present: QueueSlot,
+    pub transfer: QueueSlot,
+    pub compute: QueueSlot,
+    /// Queue priorities to request, keyed by family index.
+    pub priorities: Vec<(u32, Vec<f32>)>,
+}
+
+impl QueueLayout {
+    pub fn new(indices: &QueueFamilyIndices, queue_counts: &[u32]) -> Self {
+        let mut layout = Self::default();
+
+        layout.graphics = layout.allocate(indices.graphics, GRAPHICS_QUEUE_PRIORITY, queue_counts);
+        layout.present = if indices.present == indices.graphics {
+            layout.graphics
+        } else {
+            layout.allocate(indices.present, PRESENT_QUEUE_PRIORITY, queue_counts)
+        };
+        layout.compute = layout.allocate(indices.compute, COMPUTE_QUEUE_PRIORITY, queue_counts);
+        layout.transfer = layout.allocate(indices.transfer, TRANSFER_QUEUE_PRIORITY, queue_counts);
+
+        layout
+    }
+
+    fn allocate(&mut self, family: u32, priority: f32, queue_counts: &[u32]) -> QueueSlot {
+        let available = queue_counts.get(family as usize).copied().unwrap_or(1).max(1);
+
+        let priorities = match self.priorities.iter_mut().find(|(f, _)| *f == family) {
+            Some((_, priorities)) => priorities,
+            None => {
+                self.priorities.push((family, Vec::new()));
+                &mut self.priorities.last_mut().unwrap().1
+            }
+        };
+
+        if (priorities.len() as u32) < available {
+            priorities.push(priority);
+        } else if let Some(last) = priorities.last_mut() {
+            *last = last.max(priority);
+        }
+
+        QueueSlot { family, index: priorities.len() as u32 - 1 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(family: u32, index: u32) -> QueueSlot {
+        QueueSlot { family, index }
+    }
+
+    #[test]
+    fn dedicated_families_get_their_own_queues() {
+        let indices = QueueFamilyIndices { graphics: 0, present: 0, transfer: 2, compute: 1 };
+        let layout = QueueLayout::new(&indices, &[16, 8, 2]);
+
+        assert_eq!(layout.graphics, slot(0, 0));
+        assert_eq!(layout.present, slot(0, 0));
+        assert_eq!(layout.compute, slot(1, 0));
+        assert_eq!(layout.transfer, slot(2, 0));
+        assert_eq!(layout.priorities, [
+            (0, vec![GRAPHICS_QUEUE_PRIORITY]),
+            (1, vec![COMPUTE_QUEUE_PRIORITY]),
+            (2, vec![TRANSFER_QUEUE_PRIORITY]),
+        ]);
+    }
+
+    #[test]
+    fn single_queue_is_shared_by_every_role() {
+        let indices = QueueFamilyIndices::default();
+        let layout = QueueLayout::new(&indices, &[1]);
+
+        for role in [layout.graphics, layout.present, layout.compute, layout.transfer] {
+            assert_eq!(role, slot(0, 0));
+        }
+        assert_eq!(layout.priorities, [(0, vec![GRAPHICS_QUEUE_PRIORITY])]);
+    }
+
+    #[test]
+    fn shared_family_hands_out_queues_until_it_runs_out() {
+        let indices = QueueFamilyIndices::default();
+
+        let layout = QueueLayout::new(&indices, &[4]);
+        assert_eq!(layout.graphics, slot(0, 0));
+        assert_eq!(layout.present, slot(0, 0));
+        assert_eq!(layout.compute, slot(0, 1));
+        assert_eq!(layout.transfer, slot(0, 2));
+        assert_eq!(layout.priorities, [
+            (0, vec![GRAPHICS_QUEUE_PRIORITY, COMPUTE_QUEUE_PRIORITY, TRANSFER_QUEUE_PRIORITY]),
+        ]);
+
+        // Transfer shares the compute queue, which keeps the higher priority.
+        let layout = QueueLayout::new(&indices, &[2]);
+        assert_eq!(layout.compute, slot(0, 1));
+        assert_eq!(layout.transfer, slot(0, 1));
+        assert_eq!(layout.priorities, [(0, vec![GRAPHICS_QUEUE_PRIORITY, COMPUTE_QUEUE_PRIORITY])]);
+    }
+
+    #[test]
+    fn separate_present_family_gets_its_own_queue() {
+        let indices = QueueFamilyIndices { graphics: 0, present: 1, transfer: 0, compute: 0 };
+        let layout = QueueLayout::new(&indices, &[1, 1]);
+        assert_eq!(layout.present, slot(1, 0));
+        assert_eq!(layout.transfer, slot(0, 0));
+        assert_eq!(layout.priorities, [(0, vec![GRAPHICS_QUEUE_PRIORITY]), (1, vec![PRESENT_QUEUE_PRIORITY])]);
+    }
+}
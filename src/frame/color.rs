@@ -0,0 +1,69 @@
+use anyhow::Result;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_name;
+use crate::image::{create_image, create_image_view, create_multisampled_image};
+
+/// The format the scene is rendered in before post-processing maps it to
+/// the swapchain, so colors brighter than 1 survive until tonemapping.
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Creates the HDR color attachment the scene is rendered into, which
+/// post-processing then samples. With MSAA, the scene is rendered into a
+/// multisampled attachment that's resolved into it.
+pub unsafe fn create_color_objects(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData
+) -> Result<()> {
+    let (color_image, color_image_memory) = create_image(
+        instance,
+        device,
+        data,
+        data.swapchain_extent.width,
+        data.swapchain_extent.height,
+        HDR_FORMAT,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+
+    data.color_image = color_image;
+    data.color_image_memory = color_image_memory;
+    data.color_image_view = create_image_view(device, color_image, HDR_FORMAT, vk::ImageAspectFlags::COLOR)?;
+    set_object_name(device, data, data.color_image, "scene color");
+    set_object_name(device, data, data.color_image_view, "scene color view");
+
+    if data.msaa_samples != vk::SampleCountFlags::_1 {
+        let (image, image_memory) = create_multisampled_image(
+            instance,
+            device,
+            data,
+            data.swapchain_extent.width,
+            data.swapchain_extent.height,
+            data.msaa_samples,
+            HDR_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        )?;
+
+        data.msaa_color_image = image;
+        data.msaa_color_image_memory = image_memory;
+        data.msaa_color_image_view = create_image_view(device, image, HDR_FORMAT, vk::ImageAspectFlags::COLOR)?;
+        set_object_name(device, data, data.msaa_color_image, "multisampled scene color");
+        set_object_name(device, data, data.msaa_color_image_view, "multisampled scene color view");
+    }
+
+    Ok(())
+}
+
+pub unsafe fn destroy_color_objects(device: &Device, data: &mut AppData) {
+    device.destroy_image_view(data.color_image_view, None);
+    device.free_memory(data.color_image_memory, None);
+    device.destroy_image(data.color_image, None);
+    device.destroy_image_view(data.msaa_color_image_view, None);
+    device.free_memory(data.msaa_color_image_memory, None);
+    device.destroy_image(data.msaa_color_image, None);
+}
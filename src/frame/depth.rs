@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Result};
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_name;
+use crate::image::{create_image_view, create_multisampled_image, get_supported_format};
+
+pub unsafe fn get_depth_format(instance: &Instance, data: &AppData) -> Result<vk::Format> {
+    let candidates = &[
+        vk::Format::D32_SFLOAT,
+        vk::Format::D32_SFLOAT_S8_UINT,
+        vk::Format::D24_UNORM_S8_UINT,
+    ];
+
+    get_supported_format(
+        instance,
+        data,
+        candidates,
+        vk::ImageTiling::OPTIMAL,
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+    ).ok_or_else(|| anyhow!("Failed to find supported depth format."))
+}
+
+/// Creates the scene's depth attachment, with `msaa_samples` samples.
+pub unsafe fn create_depth_objects(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData
+) -> Result<()> {
+    let format = get_depth_format(instance, data)?;
+
+    let (depth_image, depth_image_memory) = create_multisampled_image(
+        instance,
+        device,
+        data,
+        data.swapchain_extent.width,
+        data.swapchain_extent.height,
+        data.msaa_samples,
+        format,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+    )?;
+
+    data.depth_image = depth_image;
+    data.depth_image_memory = depth_image_memory;
+    data.depth_image_view = create_image_view(device, depth_image, format, vk::ImageAspectFlags::DEPTH)?;
+    set_object_name(device, data, data.depth_image, "depth");
+    set_object_name(device, data, data.depth_image_view, "depth view");
+
+    Ok(())
+}
+
+pub unsafe fn destroy_depth_objects(device: &Device, data: &mut AppData) {
+    device.destroy_image_view(data.depth_image_view, None);
+    device.free_memory(data.depth_image_memory, None);
+    device.destroy_image(data.depth_image, None);
+}
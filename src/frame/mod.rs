@@ -1,30 +1,35 @@
+pub mod color;
+pub mod depth;
+pub mod sync;
+pub mod uniforms;
+
 use anyhow::Result;
 
 use vulkanalia::vk;
 use vulkanalia::prelude::v1_0::*;
 
 use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_name;
 
-pub unsafe fn create_framebuffers(
+/// Creates the framebuffer the scene is rendered into, see `color` and `depth`.
+pub unsafe fn create_framebuffer(
     device: &Device,
     data: &mut AppData
 ) -> Result<()> {
-    data.framebuffers = data
-        .swapchain_image_views
-        .iter()
-        .map(|i| {
-            let attachments = &[*i];
-            let create_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(data.render_pass)
-                .attachments(attachments)
-                .width(data.swapchain_extent.width)
-                .height(data.swapchain_extent.height)
-                .layers(1);
+    let attachments = if data.msaa_samples == vk::SampleCountFlags::_1 {
+        vec![data.color_image_view, data.depth_image_view]
+    } else {
+        vec![data.msaa_color_image_view, data.depth_image_view, data.color_image_view]
+    };
+    let create_info = vk::FramebufferCreateInfo::builder()
+        .render_pass(data.render_pass)
+        .attachments(&attachments)
+        .width(data.swapchain_extent.width)
+        .height(data.swapchain_extent.height)
+        .layers(1);
 
-            device.create_framebuffer(&create_info, None)
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+    data.framebuffer = device.create_framebuffer(&create_info, None)?;
+    set_object_name(device, data, data.framebuffer, "scene framebuffer");
 
     Ok(())
 }
-
@@ -0,0 +1,71 @@
+use anyhow::Result;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_names;
+
+/// How many frames the CPU may record ahead of the GPU.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+pub unsafe fn create_sync_objects(
+    device: &Device,
+    data: &mut AppData
+) -> Result<()> {
+    let semaphore_info = vk::SemaphoreCreateInfo::builder();
+    let fence_info = vk::FenceCreateInfo::builder()
+        .flags(vk::FenceCreateFlags::SIGNALED);
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        data.image_available_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
+        data.in_flight_fences.push(device.create_fence(&fence_info, None)?);
+    }
+
+    set_object_names(device, data, &data.image_available_semaphores, "image available");
+    set_object_names(device, data, &data.in_flight_fences, "in flight");
+
+    create_swapchain_sync_objects(device, data)?;
+
+    Ok(())
+}
+
+/// Creates the sync objects that are tied to swapchain images.
+///
+/// Present waits on `render_finished_semaphores[image_index]`, so there is one
+/// per swapchain image rather than per frame in flight.
+pub unsafe fn create_swapchain_sync_objects(
+    device: &Device,
+    data: &mut AppData
+) -> Result<()> {
+    let semaphore_info = vk::SemaphoreCreateInfo::builder();
+
+    data.render_finished_semaphores = data.swapchain_images
+        .iter()
+        .map(|_| device.create_semaphore(&semaphore_info, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    data.images_in_flight = data.swapchain_images
+        .iter()
+        .map(|_| vk::Fence::null())
+        .collect();
+    set_object_names(device, data, &data.render_finished_semaphores, "render finished");
+
+    Ok(())
+}
+
+pub unsafe fn destroy_swapchain_sync_objects(device: &Device, data: &mut AppData) {
+    data.render_finished_semaphores
+        .drain(..)
+        .for_each(|s| device.destroy_semaphore(s, None));
+    data.images_in_flight.clear();
+}
+
+pub unsafe fn destroy_sync_objects(device: &Device, data: &mut AppData) {
+    destroy_swapchain_sync_objects(device, data);
+    data.image_available_semaphores
+        .drain(..)
+        .for_each(|s| device.destroy_semaphore(s, None));
+    data.in_flight_fences
+        .drain(..)
+        .for_each(|f| device.destroy_fence(f, None));
+}
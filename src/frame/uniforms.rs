@@ -0,0 +1,169 @@
+use anyhow::Result;
+
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::{set_object_name, set_object_names};
+use crate::buffer::create_buffer;
+use crate::camera::Camera;
+use crate::descriptor;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::light::{GpuLight, LightHeader, ShadingModel};
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// Per-frame data shared by every draw, bound as set 0 binding 0 (std140).
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FrameUniforms {
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub view_proj: Mat4,
+    /// World space camera position, `w` is unused.
+    pub camera_position: Vec4,
+}
+
+impl FrameUniforms {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            view: camera.view(),
+            proj: camera.projection(),
+            view_proj: camera.view_projection(),
+            camera_position: camera.eye.to_homogeneous(),
+        }
+    }
+}
+
+fn light_buffer_size(max_lights: u32) -> vk::DeviceSize {
+    (size_of::<LightHeader>() + size_of::<GpuLight>() * max_lights as usize) as vk::DeviceSize
+}
+
+/// Creates one uniform buffer, light buffer and descriptor set per frame in
+/// flight. The set has the `FrameUniforms` at binding 0 and a storage buffer
+/// with a `LightHeader` and up to `max_lights` `GpuLight`s at binding 1.
+pub unsafe fn create_frame_uniforms(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    max_lights: u32,
+) -> Result<()> {
+    let bindings = &[
+        descriptor::uniform_buffer_binding(0, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        descriptor::storage_buffer_binding(1, vk::ShaderStageFlags::FRAGMENT),
+    ];
+    data.frame_set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+
+    let size = |type_| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32)
+            .build()
+    };
+    let sizes = &[size(vk::DescriptorType::UNIFORM_BUFFER), size(vk::DescriptorType::STORAGE_BUFFER)];
+    data.frame_descriptor_pool = descriptor::create_descriptor_pool(device, sizes, MAX_FRAMES_IN_FLIGHT as u32)?;
+    data.max_lights = max_lights;
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            size_of::<FrameUniforms>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        let (light_buffer, light_buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            light_buffer_size(max_lights),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        let set = descriptor::allocate_descriptor_set(device, data.frame_descriptor_pool, data.frame_set_layout)?;
+        descriptor::write_uniform_buffer(device, set, 0, buffer);
+        descriptor::write_storage_buffer(device, set, 1, light_buffer);
+
+        data.frame_uniform_buffers.push(buffer);
+        data.frame_uniform_buffers_memory.push(buffer_memory);
+        data.frame_light_buffers.push(light_buffer);
+        data.frame_light_buffers_memory.push(light_buffer_memory);
+        data.frame_descriptor_sets.push(set);
+    }
+
+    set_object_name(device, data, data.frame_set_layout, "frame set layout");
+    set_object_names(device, data, &data.frame_uniform_buffers, "frame uniforms");
+    set_object_names(device, data, &data.frame_light_buffers, "frame lights");
+    set_object_names(device, data, &data.frame_descriptor_sets, "frame set");
+
+    Ok(())
+}
+
+/// Writes the uniforms for `frame`, whose previous submission must have completed.
+pub unsafe fn update_frame_uniforms(
+    device: &Device,
+    data: &AppData,
+    frame: usize,
+    uniforms: &FrameUniforms,
+) -> Result<()> {
+    let memory = device.map_memory(
+        data.frame_uniform_buffers_memory[frame],
+        0,
+        size_of::<FrameUniforms>() as vk::DeviceSize,
+        vk::MemoryMapFlags::empty(),
+    )?;
+    memcpy(uniforms, memory.cast(), 1);
+    device.unmap_memory(data.frame_uniform_buffers_memory[frame]);
+
+    Ok(())
+}
+
+/// Writes the lights for `frame`, whose previous submission must have
+/// completed. Lights past `data.max_lights` are left out.
+pub unsafe fn update_frame_lights(
+    device: &Device,
+    data: &AppData,
+    frame: usize,
+    shading_model: ShadingModel,
+    lights: &[GpuLight],
+) -> Result<()> {
+    let lights = &lights[..lights.len().min(data.max_lights as usize)];
+    let header = LightHeader::new(lights.len() as u32, shading_model);
+
+    let memory = device.map_memory(
+        data.frame_light_buffers_memory[frame],
+        0,
+        light_buffer_size(lights.len() as u32),
+        vk::MemoryMapFlags::empty(),
+    )?;
+    memcpy(&header, memory.cast(), 1);
+    memcpy(lights.as_ptr(), memory.cast::<u8>().add(size_of::<LightHeader>()).cast(), lights.len());
+    device.unmap_memory(data.frame_light_buffers_memory[frame]);
+
+    Ok(())
+}
+
+pub unsafe fn destroy_frame_uniforms(device: &Device, data: &mut AppData) {
+    data.frame_light_buffers
+        .drain(..)
+        .for_each(|b| device.destroy_buffer(b, None));
+    data.frame_light_buffers_memory
+        .drain(..)
+        .for_each(|m| device.free_memory(m, None));
+    data.frame_uniform_buffers
+        .drain(..)
+        .for_each(|b| device.destroy_buffer(b, None));
+    data.frame_uniform_buffers_memory
+        .drain(..)
+        .for_each(|m| device.free_memory(m, None));
+    data.frame_descriptor_sets.clear();
+    device.destroy_descriptor_pool(data.frame_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.frame_set_layout, None);
+}
@@ -0,0 +1,127 @@
+pub mod renderer;
+
+use std::fmt;
+
+use egui::{ClippedPrimitive, Context, TexturesDelta, ViewportId};
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+/// egui for the app's window. Window events go in through `handle_event`,
+/// and widgets declared with `context` between `begin_frame` and `end_frame`
+/// become meshes for `renderer::update_gui`.
+pub struct Gui {
+    pub context: Context,
+    state: egui_winit::State,
+}
+
+/// What a frame between `Gui::begin_frame` and `Gui::end_frame` draws.
+#[derive(Clone, Debug, Default)]
+pub struct GuiOutput {
+    /// Meshes in points, each clipped to its `clip_rect`.
+    pub primitives: Vec<ClippedPrimitive>,
+    /// Textures to upload before drawing `primitives` and to free after.
+    pub textures: TexturesDelta,
+    pub pixels_per_point: f32,
+}
+
+impl Gui {
+    /// `max_texture_side` is the largest image the device supports, which
+    /// bounds egui's font atlas.
+    pub fn new(window: &Window, max_texture_side: usize) -> Self {
+        let context = Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(max_texture_side),
+        );
+        Self { context, state }
+    }
+
+    /// Feeds a window event to egui, returning whether it was used and
+    /// shouldn't reach the rest of the app, e.g. a click on a window.
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.state.on_window_event(window, event).consumed
+    }
+
+    /// Starts a frame with the events since the last one.
+    pub fn begin_frame(&mut self, window: &Window) {
+        let input = self.state.take_egui_input(window);
+        self.context.begin_frame(input);
+    }
+
+    /// Ends the frame, applies what egui asks of the window (e.g. the
+    /// cursor) and returns what to draw.
+    pub fn end_frame(&mut self, window: &Window) -> GuiOutput {
+        let (output, platform_output) = end_frame(&self.context);
+        self.state.handle_platform_output(window, platform_output);
+        output
+    }
+}
+
+impl fmt::Debug for Gui {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gui").field("context", &self.context).finish_non_exhaustive()
+    }
+}
+
+/// Ends `context`'s frame and tessellates it.
+fn end_frame(context: &Context) -> (GuiOutput, egui::PlatformOutput) {
+    let output = context.end_frame();
+    let primitives = context.tessellate(output.shapes, output.pixels_per_point);
+    let gui = GuiOutput { primitives, textures: output.textures_delta, pixels_per_point: output.pixels_per_point };
+    (gui, output.platform_output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use egui::epaint::Primitive;
+    use egui::{pos2, vec2, RawInput, Rect, TextureId};
+
+    fn input() -> RawInput {
+        RawInput {
+            screen_rect: Some(Rect::from_min_size(pos2(0.0, 0.0), vec2(800.0, 600.0))),
+            ..RawInput::default()
+        }
+    }
+
+    fn frame(context: &Context) -> GuiOutput {
+        context.begin_frame(input());
+        egui::Window::new("Renderer").show(context, |ui| {
+            ui.label("Exposure");
+            let _ = ui.button("Screenshot");
+        });
+        end_frame(context).0
+    }
+
+    #[test]
+    fn first_frame_uploads_the_whole_font_atlas() {
+        let context = Context::default();
+        let output = frame(&context);
+        let (id, delta) = &output.textures.set[0];
+        assert_eq!(*id, TextureId::default());
+        assert_eq!(delta.pos, None);
+
+        let output = frame(&context);
+        assert!(output.textures.set.iter().all(|(_, delta)| delta.pos.is_some()));
+    }
+
+    #[test]
+    fn windows_become_clipped_meshes() {
+        let context = Context::default();
+        // Windows are only measured in their first frame.
+        frame(&context);
+        let output = frame(&context);
+
+        assert!(!output.primitives.is_empty());
+        for primitive in &output.primitives {
+            let Primitive::Mesh(mesh) = &primitive.primitive else { panic!("Unexpected paint callback.") };
+            assert_eq!(mesh.texture_id, TextureId::default());
+            assert!(mesh.indices.iter().all(|i| (*i as usize) < mesh.vertices.len()));
+            assert!(primitive.clip_rect.max.x <= 800.0 && primitive.clip_rect.max.y <= 600.0);
+        }
+    }
+}
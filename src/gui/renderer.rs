@@ -0,0 +1,614 @@
+use anyhow::Result;
+use log::*;
+
+use std::collections::HashMap;
+use std::mem::{offset_of, size_of};
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use egui::epaint::{ImageData, ImageDelta, Primitive, Vertex};
+use egui::{TextureFilter, TextureId, TextureOptions, TextureWrapMode};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::create_buffer;
+use crate::core::debug_utils::{set_object_name, set_object_names};
+use crate::descriptor;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::gui::GuiOutput;
+use crate::pipeline::graphics::{create_graphics_pipeline, BlendMode, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range, PushConstants};
+use crate::post;
+use crate::text::screen_projection;
+use crate::texture::{create_texture, destroy_texture, SamplerInfo, Texture, TextureData};
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// How many vertices and indices a frame's GUI can have. Meshes past these
+/// are dropped.
+pub const MAX_GUI_VERTICES: usize = 65_536;
+pub const MAX_GUI_INDICES: usize = MAX_GUI_VERTICES * 3 / 2;
+
+/// How many textures egui can have at once, its font atlas included.
+pub const MAX_GUI_TEXTURES: usize = 8;
+
+/// Replaced textures keep their sets until no frame draws them, so there are
+/// enough for every texture to be replaced in every frame in flight.
+const GUI_TEXTURE_SETS: usize = MAX_GUI_TEXTURES * (MAX_FRAMES_IN_FLIGHT + 1);
+
+/// Must match `gui.frag`'s push constants.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GuiPushConstants {
+    /// Points to clip space.
+    transform: Mat4,
+    /// See `post::output_params`.
+    output_params: Vec4,
+}
+
+const _: () = assert!(size_of::<GuiPushConstants>() == 80);
+
+// SAFETY: `#[repr(C)]` and `Mat4` and `Vec4` are all `f32`s, so there is no padding.
+unsafe impl PushConstants for GuiPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_bits_truncate(
+        vk::ShaderStageFlags::VERTEX.bits() | vk::ShaderStageFlags::FRAGMENT.bits(),
+    );
+}
+
+/// An egui texture and the descriptor set it is bound to.
+#[derive(Clone, Debug)]
+pub struct GuiTexture {
+    /// Width and height in pixels.
+    pub size: [usize; 2],
+    pub texture: Texture,
+    pub set: vk::DescriptorSet,
+}
+
+/// A patch of a texture, copied from its staging buffer by a frame's
+/// command buffer.
+#[derive(Copy, Clone, Debug)]
+pub struct GuiUpload {
+    pub image: vk::Image,
+    pub region: vk::Rect2D,
+    pub buffer: vk::Buffer,
+    pub buffer_memory: vk::DeviceMemory,
+}
+
+/// An egui mesh in a frame's vertex and index buffers.
+#[derive(Copy, Clone, Debug)]
+pub struct DrawCommand {
+    /// The set of the mesh's texture when the frame was written.
+    pub set: vk::DescriptorSet,
+    /// In points.
+    pub clip: egui::Rect,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// Draws egui's meshes in the post-processor's overlay pass, with each
+/// mesh's clip rectangle as the scissor.
+#[derive(Clone, Debug, Default)]
+pub struct GuiRenderer {
+    pub textures: HashMap<TextureId, GuiTexture>,
+    /// A texture at binding 0 and its sampler at binding 1.
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// Sets no texture is bound to.
+    pub free_sets: Vec<vk::DescriptorSet>,
+    /// Textures replaced or freed while preparing each frame, destroyed the
+    /// next time that frame is prepared, when no frame draws them anymore.
+    pub retired: Vec<Vec<GuiTexture>>,
+    /// The patches each frame's command buffer copies, whose staging buffers
+    /// are destroyed the next time that frame is prepared.
+    pub uploads: Vec<Vec<GuiUpload>>,
+    pub vertex_buffers: Vec<vk::Buffer>,
+    pub vertex_buffers_memory: Vec<vk::DeviceMemory>,
+    pub index_buffers: Vec<vk::Buffer>,
+    pub index_buffers_memory: Vec<vk::DeviceMemory>,
+    /// The commands each frame's buffers hold.
+    pub commands: Vec<Vec<DrawCommand>>,
+    /// The pixels per point each frame's commands were laid out with.
+    pub pixels_per_point: Vec<f32>,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    /// The swapchain format `pipeline` was created for.
+    format: vk::Format,
+    /// Whether dropping meshes was already logged.
+    warned: bool,
+}
+
+/// Creates the GUI renderer. Needs the post-processor. egui's textures are
+/// uploaded by `update_gui` when it asks for them.
+pub unsafe fn create_gui_renderer(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let mut renderer = GuiRenderer::default();
+
+    let stages = vk::ShaderStageFlags::FRAGMENT;
+    let bindings = &[
+        descriptor::sampled_image_binding(0, stages),
+        descriptor::sampler_binding(1, stages),
+    ];
+    renderer.set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+    let size = |type_| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(GUI_TEXTURE_SETS as u32)
+            .build()
+    };
+    let sizes = &[size(vk::DescriptorType::SAMPLED_IMAGE), size(vk::DescriptorType::SAMPLER)];
+    renderer.descriptor_pool = descriptor::create_descriptor_pool(device, sizes, GUI_TEXTURE_SETS as u32)?;
+    for _ in 0..GUI_TEXTURE_SETS {
+        let set = descriptor::allocate_descriptor_set(device, renderer.descriptor_pool, renderer.set_layout)?;
+        renderer.free_sets.push(set);
+    }
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            (MAX_GUI_VERTICES * size_of::<Vertex>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        renderer.vertex_buffers.push(buffer);
+        renderer.vertex_buffers_memory.push(buffer_memory);
+
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            (MAX_GUI_INDICES * size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        renderer.index_buffers.push(buffer);
+        renderer.index_buffers_memory.push(buffer_memory);
+        renderer.commands.push(Vec::new());
+        renderer.pixels_per_point.push(1.0);
+        renderer.retired.push(Vec::new());
+        renderer.uploads.push(Vec::new());
+    }
+
+    let set_layouts = &[renderer.set_layout];
+    let push_constant_ranges = &[push_constant_range::<GuiPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    renderer.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    data.gui = renderer;
+    create_gui_pipeline(device, data)?;
+
+    let renderer = &data.gui;
+    set_object_names(device, data, &renderer.free_sets, "gui texture set");
+    set_object_names(device, data, &renderer.vertex_buffers, "gui vertices");
+    set_object_names(device, data, &renderer.index_buffers, "gui indices");
+    set_object_name(device, data, renderer.pipeline_layout, "gui pipeline layout");
+
+    Ok(())
+}
+
+/// Creates the pipeline for the overlay render pass, unless the swapchain
+/// format is the same as last time. Call after the post-processor's targets
+/// are recreated.
+pub unsafe fn create_gui_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let renderer = &mut data.gui;
+    if renderer.format == data.post.present_format && !renderer.pipeline.is_null() {
+        return Ok(());
+    }
+    device.destroy_pipeline(renderer.pipeline, None);
+
+    let bindings = &[vertex_binding_description()];
+    let attributes = &vertex_attribute_descriptions();
+    let info = GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        cull_mode: vk::CullModeFlags::NONE,
+        blend_mode: BlendMode::Alpha,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/gui_vert.spv")[..],
+            &include_bytes!("../shaders/gui_frag.spv")[..],
+            renderer.pipeline_layout,
+            data.post.overlay_render_pass,
+        )
+    };
+    renderer.pipeline = create_graphics_pipeline(device, &info)?;
+    renderer.format = data.post.present_format;
+
+    set_object_name(device, data, data.gui.pipeline, "gui pipeline");
+
+    Ok(())
+}
+
+fn vertex_binding_description() -> vk::VertexInputBindingDescription {
+    vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(size_of::<Vertex>() as u32)
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .build()
+}
+
+/// Positions and UVs are floats, colors are premultiplied sRGB bytes.
+fn vertex_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    let attribute = |location: u32, format, offset: usize| {
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(location)
+            .format(format)
+            .offset(offset as u32)
+            .build()
+    };
+    [
+        attribute(0, vk::Format::R32G32_SFLOAT, offset_of!(Vertex, pos)),
+        attribute(1, vk::Format::R32G32_SFLOAT, offset_of!(Vertex, uv)),
+        attribute(2, vk::Format::R8G8B8A8_UNORM, offset_of!(Vertex, color)),
+    ]
+}
+
+/// Uploads `output`'s textures and writes its meshes for `frame`, whose
+/// previous submission must have completed.
+///
+/// Patches to existing textures are only staged here and copied by
+/// `cmd_upload_gui_textures` in the frame's command buffer. Replaced and freed
+/// textures are destroyed `MAX_FRAMES_IN_FLIGHT` frames later.
+pub unsafe fn update_gui(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    frame: usize,
+    output: &GuiOutput,
+) -> Result<()> {
+    let renderer = &mut data.gui;
+    for mut texture in std::mem::take(&mut renderer.retired[frame]) {
+        destroy_texture(device, &mut texture.texture);
+        renderer.free_sets.push(texture.set);
+    }
+    for upload in std::mem::take(&mut renderer.uploads[frame]) {
+        device.destroy_buffer(upload.buffer, None);
+        device.free_memory(upload.buffer_memory, None);
+    }
+
+    for (id, delta) in &output.textures.set {
+        update_gui_texture(instance, device, data, frame, *id, delta)?;
+    }
+    let renderer = &mut data.gui;
+    let mut vertices = Vec::<Vertex>::new();
+    let mut indices = Vec::<u32>::new();
+    let mut commands = Vec::new();
+    for primitive in &output.primitives {
+        let Primitive::Mesh(mesh) = &primitive.primitive else {
+            continue;
+        };
+        let Some(texture) = renderer.textures.get(&mesh.texture_id) else {
+            continue;
+        };
+        if vertices.len() + mesh.vertices.len() > MAX_GUI_VERTICES
+            || indices.len() + mesh.indices.len() > MAX_GUI_INDICES
+        {
+            if !renderer.warned {
+                warn!("The GUI is too big to draw, dropping some of it.");
+                renderer.warned = true;
+            }
+            continue;
+        }
+        commands.push(DrawCommand {
+            set: texture.set,
+            clip: primitive.clip_rect,
+            first_index: indices.len() as u32,
+            index_count: mesh.indices.len() as u32,
+            vertex_offset: vertices.len() as i32,
+        });
+        vertices.extend_from_slice(&mesh.vertices);
+        indices.extend_from_slice(&mesh.indices);
+    }
+
+    renderer.commands[frame] = commands;
+    renderer.pixels_per_point[frame] = output.pixels_per_point;
+
+    // This frame still draws them.
+    for id in &output.textures.free {
+        if let Some(texture) = renderer.textures.remove(id) {
+            renderer.retired[frame].push(texture);
+        }
+    }
+    if renderer.commands[frame].is_empty() {
+        return Ok(());
+    }
+
+    for (memory, source, size) in [
+        (renderer.vertex_buffers_memory[frame], vertices.as_ptr().cast::<u8>(), size_of_val(vertices.as_slice())),
+        (renderer.index_buffers_memory[frame], indices.as_ptr().cast::<u8>(), size_of_val(indices.as_slice())),
+    ] {
+        let mapped = device.map_memory(memory, 0, size as vk::DeviceSize, vk::MemoryMapFlags::empty())?;
+        memcpy(source, mapped.cast(), size);
+        device.unmap_memory(memory);
+    }
+
+    Ok(())
+}
+
+/// Creates texture `id` from `delta`, or stages a patch of it for `frame`.
+unsafe fn update_gui_texture(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    frame: usize,
+    id: TextureId,
+    delta: &ImageDelta,
+) -> Result<()> {
+    let size = delta.image.size();
+    let pixels = image_bytes(&delta.image);
+
+    if let Some(pos) = delta.pos {
+        let Some(texture) = data.gui.textures.get(&id) else {
+            warn!("Ignoring an update of GUI texture {:?}, which doesn't exist.", id);
+            return Ok(());
+        };
+        let Some(region) = patch_region(texture.size, pos, size) else {
+            warn!("Ignoring an update of GUI texture {:?} outside of it.", id);
+            return Ok(());
+        };
+        let image = texture.texture.image;
+
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        let mapped = device.map_memory(buffer_memory, 0, pixels.len() as vk::DeviceSize, vk::MemoryMapFlags::empty())?;
+        memcpy(pixels.as_ptr(), mapped.cast(), pixels.len());
+        device.unmap_memory(buffer_memory);
+
+        data.gui.uploads[frame].push(GuiUpload { image, region, buffer, buffer_memory });
+        return Ok(());
+    }
+
+    // Earlier frames may still draw with the old texture and its set, so the
+    // new one gets a set of its own.
+    let Some(set) = data.gui.free_sets.pop() else {
+        warn!("Ignoring GUI texture {:?}, only {} can exist at once.", id, MAX_GUI_TEXTURES);
+        return Ok(());
+    };
+    let texture_data = TextureData {
+        width: size[0] as u32,
+        height: size[1] as u32,
+        pixels,
+        sampler: sampler_info(&delta.options),
+    };
+    let texture = create_texture(instance, device, data, &texture_data, vk::Format::R8G8B8A8_UNORM)?;
+    descriptor::write_sampled_image(device, set, 0, texture.image_view);
+    descriptor::write_sampler(device, set, 1, texture.sampler);
+    set_object_name(device, data, texture.image, &format!("gui texture {:?}", id));
+
+    let renderer = &mut data.gui;
+    if let Some(old) = renderer.textures.insert(id, GuiTexture { size, texture, set }) {
+        renderer.retired[frame].push(old);
+    }
+
+    Ok(())
+}
+
+/// egui's pixels as premultiplied sRGB bytes.
+fn image_bytes(image: &ImageData) -> Vec<u8> {
+    match image {
+        ImageData::Color(image) => image.pixels.iter().flat_map(|p| p.to_array()).collect(),
+        ImageData::Font(image) => image.srgba_pixels(None).flat_map(|p| p.to_array()).collect(),
+    }
+}
+
+/// The pixels a `patch_size` patch at `pos` covers in a `size` image, unless
+/// it doesn't fit.
+fn patch_region(size: [usize; 2], pos: [usize; 2], patch_size: [usize; 2]) -> Option<vk::Rect2D> {
+    if pos[0] + patch_size[0] > size[0] || pos[1] + patch_size[1] > size[1] {
+        return None;
+    }
+    Some(vk::Rect2D {
+        offset: vk::Offset2D { x: pos[0] as i32, y: pos[1] as i32 },
+        extent: vk::Extent2D { width: patch_size[0] as u32, height: patch_size[1] as u32 },
+    })
+}
+
+/// Records copying the patches staged for `frame` into their textures. Must
+/// be recorded outside of a render pass, before `cmd_draw_gui`.
+pub unsafe fn cmd_upload_gui_textures(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &GuiRenderer,
+    frame: usize,
+) {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+        .build();
+    let barrier = |image, src_access, dst_access, old_layout, new_layout| {
+        vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range)
+    };
+    let transfer_dst = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+    let shader_read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+    for upload in &renderer.uploads[frame] {
+        // Earlier frames' fragment shaders must be done reading the image.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier(upload.image, vk::AccessFlags::empty(), vk::AccessFlags::TRANSFER_WRITE, shader_read, transfer_dst)],
+        );
+
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+        let vk::Rect2D { offset, extent } = upload.region;
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_offset(vk::Offset3D { x: offset.x, y: offset.y, z: 0 })
+            .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+        device.cmd_copy_buffer_to_image(command_buffer, upload.buffer, upload.image, transfer_dst, &[region]);
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier(upload.image, vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ, transfer_dst, shader_read)],
+        );
+    }
+}
+
+/// egui textures have no mipmaps.
+fn sampler_info(options: &TextureOptions) -> SamplerInfo {
+    let filter = |filter| match filter {
+        TextureFilter::Nearest => vk::Filter::NEAREST,
+        TextureFilter::Linear => vk::Filter::LINEAR,
+    };
+    let address_mode = match options.wrap_mode {
+        TextureWrapMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        TextureWrapMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        TextureWrapMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+    };
+    SamplerInfo {
+        mag_filter: filter(options.magnification),
+        min_filter: filter(options.minification),
+        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+    }
+}
+
+/// Records drawing the GUI written for `frame`, encoded for the swapchain
+/// like `post`'s output. Must be recorded in the overlay render pass, see
+/// `post::processor::cmd_begin_overlay`. Leaves the scissor covering `extent`.
+pub unsafe fn cmd_draw_gui(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &GuiRenderer,
+    frame: usize,
+    post: &post::processor::PostProcessor,
+    extent: vk::Extent2D,
+) {
+    let commands = &renderer.commands[frame];
+    if commands.is_empty() {
+        return;
+    }
+
+    let pixels_per_point = renderer.pixels_per_point[frame];
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, renderer.pipeline);
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[renderer.vertex_buffers[frame]], &[0]);
+    device.cmd_bind_index_buffer(command_buffer, renderer.index_buffers[frame], 0, vk::IndexType::UINT32);
+    let push_constants = GuiPushConstants {
+        transform: screen_projection(extent) * Mat4::from_scale(pixels_per_point),
+        output_params: post::output_params(post.output_encoding, post.paper_white_nits),
+    };
+    cmd_push_constants(device, command_buffer, renderer.pipeline_layout, &push_constants);
+
+    let mut bound = None;
+    for command in commands {
+        // Whole pixels inside the target; scissors can't be negative.
+        let clip = command.clip;
+        let min_x = (clip.min.x * pixels_per_point).max(0.0).round() as u32;
+        let min_y = (clip.min.y * pixels_per_point).max(0.0).round() as u32;
+        let max_x = ((clip.max.x * pixels_per_point).max(0.0).round() as u32).min(extent.width);
+        let max_y = ((clip.max.y * pixels_per_point).max(0.0).round() as u32).min(extent.height);
+        if max_x <= min_x || max_y <= min_y {
+            continue;
+        }
+
+        if bound != Some(command.set) {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                renderer.pipeline_layout,
+                0,
+                &[command.set],
+                &[],
+            );
+            bound = Some(command.set);
+        }
+        let scissor = vk::Rect2D::builder()
+            .offset(vk::Offset2D { x: min_x as i32, y: min_y as i32 })
+            .extent(vk::Extent2D { width: max_x - min_x, height: max_y - min_y });
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+        device.cmd_draw_indexed(command_buffer, command.index_count, 1, command.first_index, command.vertex_offset, 0);
+    }
+
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D::default())
+        .extent(extent);
+    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+}
+
+pub unsafe fn destroy_gui_renderer(device: &Device, renderer: &mut GuiRenderer) {
+    device.destroy_pipeline(renderer.pipeline, None);
+    device.destroy_pipeline_layout(renderer.pipeline_layout, None);
+    renderer.vertex_buffers.iter().chain(&renderer.index_buffers).for_each(|b| device.destroy_buffer(*b, None));
+    renderer.vertex_buffers_memory
+        .iter()
+        .chain(&renderer.index_buffers_memory)
+        .for_each(|m| device.free_memory(*m, None));
+    renderer.textures.values_mut().for_each(|t| destroy_texture(device, &mut t.texture));
+    renderer.retired.iter_mut().flatten().for_each(|t| destroy_texture(device, &mut t.texture));
+    for upload in renderer.uploads.iter().flatten() {
+        device.destroy_buffer(upload.buffer, None);
+        device.free_memory(upload.buffer_memory, None);
+    }
+    device.destroy_descriptor_pool(renderer.descriptor_pool, None);
+    device.destroy_descriptor_set_layout(renderer.set_layout, None);
+    *renderer = GuiRenderer::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use egui::epaint::FontImage;
+
+    #[test]
+    fn vertices_match_the_attributes() {
+        assert_eq!(vertex_binding_description().stride, 20);
+        let offsets = vertex_attribute_descriptions().map(|a| a.offset);
+        assert_eq!(offsets, [0, 8, 16]);
+    }
+
+    #[test]
+    fn patches_must_fit_in_the_texture() {
+        let region = patch_region([4, 3], [1, 1], [2, 2]).unwrap();
+        assert_eq!((region.offset.x, region.offset.y), (1, 1));
+        assert_eq!((region.extent.width, region.extent.height), (2, 2));
+
+        assert!(patch_region([4, 3], [2, 2], [2, 2]).is_none());
+        assert!(patch_region([4, 3], [3, 0], [2, 1]).is_none());
+    }
+
+    #[test]
+    fn font_images_become_premultiplied_srgba() {
+        let font = FontImage { size: [2, 1], pixels: vec![0.0, 1.0] };
+        let bytes = image_bytes(&ImageData::Font(font));
+        assert_eq!(bytes, [0, 0, 0, 0, 255, 255, 255, 255]);
+    }
+}
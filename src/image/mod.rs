@@ -0,0 +1,368 @@
+use anyhow::Result;
+
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::{create_buffer, get_memory_type_index, UploadTarget};
+use crate::command::single_time::{begin_single_time_commands, end_single_time_commands};
+
+
+pub unsafe fn create_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    width: u32,
+    height: u32,
+    format: vk::Format,
+    tiling: vk::ImageTiling,
+    usage: vk::ImageUsageFlags,
+    properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let flags = vk::ImageCreateFlags::empty();
+    create_layered_image(instance, device, data, width, height, 1, flags, format, tiling, usage, properties)
+}
+
+/// Like `create_image` but with `layers` array layers, e.g. 6 with
+/// `CUBE_COMPATIBLE` for a cubemap.
+pub unsafe fn create_layered_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    width: u32,
+    height: u32,
+    layers: u32,
+    flags: vk::ImageCreateFlags,
+    format: vk::Format,
+    tiling: vk::ImageTiling,
+    usage: vk::ImageUsageFlags,
+    properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let info = vk::ImageCreateInfo::builder()
+        .flags(flags)
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D { width, height, depth: 1 })
+        .mip_levels(1)
+        .array_layers(layers)
+        .format(format)
+        .tiling(tiling)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .samples(vk::SampleCountFlags::_1);
+    allocate_image(instance, device, data, &info, properties)
+}
+
+/// Creates a device local 2D image with `samples` samples per pixel, e.g. a
+/// multisampled attachment.
+pub unsafe fn create_multisampled_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    width: u32,
+    height: u32,
+    samples: vk::SampleCountFlags,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D { width, height, depth: 1 })
+        .mip_levels(1)
+        .array_layers(1)
+        .format(format)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .samples(samples);
+    allocate_image(instance, device, data, &info, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+}
+
+unsafe fn allocate_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    info: &vk::ImageCreateInfo,
+    properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let image = device.create_image(info, None)?;
+
+    let requirements = device.get_image_memory_requirements(image);
+    let info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(get_memory_type_index(instance, data, properties, requirements)?);
+    let image_memory = device.allocate_memory(&info, None)?;
+
+    device.bind_image_memory(image, image_memory, 0)?;
+
+    Ok((image, image_memory))
+}
+
+/// Creates a device local, sampled 2D image filled with `bytes` (tightly
+/// packed rows) and leaves it in `SHADER_READ_ONLY_OPTIMAL`.
+///
+/// Like `create_device_local_buffer`, the copy runs on the transfer queue and
+/// the image is handed over to `target`.
+pub unsafe fn create_device_local_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    width: u32,
+    height: u32,
+    format: vk::Format,
+    bytes: &[u8],
+    target: UploadTarget,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let flags = vk::ImageCreateFlags::empty();
+    create_device_local_layered_image(instance, device, data, width, height, 1, flags, format, bytes, target)
+}
+
+/// Like `create_device_local_image` but with `layers` array layers, whose
+/// pixels follow each other in `bytes`.
+pub unsafe fn create_device_local_layered_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    width: u32,
+    height: u32,
+    layers: u32,
+    flags: vk::ImageCreateFlags,
+    format: vk::Format,
+    bytes: &[u8],
+    target: UploadTarget,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let size = bytes.len() as vk::DeviceSize;
+
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+
+    let memory = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(bytes.as_ptr(), memory.cast(), bytes.len());
+    device.unmap_memory(staging_buffer_memory);
+
+    let (image, image_memory) = create_layered_image(
+        instance,
+        device,
+        data,
+        width,
+        height,
+        layers,
+        flags,
+        format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+
+    copy_buffer_to_image(device, data, staging_buffer, image, width, height, layers, target)?;
+
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+
+    Ok((image, image_memory))
+}
+
+/// Copies a buffer into every layer of a color image on the transfer queue,
+/// transitioning it from `UNDEFINED` to `SHADER_READ_ONLY_OPTIMAL`, and hands
+/// `image` over to `target`.
+pub unsafe fn copy_buffer_to_image(
+    device: &Device,
+    data: &AppData,
+    source: vk::Buffer,
+    image: vk::Image,
+    width: u32,
+    height: u32,
+    layers: u32,
+    target: UploadTarget,
+) -> Result<()> {
+    let transfer_family = data.queue_families.transfer;
+    let ownership_transfer = transfer_family != target.queue_family;
+
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(layers)
+        .build();
+    let barrier = |src_access, dst_access, old_layout, new_layout, transfer: bool| {
+        vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(if transfer { transfer_family } else { vk::QUEUE_FAMILY_IGNORED })
+            .dst_queue_family_index(if transfer { target.queue_family } else { vk::QUEUE_FAMILY_IGNORED })
+            .image(image)
+            .subresource_range(subresource_range)
+    };
+
+    let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TOP_OF_PIPE,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            false,
+        )],
+    );
+
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(layers);
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D { width, height, depth: 1 });
+    device.cmd_copy_buffer_to_image(
+        command_buffer,
+        source,
+        image,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        &[region],
+    );
+
+    let transfer_dst = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+    let shader_read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+    if !ownership_transfer {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            target.stage,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier(vk::AccessFlags::TRANSFER_WRITE, target.access, transfer_dst, shader_read, false)],
+        );
+        return end_single_time_commands(
+            device,
+            data.transfer_command_pool,
+            data.transfer_queue,
+            command_buffer,
+            None,
+            None,
+        );
+    }
+
+    // Release on the transfer queue. Both halves of the transfer perform the
+    // same layout transition, which happens once, between them.
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty(), transfer_dst, shader_read, true)],
+    );
+
+    let semaphore = device.create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)?;
+    end_single_time_commands(
+        device,
+        data.transfer_command_pool,
+        data.transfer_queue,
+        command_buffer,
+        None,
+        Some(semaphore),
+    )?;
+
+    // Acquire on the target queue.
+    let command_buffer = begin_single_time_commands(device, target.pool)?;
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TOP_OF_PIPE,
+        target.stage,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(vk::AccessFlags::empty(), target.access, transfer_dst, shader_read, true)],
+    );
+    end_single_time_commands(
+        device,
+        target.pool,
+        target.queue,
+        command_buffer,
+        Some((semaphore, target.stage)),
+        None,
+    )?;
+
+    device.destroy_semaphore(semaphore, None);
+
+    Ok(())
+}
+
+pub unsafe fn create_image_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspects: vk::ImageAspectFlags,
+) -> Result<vk::ImageView> {
+    create_layer_view(device, image, format, aspects, vk::ImageViewType::_2D, 0, 1)
+}
+
+/// A view of `layer_count` array layers of `image` starting at `base_layer`.
+pub unsafe fn create_layer_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspects: vk::ImageAspectFlags,
+    view_type: vk::ImageViewType,
+    base_layer: u32,
+    layer_count: u32,
+) -> Result<vk::ImageView> {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(aspects)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(base_layer)
+        .layer_count(layer_count);
+
+    let info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(view_type)
+        .format(format)
+        .subresource_range(subresource_range);
+
+    Ok(device.create_image_view(&info, None)?)
+}
+
+/// Returns the first of `candidates` that supports `features` with `tiling`.
+pub unsafe fn get_supported_format(
+    instance: &Instance,
+    data: &AppData,
+    candidates: &[vk::Format],
+    tiling: vk::ImageTiling,
+    features: vk::FormatFeatureFlags,
+) -> Option<vk::Format> {
+    candidates.iter().cloned().find(|f| {
+        let properties = instance.get_physical_device_format_properties(data.physical_device, *f);
+        match tiling {
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features.contains(features),
+            vk::ImageTiling::OPTIMAL => properties.optimal_tiling_features.contains(features),
+            _ => false,
+        }
+    })
+}
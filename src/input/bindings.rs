@@ -0,0 +1,331 @@
+use anyhow::{anyhow, Context, Result};
+use log::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+/// Bindings are read from this file in the working directory if it exists.
+pub const DEFAULT_BINDINGS_PATH: &str = "bindings.txt";
+
+/// Something the user can do, independent of which key does it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    /// Held to rotate the orbit camera with the mouse.
+    Orbit,
+    /// Held to look around with the fly camera.
+    Look,
+    ToggleCameraMode,
+    ToggleWireframe,
+    ToggleShadingModel,
+    /// Switches between tonemapping operators and no tonemapping.
+    CycleTonemapper,
+    ToggleFxaa,
+    ExposureUp,
+    ExposureDown,
+    /// Switches to the next `PresentPolicy`.
+    CyclePresentMode,
+    /// Logs the average GPU timings and saves recent frames as a trace.
+    SaveGpuTrace,
+    /// Skips objects that were hidden behind others, see `OcclusionQueries`.
+    ToggleOcclusionCulling,
+    /// Shows lights and shadow map frustums with `DebugDraw`.
+    ToggleGizmos,
+    /// Shows frame and GPU timings over the scene.
+    ToggleStats,
+    /// Shows the settings GUI.
+    ToggleGui,
+    Screenshot,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Orbit,
+        Action::Look,
+        Action::ToggleCameraMode,
+        Action::ToggleWireframe,
+        Action::ToggleShadingModel,
+        Action::CycleTonemapper,
+        Action::ToggleFxaa,
+        Action::ExposureUp,
+        Action::ExposureDown,
+        Action::CyclePresentMode,
+        Action::SaveGpuTrace,
+        Action::ToggleOcclusionCulling,
+        Action::ToggleGizmos,
+        Action::ToggleStats,
+        Action::ToggleGui,
+        Action::Screenshot,
+    ];
+
+    /// The name used in binding files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::Orbit => "orbit",
+            Action::Look => "look",
+            Action::ToggleCameraMode => "toggle_camera_mode",
+            Action::ToggleWireframe => "toggle_wireframe",
+            Action::ToggleShadingModel => "toggle_shading_model",
+            Action::CycleTonemapper => "cycle_tonemapper",
+            Action::ToggleFxaa => "toggle_fxaa",
+            Action::ExposureUp => "exposure_up",
+            Action::ExposureDown => "exposure_down",
+            Action::CyclePresentMode => "cycle_present_mode",
+            Action::SaveGpuTrace => "save_gpu_trace",
+            Action::ToggleOcclusionCulling => "toggle_occlusion_culling",
+            Action::ToggleGizmos => "toggle_gizmos",
+            Action::ToggleStats => "toggle_stats",
+            Action::ToggleGui => "toggle_gui",
+            Action::Screenshot => "screenshot",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|a| a.name() == s)
+            .ok_or_else(|| anyhow!("Unknown action `{}`.", s))
+    }
+}
+
+/// A key (by physical location) or mouse button.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// Keys that can be named in binding files, by their `KeyCode` variant name.
+const KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE,
+    KeyCode::KeyF, KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ,
+    KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO,
+    KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT,
+    KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Tab, KeyCode::Enter, KeyCode::Escape, KeyCode::Backspace,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::PageUp, KeyCode::PageDown, KeyCode::Home, KeyCode::End,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::PrintScreen,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight,
+    KeyCode::Semicolon, KeyCode::Quote, KeyCode::Backquote, KeyCode::Backslash,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+];
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    /// Parses `KeyCode` variant names (e.g. `KeyW`, `Space`) and `MouseLeft`,
+    /// `MouseRight`, `MouseMiddle`, `MouseBack` and `MouseForward`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mouse = match s {
+            "MouseLeft" => Some(MouseButton::Left),
+            "MouseRight" => Some(MouseButton::Right),
+            "MouseMiddle" => Some(MouseButton::Middle),
+            "MouseBack" => Some(MouseButton::Back),
+            "MouseForward" => Some(MouseButton::Forward),
+            _ => None,
+        };
+        if let Some(button) = mouse {
+            return Ok(Binding::Mouse(button));
+        }
+
+        KEYS.iter()
+            .find(|k| format!("{:?}", k) == s)
+            .map(|k| Binding::Key(*k))
+            .ok_or_else(|| anyhow!("Unknown key or mouse button `{}`.", s))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Mouse(button) => write!(f, "Mouse{:?}", button),
+        }
+    }
+}
+
+/// Which keys and buttons trigger each action.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    actions: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::{Key, Mouse};
+
+        let actions = HashMap::from([
+            (Action::MoveForward, vec![Key(KeyCode::KeyW), Key(KeyCode::ArrowUp)]),
+            (Action::MoveBackward, vec![Key(KeyCode::KeyS), Key(KeyCode::ArrowDown)]),
+            (Action::MoveLeft, vec![Key(KeyCode::KeyA), Key(KeyCode::ArrowLeft)]),
+            (Action::MoveRight, vec![Key(KeyCode::KeyD), Key(KeyCode::ArrowRight)]),
+            (Action::MoveUp, vec![Key(KeyCode::Space)]),
+            (Action::MoveDown, vec![Key(KeyCode::ShiftLeft)]),
+            (Action::Orbit, vec![Mouse(MouseButton::Left)]),
+            (Action::Look, vec![Mouse(MouseButton::Right)]),
+            (Action::ToggleCameraMode, vec![Key(KeyCode::Tab)]),
+            (Action::ToggleWireframe, vec![Key(KeyCode::F3)]),
+            (Action::ToggleShadingModel, vec![Key(KeyCode::F4)]),
+            (Action::CycleTonemapper, vec![Key(KeyCode::F5)]),
+            (Action::ToggleFxaa, vec![Key(KeyCode::F6)]),
+            (Action::ExposureUp, vec![Key(KeyCode::Equal)]),
+            (Action::ExposureDown, vec![Key(KeyCode::Minus)]),
+            (Action::CyclePresentMode, vec![Key(KeyCode::F7)]),
+            (Action::SaveGpuTrace, vec![Key(KeyCode::F8)]),
+            (Action::ToggleOcclusionCulling, vec![Key(KeyCode::F9)]),
+            (Action::ToggleGizmos, vec![Key(KeyCode::F10)]),
+            (Action::ToggleStats, vec![Key(KeyCode::F11)]),
+            (Action::ToggleGui, vec![Key(KeyCode::F1)]),
+            (Action::Screenshot, vec![Key(KeyCode::F12)]),
+        ]);
+        Self { actions }
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn set(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.actions.insert(action, bindings);
+    }
+
+    /// Parses a binding table, one `action = Binding, Binding` per line.
+    ///
+    /// Actions that are not listed keep their default bindings and an action
+    /// with nothing after the `=` is unbound. `#` starts a comment.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut bindings = Self::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (action, keys) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Line {}: expected `action = Binding, ...`.", number + 1))?;
+            let action = action.trim().parse::<Action>()
+                .with_context(|| format!("Line {}", number + 1))?;
+            let keys = keys
+                .split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(Binding::from_str)
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Line {}", number + 1))?;
+
+            bindings.set(action, keys);
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read bindings from `{}`.", path.display()))?;
+        Self::parse(&source)
+            .with_context(|| format!("Invalid bindings in `{}`.", path.display()))
+    }
+
+    /// Loads `path` if it exists, otherwise uses the default bindings.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            info!("Loading input bindings from `{}`.", path.display());
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Binding::{Key, Mouse};
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let bindings = Bindings::parse("# Movement\n\n   \nmove_forward = KeyI, ArrowUp # and arrows\n").unwrap();
+        assert_eq!(bindings.get(Action::MoveForward), [Key(KeyCode::KeyI), Key(KeyCode::ArrowUp)]);
+    }
+
+    #[test]
+    fn empty_right_hand_side_unbinds() {
+        let bindings = Bindings::parse("screenshot =\nlook = MouseMiddle").unwrap();
+        assert!(bindings.get(Action::Screenshot).is_empty());
+        assert_eq!(bindings.get(Action::Look), [Mouse(MouseButton::Middle)]);
+    }
+
+    #[test]
+    fn unlisted_actions_keep_their_defaults() {
+        let bindings = Bindings::parse("toggle_gui = F2").unwrap();
+        let defaults = Bindings::default();
+        for action in Action::ALL.iter().copied().filter(|a| *a != Action::ToggleGui) {
+            assert_eq!(bindings.get(action), defaults.get(action), "{:?}", action);
+        }
+        assert_eq!(bindings.get(Action::ToggleGui), [Key(KeyCode::F2)]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = format!("{:#}", Bindings::parse("# comment\nmove_forward = KeyW\njump = Space").unwrap_err());
+        assert!(error.contains("Line 3") && error.contains("Unknown action `jump`"), "{}", error);
+
+        let error = format!("{:#}", Bindings::parse("\nlook = KeyW, Mouse4").unwrap_err());
+        assert!(error.contains("Line 2") && error.contains("`Mouse4`"), "{}", error);
+
+        let error = format!("{:#}", Bindings::parse("screenshot").unwrap_err());
+        assert!(error.contains("Line 1"), "{}", error);
+    }
+
+    #[test]
+    fn binding_names_round_trip() {
+        for binding in [Key(KeyCode::KeyW), Key(KeyCode::F12), Mouse(MouseButton::Forward)] {
+            assert_eq!(binding.to_string().parse::<Binding>().unwrap(), binding);
+        }
+        for action in Action::ALL {
+            assert_eq!(action.name().parse::<Action>().unwrap(), *action);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+pub mod bindings;
+
+use std::collections::HashSet;
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::input::bindings::{Action, Binding, Bindings};
+
+/// Pixels of scrolling that count as one line for `MouseScrollDelta::PixelDelta`.
+const PIXELS_PER_LINE: f32 = 40.0;
+
+/// Pressed, held and released sets for one kind of button.
+#[derive(Clone, Debug)]
+struct ButtonState<T> {
+    held: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+}
+
+impl<T> Default for ButtonState<T> {
+    fn default() -> Self {
+        Self { held: HashSet::new(), pressed: HashSet::new(), released: HashSet::new() }
+    }
+}
+
+impl<T: Copy + Eq + std::hash::Hash> ButtonState<T> {
+    fn press(&mut self, button: T) {
+        if self.held.insert(button) {
+            self.pressed.insert(button);
+        }
+    }
+
+    fn release(&mut self, button: T) {
+        if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+/// Keyboard and mouse state for the current frame.
+///
+/// Events are fed in as they arrive; `end_frame` must be called once every
+/// frame after the state has been read so "pressed" and "released" only last
+/// for the frame they happened in.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    keys: ButtonState<KeyCode>,
+    mouse_buttons: ButtonState<MouseButton>,
+    cursor_position: (f64, f64),
+    mouse_delta: (f64, f64),
+    scroll: f32,
+}
+
+impl InputState {
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => self.keys.press(code),
+                        ElementState::Released => self.keys.release(code),
+                    }
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => self.mouse_buttons.press(*button),
+                ElementState::Released => self.mouse_buttons.release(*button),
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = (position.x, position.y);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / PIXELS_PER_LINE,
+                };
+            }
+            // Buttons released while unfocused never send a release event.
+            WindowEvent::Focused(false) => {
+                self.keys.release_all();
+                self.mouse_buttons.release_all();
+            }
+            _ => {}
+        }
+    }
+
+    /// Raw mouse motion, from `DeviceEvent::MouseMotion`.
+    pub fn handle_mouse_motion(&mut self, (dx, dy): (f64, f64)) {
+        self.mouse_delta.0 += dx;
+        self.mouse_delta.1 += dy;
+    }
+
+    pub fn end_frame(&mut self) {
+        self.keys.end_frame();
+        self.mouse_buttons.end_frame();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll = 0.0;
+    }
+
+    pub fn key_held(&self, key: KeyCode) -> bool {
+        self.keys.held.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: KeyCode) -> bool {
+        self.keys.pressed.contains(&key)
+    }
+
+    pub fn key_released(&self, key: KeyCode) -> bool {
+        self.keys.released.contains(&key)
+    }
+
+    pub fn mouse_held(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.held.contains(&button)
+    }
+
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed.contains(&button)
+    }
+
+    pub fn mouse_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.released.contains(&button)
+    }
+
+    /// Cursor position in physical pixels from the top left of the window.
+    pub fn cursor_position(&self) -> (f64, f64) {
+        self.cursor_position
+    }
+
+    /// Mouse movement this frame, in unscaled device units.
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    /// Lines scrolled this frame, positive away from the user.
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    fn binding_held(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_held(key),
+            Binding::Mouse(button) => self.mouse_held(button),
+        }
+    }
+
+    fn binding_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_pressed(key),
+            Binding::Mouse(button) => self.mouse_pressed(button),
+        }
+    }
+
+    fn binding_released(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_released(key),
+            Binding::Mouse(button) => self.mouse_released(button),
+        }
+    }
+}
+
+/// Input state queried through named actions.
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    pub state: InputState,
+    pub bindings: Bindings,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Self {
+        Self { state: InputState::default(), bindings }
+    }
+
+    /// Whether any binding of `action` is held down.
+    pub fn held(&self, action: Action) -> bool {
+        self.bindings.get(action).iter().any(|b| self.state.binding_held(*b))
+    }
+
+    /// Whether a binding of `action` went down this frame.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings.get(action).iter().any(|b| self.state.binding_pressed(*b))
+    }
+
+    /// Whether a binding of `action` went up this frame.
+    pub fn released(&self, action: Action) -> bool {
+        self.bindings.get(action).iter().any(|b| self.state.binding_released(*b))
+    }
+
+    /// -1, 0 or 1 depending on which of two opposing actions are held.
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+    }
+
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.state.mouse_delta()
+    }
+
+    pub fn scroll(&self) -> f32 {
+        self.state.scroll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use winit::event::DeviceId;
+
+    fn mouse(input: &mut Input, state: ElementState, button: MouseButton) {
+        let device_id = unsafe { DeviceId::dummy() };
+        input.state.handle_window_event(&WindowEvent::MouseInput { device_id, state, button });
+    }
+
+    #[test]
+    fn pressed_lasts_one_frame_and_held_lasts_until_release() {
+        let mut input = Input::default();
+        mouse(&mut input, ElementState::Pressed, MouseButton::Left);
+        assert!(input.pressed(Action::Orbit));
+        assert!(input.held(Action::Orbit));
+        input.state.end_frame();
+
+        assert!(!input.pressed(Action::Orbit));
+        assert!(input.held(Action::Orbit));
+        input.state.end_frame();
+        assert!(input.held(Action::Orbit));
+
+        mouse(&mut input, ElementState::Released, MouseButton::Left);
+        assert!(input.released(Action::Orbit));
+        assert!(!input.held(Action::Orbit));
+        input.state.end_frame();
+        assert!(!input.released(Action::Orbit));
+    }
+
+    #[test]
+    fn repeated_presses_do_not_retrigger() {
+        let mut keys = ButtonState::default();
+        keys.press(KeyCode::KeyW);
+        keys.end_frame();
+        keys.press(KeyCode::KeyW);
+        assert!(keys.pressed.is_empty());
+        assert!(keys.held.contains(&KeyCode::KeyW));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut input = Input::default();
+        mouse(&mut input, ElementState::Pressed, MouseButton::Right);
+        input.state.end_frame();
+        input.state.handle_window_event(&WindowEvent::Focused(false));
+        assert!(!input.held(Action::Look));
+        assert!(input.released(Action::Look));
+    }
+
+    #[test]
+    fn axis_cancels_opposing_actions() {
+        let mut input = Input::default();
+        input.bindings.set(Action::MoveForward, vec![Binding::Mouse(MouseButton::Left)]);
+        input.bindings.set(Action::MoveBackward, vec![Binding::Mouse(MouseButton::Right)]);
+        mouse(&mut input, ElementState::Pressed, MouseButton::Left);
+        assert_eq!(input.axis(Action::MoveForward, Action::MoveBackward), 1.0);
+        mouse(&mut input, ElementState::Pressed, MouseButton::Right);
+        assert_eq!(input.axis(Action::MoveForward, Action::MoveBackward), 0.0);
+    }
+}
@@ -2,6 +2,8 @@ use anyhow::{anyhow, Result};
 use log::*;
 
 use std::collections::HashSet;
+use std::os::raw::c_void;
+use std::sync::Arc;
 
 use vulkanalia::vk::ExtDebugUtilsExtension;
 use vulkanalia::window as vk_window;
@@ -12,17 +14,87 @@ use winit::window::Window;
 
 use crate::app::data;
 use crate::core::debug_callback;
+use crate::core::messages::MessageSink;
 
 
-pub const VALIDATION_ENABLED: bool =
-    cfg!(debug_assertions);
-
 pub const VALIDATION_LAYER: vk::ExtensionName =
     vk::ExtensionName::from_bytes(b"VK_LAYER_KHRONOS_validation");
 
+/// `VK_EXT_validation_features`, which vulkanalia deprecates in favor of
+/// `VK_EXT_layer_settings` but every validation layer still provides.
+const VALIDATION_FEATURES_EXTENSION: vk::ExtensionName =
+    vk::ExtensionName::from_bytes(b"VK_EXT_validation_features");
+
 pub const PORTABILITY_MACOS_VERSION: Version = Version::new(1, 3, 216);
 // Required by Vulkan SDK on macOS since 1.3.216.
 
+/// Whether to load the validation layer and which of its optional checks to
+/// turn on through `VK_EXT_validation_features`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationSettings {
+    pub enabled: bool,
+    /// Reports missing or wrong barriers and other synchronization hazards.
+    pub synchronization: bool,
+    /// Warns about valid but inefficient API use.
+    pub best_practices: bool,
+    /// Instruments shaders to catch out of bounds descriptor and buffer
+    /// accesses. Can't be combined with `debug_printf`.
+    pub gpu_assisted: bool,
+    /// Forwards `debugPrintfEXT` output from shaders as info messages.
+    pub debug_printf: bool,
+}
+
+impl ValidationSettings {
+    /// Plain validation in debug builds, none in release builds.
+    pub fn for_build() -> Self {
+        Self { enabled: cfg!(debug_assertions), ..Self::default() }
+    }
+
+    /// Parses `off`, `on`, or a comma separated list of `sync`,
+    /// `best_practices`, `gpu_assisted` and `debug_printf` to enable
+    /// validation with those checks.
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut settings = Self { enabled: true, ..Self::default() };
+        for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name {
+                "off" => return Ok(Self::default()),
+                "on" => {}
+                "sync" => settings.synchronization = true,
+                "best_practices" => settings.best_practices = true,
+                "gpu_assisted" => settings.gpu_assisted = true,
+                "debug_printf" => settings.debug_printf = true,
+                _ => return Err(anyhow!(
+                    "Unknown validation setting `{}`, expected `off`, `on`, `sync`, `best_practices`, `gpu_assisted` or `debug_printf`.",
+                    name,
+                )),
+            }
+        }
+        if settings.gpu_assisted && settings.debug_printf {
+            return Err(anyhow!("GPU-assisted validation and debug printf can't be enabled together."));
+        }
+        Ok(settings)
+    }
+
+    /// The optional checks to enable in `ValidationFeaturesEXT`.
+    pub fn features(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut features = vec![];
+        if self.synchronization {
+            features.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+        }
+        if self.best_practices {
+            features.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+        }
+        if self.gpu_assisted {
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+        if self.debug_printf {
+            features.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+        }
+        features
+    }
+}
+
 
 pub unsafe fn create_instance(
     window: &Window,
@@ -42,12 +114,13 @@ pub unsafe fn create_instance(
         .iter()
         .map(|l| l.layer_name)
         .collect::<HashSet<_>>();
-    
-    if VALIDATION_ENABLED && !available_layers.contains(&VALIDATION_LAYER) {
-        return Err(anyhow!("Validation layer requested is not supported."));
+
+    if data.validation.enabled && !available_layers.contains(&VALIDATION_LAYER) {
+        warn!("Validation was requested but the validation layer is not installed, continuing without it.");
+        data.validation = ValidationSettings::default();
     }
 
-    let layers = if VALIDATION_ENABLED {
+    let layers = if data.validation.enabled {
         vec![VALIDATION_LAYER.as_ptr()]
     } else {
         Vec::new()
@@ -58,10 +131,39 @@ pub unsafe fn create_instance(
         .map(|e| e.as_ptr())
         .collect::<Vec<_>>();
 
-    if VALIDATION_ENABLED {
+    if data.validation.enabled {
         extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name.as_ptr());
     }
 
+    // The validation layer provides `VK_EXT_validation_features` itself.
+    let mut validation_features = data.validation.features();
+    if !validation_features.is_empty() {
+        let layer_extensions = entry
+            .enumerate_instance_extension_properties(Some(VALIDATION_LAYER.as_bytes()))?
+            .iter()
+            .map(|e| e.extension_name)
+            .collect::<HashSet<_>>();
+        if layer_extensions.contains(&VALIDATION_FEATURES_EXTENSION) {
+            info!("Enabling validation features {:?}.", validation_features);
+            extensions.push(VALIDATION_FEATURES_EXTENSION.as_ptr());
+        } else {
+            warn!("The validation layer doesn't support `VK_EXT_validation_features`, so only plain validation is enabled.");
+            validation_features.clear();
+        }
+    }
+
+    // Lets surfaces offer HDR10 and scRGB color spaces.
+    let available_extensions = entry
+        .enumerate_instance_extension_properties(None)?
+        .iter()
+        .map(|e| e.extension_name)
+        .collect::<HashSet<_>>();
+    if available_extensions.contains(&vk::EXT_SWAPCHAIN_COLORSPACE_EXTENSION.name) {
+        extensions.push(vk::EXT_SWAPCHAIN_COLORSPACE_EXTENSION.name.as_ptr());
+    } else {
+        info!("`VK_EXT_swapchain_colorspace` is unavailable, so HDR output is too.");
+    }
+
     let flags = if 
         cfg!(target_os = "macos") && 
         entry.version()? >= PORTABILITY_MACOS_VERSION
@@ -79,33 +181,25 @@ pub unsafe fn create_instance(
         .enabled_extension_names(&extensions)
         .enabled_layer_names(&layers)
         .flags(flags);
-    
-    let mut debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-        .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
-        .message_type(
-            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-        )
-        .user_callback(Some(debug_callback));
 
-    if VALIDATION_ENABLED {
+    let mut debug_info = debug_messenger_info(&data.messages);
+
+    let mut features_info = vk::ValidationFeaturesEXT::builder()
+        .enabled_validation_features(&validation_features);
+
+    if data.validation.enabled {
         instance_info = instance_info.push_next(&mut debug_info);
     }
+    if !validation_features.is_empty() {
+        instance_info = instance_info.push_next(&mut features_info);
+    }
 
     let instance = entry.create_instance(&instance_info, None)?;
 
-    if VALIDATION_ENABLED {
-        let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-            .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-            )
-            .user_callback(Some(debug_callback));
-
+    // `push_next` prepends, so `debug_info.next` is still null and it can be reused.
+    if data.validation.enabled {
         data.messenger = instance.create_debug_utils_messenger_ext(&debug_info, None)?;
+        data.debug_utils = Some(instance.clone());
     }
 
     println!("instance is created bro");
@@ -113,3 +207,39 @@ pub unsafe fn create_instance(
 
 }
 
+/// Sends every message to `debug_callback`, which forwards it to `messages`.
+fn debug_messenger_info(messages: &Arc<MessageSink>) -> vk::DebugUtilsMessengerCreateInfoEXT {
+    let mut info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+        .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
+        .message_type(
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        )
+        .user_callback(Some(debug_callback))
+        .build();
+    // The sink is only read through this pointer, and outlives the instance.
+    info.user_data = Arc::as_ptr(messages) as *mut c_void;
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_settings_parse() {
+        assert_eq!(ValidationSettings::parse("off").unwrap(), ValidationSettings::default());
+        assert_eq!(ValidationSettings::parse("on").unwrap().features(), []);
+
+        let settings = ValidationSettings::parse("sync, best_practices").unwrap();
+        assert!(settings.enabled && settings.synchronization && settings.best_practices);
+        assert_eq!(settings.features(), [
+            vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION,
+            vk::ValidationFeatureEnableEXT::BEST_PRACTICES,
+        ]);
+
+        assert!(ValidationSettings::parse("gpu_assisted,debug_printf").is_err());
+        assert!(ValidationSettings::parse("everything").is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::Result;
+
+use std::mem::size_of;
+
+use cgmath::{vec3, vec4, Matrix4};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::{create_device_local_buffer, slice_as_bytes, UploadTarget};
+use crate::core::debug_utils::set_object_name;
+use crate::mesh::{self, Mesh, MeshData, Vertex};
+use crate::pipeline::graphics::{create_graphics_pipeline, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range, PushConstants};
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// Vertex buffer binding that per-instance attributes are read from.
+pub const INSTANCE_BINDING: u32 = 1;
+
+/// First shader location of the per-instance attributes (after `Vertex`'s).
+pub const INSTANCE_FIRST_LOCATION: u32 = 6;
+
+/// Per-instance data, read from binding `INSTANCE_BINDING` once per instance.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InstanceData {
+    pub model: Mat4,
+    pub color: Vec4,
+}
+
+impl InstanceData {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(INSTANCE_BINDING)
+            .stride(size_of::<InstanceData>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    /// The model matrix takes one location per column, then the color.
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        let attribute = |index: u32| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(INSTANCE_BINDING)
+                .location(INSTANCE_FIRST_LOCATION + index)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(index * size_of::<Vec4>() as u32)
+                .build()
+        };
+        [attribute(0), attribute(1), attribute(2), attribute(3), attribute(4)]
+    }
+}
+
+/// Collects instances on the CPU before they are uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct InstanceBufferBuilder {
+    instances: Vec<InstanceData>,
+}
+
+impl InstanceBufferBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, model: Mat4, color: Vec4) -> &mut Self {
+        self.instances.push(InstanceData { model, color });
+        self
+    }
+
+    /// Adds a `columns` x `rows` grid of instances `spacing` apart, centered
+    /// on the origin in the XZ plane. `instance` maps the (column, row) of
+    /// each cell to its extra local transform and color.
+    pub fn grid(
+        &mut self,
+        columns: u32,
+        rows: u32,
+        spacing: f32,
+        mut instance: impl FnMut(u32, u32) -> (Mat4, Vec4),
+    ) -> &mut Self {
+        let offset_x = (columns as f32 - 1.0) * spacing / 2.0;
+        let offset_y = (rows as f32 - 1.0) * spacing / 2.0;
+        for row in 0..rows {
+            for column in 0..columns {
+                let translation = Matrix4::from_translation(vec3(
+                    column as f32 * spacing - offset_x,
+                    0.0,
+                    row as f32 * spacing - offset_y,
+                ));
+                let (local, color) = instance(column, row);
+                self.push(translation * local, color);
+            }
+        }
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn instances(&self) -> &[InstanceData] {
+        &self.instances
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `InstanceData` is `#[repr(C)]` and all `f32`s, so it has no padding.
+        unsafe { slice_as_bytes(&self.instances) }
+    }
+}
+
+/// Instances uploaded to a device local vertex buffer.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct InstanceBuffer {
+    pub buffer: vk::Buffer,
+    pub buffer_memory: vk::DeviceMemory,
+    pub count: u32,
+}
+
+pub unsafe fn create_instance_buffer(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    builder: &InstanceBufferBuilder,
+) -> Result<InstanceBuffer> {
+    let (buffer, buffer_memory) = create_device_local_buffer(
+        instance,
+        device,
+        data,
+        builder.as_bytes(),
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        ),
+    )?;
+
+    Ok(InstanceBuffer { buffer, buffer_memory, count: builder.len() as u32 })
+}
+
+pub unsafe fn destroy_instance_buffer(device: &Device, instances: &mut InstanceBuffer) {
+    device.destroy_buffer(instances.buffer, None);
+    device.free_memory(instances.buffer_memory, None);
+    *instances = InstanceBuffer::default();
+}
+
+/// Records drawing `instance_count` instances of `mesh` starting at
+/// `first_instance` in `instances`. The pipeline must already be bound.
+pub unsafe fn cmd_draw_instanced(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    mesh: &Mesh,
+    instances: &InstanceBuffer,
+    instance_count: u32,
+    first_instance: u32,
+) {
+    mesh::cmd_bind_mesh(device, command_buffer, mesh);
+    device.cmd_bind_vertex_buffers(command_buffer, INSTANCE_BINDING, &[instances.buffer], &[0]);
+    device.cmd_draw_indexed(command_buffer, mesh.index_count, instance_count, 0, 0, first_instance);
+}
+
+/// Matches the push constant block in `instanced.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct InstancedPushConstants {
+    pub view_proj: Mat4,
+}
+
+const _: () = assert!(size_of::<InstancedPushConstants>() == 64);
+
+// SAFETY: `#[repr(C)]` and `Mat4` is all `f32`s, so there is no padding.
+unsafe impl PushConstants for InstancedPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+pub unsafe fn create_instanced_pipeline_layout(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+) -> Result<vk::PipelineLayout> {
+    let push_constant_ranges = &[push_constant_range::<InstancedPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .push_constant_ranges(push_constant_ranges);
+    Ok(device.create_pipeline_layout(&layout_info, None)?)
+}
+
+/// A pipeline for `Vertex` meshes with `InstanceData` instances.
+pub unsafe fn create_instanced_pipeline(
+    device: &Device,
+    data: &AppData,
+    layout: vk::PipelineLayout,
+    polygon_mode: vk::PolygonMode,
+) -> Result<vk::Pipeline> {
+    let bindings = &[Vertex::binding_description(), InstanceData::binding_description()];
+    let attributes = Vertex::attribute_descriptions()
+        .into_iter()
+        .chain(InstanceData::attribute_descriptions())
+        .collect::<Vec<_>>();
+    let info = GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: &attributes,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        polygon_mode,
+        depth_test: true,
+        depth_write: true,
+        samples: data.msaa_samples,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/instanced_vert.spv")[..],
+            &include_bytes!("../shaders/instanced_frag.spv")[..],
+            layout,
+            data.render_pass,
+        )
+    };
+    create_graphics_pipeline(device, &info)
+}
+
+pub const DEMO_COLUMNS: u32 = 100;
+pub const DEMO_ROWS: u32 = 100;
+
+/// A grid of cubes drawn with a single instanced draw call.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct InstancingDemo {
+    pub mesh: Mesh,
+    pub instances: InstanceBuffer,
+    pub pipeline: vk::Pipeline,
+    /// Null if the device has no `fillModeNonSolid`.
+    pub wireframe_pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+}
+
+pub const DEMO_SPACING: f32 = 1.5;
+
+/// The demo's instances: a `DEMO_COLUMNS` x `DEMO_ROWS` grid of cubes on a
+/// wave, tinted by their position in the grid.
+pub fn demo_instances() -> InstanceBufferBuilder {
+    let mut builder = InstanceBufferBuilder::new();
+    builder.grid(DEMO_COLUMNS, DEMO_ROWS, DEMO_SPACING, |column, row| {
+        let u = column as f32 / (DEMO_COLUMNS - 1) as f32;
+        let v = row as f32 / (DEMO_ROWS - 1) as f32;
+        let height = 3.0 * ((u * 12.0).sin() + (v * 9.0).cos());
+        let model = Matrix4::from_translation(vec3(0.0, height, 0.0));
+        (model, vec4(0.4 + 0.6 * u, 0.4 + 0.6 * v, 1.0 - 0.6 * u, 1.0))
+    });
+    builder
+}
+
+pub unsafe fn create_instancing_demo(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let mesh = mesh::create_mesh(instance, device, data, &MeshData::cube())?;
+    let instances = create_instance_buffer(instance, device, data, &demo_instances())?;
+    let pipeline_layout = create_instanced_pipeline_layout(instance, device, data)?;
+    let pipeline = create_instanced_pipeline(device, data, pipeline_layout, vk::PolygonMode::FILL)?;
+    let wireframe_pipeline = if data.features.fill_mode_non_solid == vk::TRUE {
+        create_instanced_pipeline(device, data, pipeline_layout, vk::PolygonMode::LINE)?
+    } else {
+        vk::Pipeline::null()
+    };
+
+    data.instancing = InstancingDemo { mesh, instances, pipeline, wireframe_pipeline, pipeline_layout };
+
+    let demo = &data.instancing;
+    set_object_name(device, data, demo.mesh.vertex_buffer, "instancing cube vertices");
+    set_object_name(device, data, demo.mesh.index_buffer, "instancing cube indices");
+    set_object_name(device, data, demo.instances.buffer, "instancing instances");
+    set_object_name(device, data, demo.pipeline_layout, "instancing pipeline layout");
+    set_object_name(device, data, demo.pipeline, "instancing pipeline");
+    set_object_name(device, data, demo.wireframe_pipeline, "instancing wireframe pipeline");
+
+    Ok(())
+}
+
+pub unsafe fn cmd_draw_demo(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    demo: &InstancingDemo,
+    view_proj: Mat4,
+    wireframe: bool,
+) {
+    let pipeline = if wireframe && !demo.wireframe_pipeline.is_null() {
+        demo.wireframe_pipeline
+    } else {
+        demo.pipeline
+    };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+    let push_constants = InstancedPushConstants { view_proj };
+    cmd_push_constants(device, command_buffer, demo.pipeline_layout, &push_constants);
+    cmd_draw_instanced(device, command_buffer, &demo.mesh, &demo.instances, demo.instances.count, 0);
+}
+
+pub unsafe fn destroy_instancing_demo(device: &Device, demo: &mut InstancingDemo) {
+    device.destroy_pipeline(demo.wireframe_pipeline, None);
+    device.destroy_pipeline(demo.pipeline, None);
+    device.destroy_pipeline_layout(demo.pipeline_layout, None);
+    destroy_instance_buffer(device, &mut demo.instances);
+    mesh::destroy_mesh(device, &mut demo.mesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::SquareMatrix;
+
+    fn translation(instance: &InstanceData) -> Vec4 {
+        instance.model.w
+    }
+
+    #[test]
+    fn grid_is_centered_and_spaced() {
+        let mut builder = InstanceBufferBuilder::new();
+        builder.grid(3, 2, 2.0, |column, row| (Mat4::identity(), vec4(column as f32, row as f32, 0.0, 1.0)));
+        assert_eq!(builder.len(), 6);
+
+        let positions = builder.instances().iter().map(translation).collect::<Vec<_>>();
+        assert_eq!(positions, [
+            vec4(-2.0, 0.0, -1.0, 1.0),
+            vec4(0.0, 0.0, -1.0, 1.0),
+            vec4(2.0, 0.0, -1.0, 1.0),
+            vec4(-2.0, 0.0, 1.0, 1.0),
+            vec4(0.0, 0.0, 1.0, 1.0),
+            vec4(2.0, 0.0, 1.0, 1.0),
+        ]);
+        assert_eq!(builder.instances()[4].color, vec4(1.0, 1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn grid_applies_the_local_transform_after_the_cell_translation() {
+        let mut builder = InstanceBufferBuilder::new();
+        let local = Matrix4::from_translation(vec3(0.0, 5.0, 0.0));
+        builder.grid(1, 1, 3.0, |_, _| (local, vec4(1.0, 1.0, 1.0, 1.0)));
+        assert_eq!(translation(&builder.instances()[0]), vec4(0.0, 5.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn push_keeps_order() {
+        let mut builder = InstanceBufferBuilder::new();
+        assert!(builder.is_empty());
+        assert_eq!(builder.len(), 0);
+
+        let red = vec4(1.0, 0.0, 0.0, 1.0);
+        let green = vec4(0.0, 1.0, 0.0, 1.0);
+        builder.push(Mat4::identity(), red).push(Matrix4::from_scale(2.0), green);
+
+        assert!(!builder.is_empty());
+        assert_eq!(builder.len(), 2);
+        assert_eq!(builder.instances()[0], InstanceData { model: Mat4::identity(), color: red });
+        assert_eq!(builder.instances()[1], InstanceData { model: Matrix4::from_scale(2.0), color: green });
+    }
+
+    #[test]
+    fn bytes_cover_every_instance() {
+        let mut builder = InstanceBufferBuilder::new();
+        assert!(builder.as_bytes().is_empty());
+        builder.grid(4, 3, 1.0, |_, _| (Mat4::identity(), vec4(1.0, 1.0, 1.0, 1.0)));
+        assert_eq!(builder.as_bytes().len(), builder.len() * size_of::<InstanceData>());
+    }
+
+    #[test]
+    fn attributes_follow_the_vertex_locations() {
+        let binding = InstanceData::binding_description();
+        assert_eq!(binding.binding, INSTANCE_BINDING);
+        assert_eq!(binding.stride, 80);
+        assert_eq!(binding.input_rate, vk::VertexInputRate::INSTANCE);
+
+        let attributes = InstanceData::attribute_descriptions();
+        for (i, attribute) in attributes.iter().enumerate() {
+            assert_eq!(attribute.binding, INSTANCE_BINDING);
+            assert_eq!(attribute.location, INSTANCE_FIRST_LOCATION + i as u32);
+            assert_eq!(attribute.offset, 16 * i as u32);
+            assert_eq!(attribute.format, vk::Format::R32G32B32A32_SFLOAT);
+        }
+        assert_eq!(attributes.last().unwrap().offset as usize + size_of::<Vec4>(), binding.stride as usize);
+        assert_eq!(INSTANCE_FIRST_LOCATION as usize, Vertex::attribute_descriptions().len());
+    }
+}
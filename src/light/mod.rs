@@ -0,0 +1,177 @@
+use cgmath::{vec3, vec4, InnerSpace, Rad};
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// How many lights the frame light buffers hold unless configured otherwise.
+pub const DEFAULT_MAX_LIGHTS: u32 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shines along the node's -Z axis from infinitely far away.
+    Directional,
+    /// Shines in all directions from the node's origin.
+    Point,
+    /// Shines along the node's -Z axis from its origin, at full intensity
+    /// inside `inner_cone` and fading out towards `outer_cone` (half angles).
+    Spot { inner_cone: Rad<f32>, outer_cone: Rad<f32> },
+}
+
+/// A light component of a scene node, modeled after glTF's
+/// `KHR_lights_punctual`; the node's world transform places and aims it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB.
+    pub color: Vec3,
+    /// Illuminance in lux for directional lights, luminous intensity in
+    /// candela for point and spot lights.
+    pub intensity: f32,
+    /// Distance at which point and spot lights fade out completely; `None`
+    /// falls off with the inverse square only.
+    pub range: Option<f32>,
+    /// Whether the light gets a shadow map, see `shadow::ShadowPlan`. Only
+    /// directional and spot lights cast shadows.
+    pub cast_shadows: bool,
+}
+
+impl Light {
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Self { kind: LightKind::Directional, color, intensity, range: None, cast_shadows: false }
+    }
+
+    pub fn point(color: Vec3, intensity: f32, range: Option<f32>) -> Self {
+        Self { kind: LightKind::Point, color, intensity, range, cast_shadows: false }
+    }
+
+    pub fn spot(color: Vec3, intensity: f32, range: Option<f32>, inner_cone: Rad<f32>, outer_cone: Rad<f32>) -> Self {
+        Self { kind: LightKind::Spot { inner_cone, outer_cone }, color, intensity, range, cast_shadows: false }
+    }
+
+    pub fn with_shadows(self) -> Self {
+        Self { cast_shadows: true, ..self }
+    }
+}
+
+/// The world space direction a light with the `world` transform shines in:
+/// its -Z axis, ignoring scale.
+pub fn world_direction(world: Mat4) -> Vec3 {
+    let direction = (world * vec4(0.0, 0.0, -1.0, 0.0)).truncate();
+    if direction.magnitude2() > 0.0 { direction.normalize() } else { vec3(0.0, 0.0, -1.0) }
+}
+
+/// Which lighting model the scene fragment shader evaluates lights with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShadingModel {
+    #[default]
+    Pbr,
+    BlinnPhong,
+}
+
+impl ShadingModel {
+    pub fn toggled(self) -> Self {
+        match self {
+            ShadingModel::Pbr => ShadingModel::BlinnPhong,
+            ShadingModel::BlinnPhong => ShadingModel::Pbr,
+        }
+    }
+}
+
+/// The start of the frame light buffer, followed by `count` `GpuLight`s (std430).
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct LightHeader {
+    pub count: u32,
+    /// 0 for PBR, 1 for Blinn-Phong.
+    pub shading_model: u32,
+    pub _padding: [u32; 2],
+}
+
+impl LightHeader {
+    pub fn new(count: u32, shading_model: ShadingModel) -> Self {
+        let shading_model = match shading_model {
+            ShadingModel::Pbr => 0,
+            ShadingModel::BlinnPhong => 1,
+        };
+        Self { count, shading_model, _padding: [0; 2] }
+    }
+}
+
+/// A light in world space as read by shaders (std430).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuLight {
+    /// World space position, `w` is the range (0 for none).
+    pub position_range: Vec4,
+    /// World space direction the light shines in, `w` is unused.
+    pub direction: Vec4,
+    /// Linear color premultiplied by intensity, `w` is unused.
+    pub color: Vec4,
+    /// 0 directional, 1 point, 2 spot.
+    pub kind: u32,
+    /// Cosines of the spot cone half angles.
+    pub cos_inner_cone: f32,
+    pub cos_outer_cone: f32,
+    /// The light's first layer in the shadow map, -1 if it has none.
+    pub shadow_layer: i32,
+}
+
+impl GpuLight {
+    pub fn new(light: &Light, world: Mat4) -> Self {
+        let position = world.w.truncate();
+        let direction = world_direction(world);
+
+        let (kind, cos_inner_cone, cos_outer_cone) = match light.kind {
+            LightKind::Directional => (0, 1.0, 1.0),
+            LightKind::Point => (1, -1.0, -1.0),
+            LightKind::Spot { inner_cone, outer_cone } => (2, inner_cone.0.cos(), outer_cone.0.cos()),
+        };
+
+        Self {
+            position_range: position.extend(light.range.unwrap_or(0.0)),
+            direction: direction.extend(0.0),
+            color: (light.color * light.intensity).extend(1.0),
+            kind,
+            cos_inner_cone,
+            cos_outer_cone,
+            shadow_layer: -1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, Deg, Matrix4};
+
+    #[test]
+    fn gpu_light_takes_position_and_direction_from_world_transform() {
+        let light = Light::spot(vec3(1.0, 0.5, 0.0), 4.0, Some(10.0), Deg(30.0).into(), Deg(60.0).into());
+        // Facing down: -Z rotated -90° around X is -Y.
+        let world = Matrix4::from_translation(vec3(1.0, 2.0, 3.0)) * Matrix4::from_angle_x(Deg(-90.0));
+        let gpu = GpuLight::new(&light, world);
+
+        assert_abs_diff_eq!(gpu.position_range, vec4(1.0, 2.0, 3.0, 10.0));
+        assert_abs_diff_eq!(gpu.direction, vec4(0.0, -1.0, 0.0, 0.0), epsilon = 1e-6);
+        assert_abs_diff_eq!(gpu.color, vec4(4.0, 2.0, 0.0, 1.0));
+        assert_eq!(gpu.kind, 2);
+        assert_abs_diff_eq!(gpu.cos_inner_cone, 3f32.sqrt() / 2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(gpu.cos_outer_cone, 0.5, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn directions_ignore_scale() {
+        let light = Light::directional(vec3(1.0, 1.0, 1.0), 1.0);
+        let gpu = GpuLight::new(&light, Matrix4::from_scale(5.0));
+        assert_abs_diff_eq!(gpu.direction, vec4(0.0, 0.0, -1.0, 0.0));
+        assert_eq!(gpu.position_range.w, 0.0);
+    }
+
+    #[test]
+    fn gpu_layout_matches_std430() {
+        assert_eq!(std::mem::size_of::<LightHeader>(), 16);
+        assert_eq!(std::mem::size_of::<GpuLight>(), 64);
+    }
+}
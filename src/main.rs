@@ -3,55 +3,102 @@
     unused_variables,
     clippy::too_many_arguments,
     clippy::unnecessary_wraps,
+    clippy::missing_safety_doc,
     unsafe_attr_outside_unsafe,
     unsafe_op_in_unsafe_fn,
 )]
 
 
 pub mod app;
+pub mod buffer;
+pub mod camera;
 pub mod core;
+pub mod debug_draw;
+pub mod descriptor;
 pub mod device;
 pub mod frame;
+pub mod gui;
+pub mod image;
 pub mod instance;
+pub mod input;
+pub mod instancing;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod model;
+pub mod particles;
 pub mod pipeline;
+pub mod post;
+pub mod profiler;
+pub mod scene;
+pub mod screenshot;
+pub mod shadow;
+pub mod skybox;
 pub mod swapchain;
+pub mod text;
+pub mod texture;
 pub mod command;
+pub mod config;
 
 use anyhow::Result;
 
 use winit::dpi::LogicalSize;
-use winit::event::{Event, WindowEvent};
+use winit::event::{DeviceEvent, Event, WindowEvent};
 use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, WindowBuilder};
+
+use config::Config;
 
 
 fn main() -> Result<()> {
 
-    unsafe { std::env::set_var("RUST_LOG", "info"); }
-    pretty_env_logger::init();
+    // Config
+    let Some(config) = Config::load()? else {
+        print!("{}", config::usage());
+        return Ok(());
+    };
+    pretty_env_logger::formatted_builder().parse_filters(&config.log).init();
 
     // Window
     let event_loop = EventLoop::new()?;
     let window = WindowBuilder::new()
-        .with_title("Vulkan Tutorial (Rust)")
-        .with_inner_size(LogicalSize::new(1024, 768))
+        .with_title(&config.title)
+        .with_inner_size(LogicalSize::new(config.width, config.height))
+        .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None)))
         .build(&event_loop)?;
 
     // App
-    let mut app = unsafe { app::app::App::create(&window)? };
+    let mut app = unsafe { app::app::App::create(&window, &config)? };
+    let mut minimized = false;
     event_loop.run(move |event, elwt| {
         match event {
             // Request a redraw when all events were processed.
             Event::AboutToWait => window.request_redraw(),
-            Event::WindowEvent { event, .. } => match event {
-                // Render a frame if our Vulkan app is not being destroyed.
-                WindowEvent::RedrawRequested if !elwt.exiting() => unsafe { app.render(&window) }.unwrap(),
-                // Destroy our Vulkan app.
-                WindowEvent::CloseRequested => {
-                    elwt.exit();
-                    unsafe { app.destroy(); }
+            // Raw mouse motion for the camera, not limited by the window edges.
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+                app.handle_mouse_motion(delta);
+            }
+            Event::WindowEvent { event, .. } => {
+                app.handle_window_event(&window, &event);
+                match event {
+                    // Render a frame if our Vulkan app is not being destroyed.
+                    WindowEvent::RedrawRequested if !elwt.exiting() && !minimized => unsafe { app.render(&window) }.unwrap(),
+                    // Mark the window as having been resized.
+                    WindowEvent::Resized(size) => {
+                        if size.width == 0 || size.height == 0 {
+                            minimized = true;
+                        } else {
+                            minimized = false;
+                            app.resized = true;
+                        }
+                    }
+                    // Destroy our Vulkan app.
+                    WindowEvent::CloseRequested => {
+                        elwt.exit();
+                        unsafe { app.destroy(); }
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
             _ => {}
         }
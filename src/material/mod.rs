@@ -0,0 +1,310 @@
+use anyhow::Result;
+
+use cgmath::{vec3, vec4};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::{create_device_local_buffer, slice_as_bytes, UploadTarget};
+use crate::descriptor;
+use crate::texture::{self, Texture, TextureId};
+
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// How many texture slots a material has, see `MaterialData::texture_slots`.
+pub const MATERIAL_TEXTURE_COUNT: usize = 5;
+
+/// Whether a slot (by index in `MaterialData::texture_slots`) holds color
+/// data that should be sampled as sRGB.
+pub const SRGB_TEXTURE_SLOTS: [bool; MATERIAL_TEXTURE_COUNT] = [true, false, false, false, true];
+
+/// A texture used by a material and the UV set it is sampled with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureSlot {
+    pub texture: TextureId,
+    pub uv_set: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    /// Fragments with alpha below the cutoff are discarded.
+    Mask { cutoff: f32 },
+    Blend,
+}
+
+/// A metallic-roughness PBR material, as described by glTF.
+///
+/// Each factor is multiplied with its texture if it has one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialData {
+    pub name: String,
+    pub base_color_factor: Vec4,
+    /// sRGB color and linear alpha.
+    pub base_color_texture: Option<TextureSlot>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in G and metalness in B.
+    pub metallic_roughness_texture: Option<TextureSlot>,
+    /// Tangent space normals.
+    pub normal_texture: Option<TextureSlot>,
+    pub normal_scale: f32,
+    /// Ambient occlusion in R.
+    pub occlusion_texture: Option<TextureSlot>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: Vec3,
+    /// sRGB emitted color.
+    pub emissive_texture: Option<TextureSlot>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl MaterialData {
+    /// The texture slots in binding order: base color, metallic-roughness,
+    /// normal, occlusion and emissive.
+    pub fn texture_slots(&self) -> [Option<TextureSlot>; MATERIAL_TEXTURE_COUNT] {
+        [
+            self.base_color_texture,
+            self.metallic_roughness_texture,
+            self.normal_texture,
+            self.occlusion_texture,
+            self.emissive_texture,
+        ]
+    }
+
+    pub fn texture_slots_mut(&mut self) -> [&mut Option<TextureSlot>; MATERIAL_TEXTURE_COUNT] {
+        [
+            &mut self.base_color_texture,
+            &mut self.metallic_roughness_texture,
+            &mut self.normal_texture,
+            &mut self.occlusion_texture,
+            &mut self.emissive_texture,
+        ]
+    }
+}
+
+impl Default for MaterialData {
+    /// The glTF default material: white, fully metallic and fully rough.
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color_factor: vec4(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: vec3(0.0, 0.0, 0.0),
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+/// A material's factors as read by the fragment shader, set 1 binding 0 (std140).
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MaterialUniforms {
+    pub base_color_factor: Vec4,
+    /// `w` is unused.
+    pub emissive_factor: Vec4,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    /// 0 unless the alpha mode is `Mask`.
+    pub alpha_cutoff: f32,
+    pub flags: u32,
+    /// Bit `i` is set if texture slot `i` uses UV set 1 instead of 0.
+    pub uv_sets: u32,
+    pub _padding: u32,
+}
+
+impl MaterialUniforms {
+    /// Set when the material has a normal map; without one the shader skips
+    /// building a tangent frame.
+    pub const HAS_NORMAL_TEXTURE: u32 = 1;
+
+    pub fn new(material: &MaterialData) -> Self {
+        let uv_sets = material
+            .texture_slots()
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.is_some_and(|s| s.uv_set == 1))
+            .fold(0, |bits, (index, _)| bits | 1 << index);
+
+        Self {
+            base_color_factor: material.base_color_factor,
+            emissive_factor: material.emissive_factor.extend(0.0),
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+            alpha_cutoff: match material.alpha_mode {
+                AlphaMode::Mask { cutoff } => cutoff,
+                _ => 0.0,
+            },
+            flags: if material.normal_texture.is_some() { Self::HAS_NORMAL_TEXTURE } else { 0 },
+            uv_sets,
+            _padding: 0,
+        }
+    }
+}
+
+/// Textures bound in place of missing maps: white for colors and factors
+/// (so only the factor applies) and a flat normal.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DefaultTextures {
+    pub white_srgb: Texture,
+    pub white: Texture,
+    pub flat_normal: Texture,
+}
+
+impl DefaultTextures {
+    fn for_slot(&self, slot: usize) -> &Texture {
+        match slot {
+            2 => &self.flat_normal,
+            _ if SRGB_TEXTURE_SLOTS[slot] => &self.white_srgb,
+            _ => &self.white,
+        }
+    }
+}
+
+pub unsafe fn create_default_textures(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+) -> Result<DefaultTextures> {
+    Ok(DefaultTextures {
+        white_srgb: texture::create_solid_texture(instance, device, data, [255; 4], vk::Format::R8G8B8A8_SRGB)?,
+        white: texture::create_solid_texture(instance, device, data, [255; 4], vk::Format::R8G8B8A8_UNORM)?,
+        flat_normal: texture::create_solid_texture(instance, device, data, [128, 128, 255, 255], vk::Format::R8G8B8A8_UNORM)?,
+    })
+}
+
+pub unsafe fn destroy_default_textures(device: &Device, textures: &mut DefaultTextures) {
+    texture::destroy_texture(device, &mut textures.white_srgb);
+    texture::destroy_texture(device, &mut textures.white);
+    texture::destroy_texture(device, &mut textures.flat_normal);
+}
+
+/// A material's uniform buffer and descriptor set (set 1).
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Material {
+    pub uniform_buffer: vk::Buffer,
+    pub uniform_buffer_memory: vk::DeviceMemory,
+    pub set: vk::DescriptorSet,
+    /// Drawn without back face culling.
+    pub double_sided: bool,
+}
+
+/// Set 1: the uniforms at binding 0, the textures in slot order at bindings
+/// 1 to 5 and their samplers at bindings 6 to 10.
+pub unsafe fn create_material_set_layout(device: &Device) -> Result<vk::DescriptorSetLayout> {
+    let stages = vk::ShaderStageFlags::FRAGMENT;
+    let count = MATERIAL_TEXTURE_COUNT as u32;
+    let bindings = std::iter::once(descriptor::uniform_buffer_binding(0, stages))
+        .chain((0..count).map(|i| descriptor::sampled_image_binding(1 + i, stages)))
+        .chain((0..count).map(|i| descriptor::sampler_binding(1 + count + i, stages)))
+        .collect::<Vec<_>>();
+    descriptor::create_descriptor_set_layout(device, &bindings)
+}
+
+/// A pool for `max_materials` material sets.
+pub unsafe fn create_material_descriptor_pool(device: &Device, max_materials: u32) -> Result<vk::DescriptorPool> {
+    let size = |type_, count| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(count * max_materials)
+            .build()
+    };
+    let count = MATERIAL_TEXTURE_COUNT as u32;
+    let sizes = &[
+        size(vk::DescriptorType::UNIFORM_BUFFER, 1),
+        size(vk::DescriptorType::SAMPLED_IMAGE, count),
+        size(vk::DescriptorType::SAMPLER, count),
+    ];
+    descriptor::create_descriptor_pool(device, sizes, max_materials)
+}
+
+/// Creates a material whose texture slots index `textures`; empty slots use `defaults`.
+pub unsafe fn create_material(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+    material: &MaterialData,
+    textures: &[Texture],
+    defaults: &DefaultTextures,
+) -> Result<Material> {
+    let uniforms = MaterialUniforms::new(material);
+    let (uniform_buffer, uniform_buffer_memory) = create_device_local_buffer(
+        instance,
+        device,
+        data,
+        // SAFETY: `MaterialUniforms` is `#[repr(C)]` and all 4-byte fields, with
+        // the trailing padding spelled out as `_padding`.
+        slice_as_bytes(&[uniforms]),
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::UNIFORM_READ,
+        ),
+    )?;
+
+    let set = descriptor::allocate_descriptor_set(device, pool, layout)?;
+    descriptor::write_uniform_buffer(device, set, 0, uniform_buffer);
+    for (index, slot) in material.texture_slots().iter().enumerate() {
+        let texture = match slot {
+            Some(slot) => &textures[slot.texture.0],
+            None => defaults.for_slot(index),
+        };
+        let binding = 1 + index as u32;
+        descriptor::write_sampled_image(device, set, binding, texture.image_view);
+        descriptor::write_sampler(device, set, binding + MATERIAL_TEXTURE_COUNT as u32, texture.sampler);
+    }
+
+    Ok(Material { uniform_buffer, uniform_buffer_memory, set, double_sided: material.double_sided })
+}
+
+/// The set is freed with its pool.
+pub unsafe fn destroy_material(device: &Device, material: &mut Material) {
+    device.destroy_buffer(material.uniform_buffer, None);
+    device.free_memory(material.uniform_buffer_memory, None);
+    *material = Material::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniforms_pack_uv_sets_flags_and_cutoff() {
+        let texture = |uv_set| Some(TextureSlot { texture: TextureId(0), uv_set });
+        let material = MaterialData {
+            base_color_texture: texture(1),
+            normal_texture: texture(0),
+            emissive_texture: texture(1),
+            alpha_mode: AlphaMode::Mask { cutoff: 0.25 },
+            ..MaterialData::default()
+        };
+
+        let uniforms = MaterialUniforms::new(&material);
+        assert_eq!(uniforms.uv_sets, 0b10001);
+        assert_eq!(uniforms.flags, MaterialUniforms::HAS_NORMAL_TEXTURE);
+        assert_eq!(uniforms.alpha_cutoff, 0.25);
+
+        let uniforms = MaterialUniforms::new(&MaterialData::default());
+        assert_eq!((uniforms.uv_sets, uniforms.flags, uniforms.alpha_cutoff), (0, 0, 0.0));
+        // Matches the std140 block in scene.frag, rounded up to a multiple of 16.
+        assert_eq!(std::mem::size_of::<MaterialUniforms>(), 64);
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::Result;
+
+use std::mem::{offset_of, size_of};
+
+use cgmath::{vec2, vec3, vec4, InnerSpace};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::{create_device_local_buffer, slice_as_bytes, UploadTarget};
+
+type Vec2 = cgmath::Vector2<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// A mesh vertex, read from vertex buffer binding 0 at locations 0 to 5.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub color: Vec4,
+    /// XYZ is the tangent and W the handedness of the bitangent (±1), or all
+    /// zero if the mesh has no tangents.
+    pub tangent: Vec4,
+    pub uv0: Vec2,
+    pub uv1: Vec2,
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Self {
+            position: vec3(0.0, 0.0, 0.0),
+            normal: vec3(0.0, 0.0, 0.0),
+            color: vec4(1.0, 1.0, 1.0, 1.0),
+            tangent: vec4(0.0, 0.0, 0.0, 0.0),
+            uv0: vec2(0.0, 0.0),
+            uv1: vec2(0.0, 0.0),
+        }
+    }
+}
+
+impl Vertex {
+    /// An opaque vertex without tangents or texture coordinates.
+    pub fn new(position: Vec3, normal: Vec3, color: Vec3) -> Self {
+        Self { position, normal, color: color.extend(1.0), ..Self::default() }
+    }
+
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Vertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 6] {
+        let attribute = |location: u32, format: vk::Format, offset: usize| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(location)
+                .format(format)
+                .offset(offset as u32)
+                .build()
+        };
+        [
+            attribute(0, vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, position)),
+            attribute(1, vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, normal)),
+            attribute(2, vk::Format::R32G32B32A32_SFLOAT, offset_of!(Vertex, color)),
+            attribute(3, vk::Format::R32G32B32A32_SFLOAT, offset_of!(Vertex, tangent)),
+            attribute(4, vk::Format::R32G32_SFLOAT, offset_of!(Vertex, uv0)),
+            attribute(5, vk::Format::R32G32_SFLOAT, offset_of!(Vertex, uv1)),
+        ]
+    }
+}
+
+/// Vertices and indices of a mesh on the CPU.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// A unit quad in the XY plane facing +Z, centered on the origin.
+    pub fn quad(color: Vec3) -> Self {
+        let normal = vec3(0.0, 0.0, 1.0);
+        let vertex = |x: f32, y: f32| Vertex {
+            tangent: vec4(1.0, 0.0, 0.0, 1.0),
+            uv0: vec2(x + 0.5, 0.5 - y),
+            ..Vertex::new(vec3(x, y, 0.0), normal, color)
+        };
+        Self {
+            vertices: vec![vertex(-0.5, -0.5), vertex(0.5, -0.5), vertex(0.5, 0.5), vertex(-0.5, 0.5)],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        }
+    }
+
+    /// A unit cube centered on the origin with one color per face.
+    pub fn cube() -> Self {
+        let faces = [
+            (vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(0.9, 0.3, 0.3)),
+            (vec3(-1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0), vec3(0.3, 0.9, 0.9)),
+            (vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), vec3(0.3, 0.9, 0.3)),
+            (vec3(0.0, -1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(0.9, 0.3, 0.9)),
+            (vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.3, 0.3, 0.9)),
+            (vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.9, 0.9, 0.3)),
+        ];
+
+        let mut mesh = Self::default();
+        for (normal, u, v, color) in faces {
+            let base = mesh.vertices.len() as u32;
+            for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let position = (normal + u * su + v * sv) * 0.5;
+                mesh.vertices.push(Vertex {
+                    tangent: u.extend(1.0),
+                    uv0: vec2((su + 1.0) / 2.0, (1.0 - sv) / 2.0),
+                    ..Vertex::new(position, normal, color)
+                });
+            }
+            mesh.indices.extend([base, base + 1, base + 2, base + 2, base + 3, base]);
+        }
+        mesh
+    }
+
+    /// Replaces the normals with face normals, giving each triangle its own
+    /// three vertices.
+    pub fn flatten_normals(&mut self) {
+        let mut vertices = Vec::with_capacity(self.indices.len());
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize]);
+            let normal = (b.position - a.position).cross(c.position - a.position).normalize();
+            vertices.extend([a, b, c].map(|v| Vertex { normal, ..v }));
+        }
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+    }
+}
+
+/// A mesh uploaded to device local vertex and index buffers.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertex_buffer: vk::Buffer,
+    pub vertex_buffer_memory: vk::DeviceMemory,
+    pub index_buffer: vk::Buffer,
+    pub index_buffer_memory: vk::DeviceMemory,
+    pub index_count: u32,
+}
+
+pub unsafe fn create_mesh(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    mesh: &MeshData,
+) -> Result<Mesh> {
+    let (vertex_buffer, vertex_buffer_memory) = create_device_local_buffer(
+        instance,
+        device,
+        data,
+        // SAFETY: `Vertex` is `#[repr(C)]` and all `f32`s, so it has no padding.
+        slice_as_bytes(&mesh.vertices),
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        ),
+    )?;
+
+    let (index_buffer, index_buffer_memory) = create_device_local_buffer(
+        instance,
+        device,
+        data,
+        // SAFETY: `u32` has no padding.
+        slice_as_bytes(&mesh.indices),
+        vk::BufferUsageFlags::INDEX_BUFFER,
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+        ),
+    )?;
+
+    Ok(Mesh {
+        vertex_buffer,
+        vertex_buffer_memory,
+        index_buffer,
+        index_buffer_memory,
+        index_count: mesh.indices.len() as u32,
+    })
+}
+
+/// Binds the mesh's vertex buffer to binding 0 and its index buffer.
+pub unsafe fn cmd_bind_mesh(device: &Device, command_buffer: vk::CommandBuffer, mesh: &Mesh) {
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[mesh.vertex_buffer], &[0]);
+    device.cmd_bind_index_buffer(command_buffer, mesh.index_buffer, 0, vk::IndexType::UINT32);
+}
+
+pub unsafe fn destroy_mesh(device: &Device, mesh: &mut Mesh) {
+    device.destroy_buffer(mesh.index_buffer, None);
+    device.free_memory(mesh.index_buffer_memory, None);
+    device.destroy_buffer(mesh.vertex_buffer, None);
+    device.free_memory(mesh.vertex_buffer_memory, None);
+    *mesh = Mesh::default();
+}
@@ -0,0 +1,464 @@
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use log::*;
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use cgmath::{Quaternion, Rad, Vector2, Vector4};
+use gltf::mesh::Mode;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use vulkanalia::vk;
+
+use crate::light::{Light, LightKind};
+use crate::material::{AlphaMode, MaterialData, TextureSlot};
+use crate::mesh::{MeshData, Vertex};
+use crate::model::Model;
+use crate::scene::{MaterialId, MeshId, NodeId, Scene, Transform};
+use crate::texture::{SamplerInfo, TextureData, TextureId};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Loads a `.gltf` or `.glb` file, resolving external buffers and images
+/// relative to its directory.
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<Model> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read `{}`.", path.display()))?;
+    parse_gltf(&bytes, path.parent())
+        .with_context(|| format!("Invalid glTF `{}`.", path.display()))
+}
+
+/// Parses glTF JSON or GLB bytes. External buffers and images are read
+/// relative to `base`; without one only embedded data can be loaded.
+///
+/// The default scene (or the first one) becomes the model's scene. Each
+/// primitive becomes its own mesh; a node whose mesh has several primitives
+/// gets one child node per primitive. Lights from `KHR_lights_punctual`
+/// become node lights. Only triangle primitives and PNG images
+/// are supported, anything else is skipped with a warning.
+pub fn parse_gltf(bytes: &[u8], base: Option<&Path>) -> Result<Model> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(bytes)?;
+
+    let buffers = document
+        .buffers()
+        .map(|b| load_buffer(&b, blob.as_deref(), base).with_context(|| format!("Buffer {}", b.index())))
+        .collect::<Result<Vec<_>>>()?;
+
+    let images = document
+        .images()
+        .map(|i| load_image(&i, &buffers, base).with_context(|| format!("Image {}", i.index())))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Textures whose image was skipped map to `None`.
+    let mut textures = Vec::new();
+    let texture_ids = document
+        .textures()
+        .map(|texture| {
+            let image = images[texture.source().index()].as_ref()?;
+            textures.push(TextureData { sampler: sampler_info(&texture.sampler()), ..image.clone() });
+            Some(TextureId(textures.len() - 1))
+        })
+        .collect::<Vec<_>>();
+
+    let materials = document.materials().map(|m| material(&m, &texture_ids)).collect();
+
+    let mut meshes = Vec::new();
+    let mut primitives = Vec::new();
+    for mesh in document.meshes() {
+        let mut mesh_primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                warn!(
+                    "Skipping mesh {} primitive {}: {:?} is not supported.",
+                    mesh.index(),
+                    primitive.index(),
+                    primitive.mode(),
+                );
+                continue;
+            }
+
+            let data = mesh_data(&primitive, &buffers)
+                .with_context(|| format!("Mesh {} primitive {}", mesh.index(), primitive.index()))?;
+            meshes.push(data);
+            mesh_primitives.push((MeshId(meshes.len() - 1), primitive.material().index().map(MaterialId)));
+        }
+        primitives.push(mesh_primitives);
+    }
+
+    let mut scene = Scene::new();
+    if let Some(gltf_scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in gltf_scene.nodes() {
+            add_node(&mut scene, None, &node, &primitives);
+        }
+    }
+
+    Ok(Model { scene, meshes, materials, textures })
+}
+
+/// Reads a `data:` URI or a file relative to `base`.
+fn read_uri(uri: &str, base: Option<&Path>) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .ok_or_else(|| anyhow!("Only base64 data URIs are supported."))?;
+        return Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?);
+    }
+
+    let base = base.ok_or_else(|| anyhow!("`{}` is external but no base directory was given.", uri))?;
+    let path = base.join(uri);
+    std::fs::read(&path).with_context(|| format!("Failed to read `{}`.", path.display()))
+}
+
+fn load_buffer(buffer: &gltf::Buffer, blob: Option<&[u8]>, base: Option<&Path>) -> Result<Vec<u8>> {
+    let data = match buffer.source() {
+        gltf::buffer::Source::Bin => blob
+            .ok_or_else(|| anyhow!("Refers to a GLB binary chunk that doesn't exist."))?
+            .to_vec(),
+        gltf::buffer::Source::Uri(uri) => read_uri(uri, base)?,
+    };
+
+    if data.len() < buffer.length() {
+        bail!("Expected {} bytes but found {}.", buffer.length(), data.len());
+    }
+
+    Ok(data)
+}
+
+/// `None` if the image isn't a PNG.
+fn load_image(image: &gltf::Image, buffers: &[Vec<u8>], base: Option<&Path>) -> Result<Option<TextureData>> {
+    let (bytes, mime_type) = match image.source() {
+        gltf::image::Source::View { view, mime_type } => {
+            let bytes = buffers
+                .get(view.buffer().index())
+                .and_then(|b| b.get(view.offset()..view.offset() + view.length()))
+                .ok_or_else(|| anyhow!(
+                    "Image {} refers to bytes {}..{} of buffer {}, which is out of bounds.",
+                    image.index(),
+                    view.offset(),
+                    view.offset() + view.length(),
+                    view.buffer().index(),
+                ))?;
+            (Cow::Borrowed(bytes), Some(mime_type))
+        }
+        gltf::image::Source::Uri { uri, mime_type } => (Cow::Owned(read_uri(uri, base)?), mime_type),
+    };
+
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        warn!(
+            "Skipping image {} ({}): only PNG images are supported.",
+            image.index(),
+            mime_type.unwrap_or("unknown type"),
+        );
+        return Ok(None);
+    }
+
+    TextureData::from_png(&bytes).map(Some)
+}
+
+fn sampler_info(sampler: &gltf::texture::Sampler) -> SamplerInfo {
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR),
+        Some(MinFilter::LinearMipmapNearest) => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+        Some(MinFilter::Linear | MinFilter::LinearMipmapLinear) | None => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR)
+        }
+    };
+
+    SamplerInfo {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            Some(MagFilter::Linear) | None => vk::Filter::LINEAR,
+        },
+        min_filter,
+        mipmap_mode,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+    }
+}
+
+fn material(material: &gltf::Material, texture_ids: &[Option<TextureId>]) -> MaterialData {
+    let slot = |texture: gltf::Texture, uv_set: u32| {
+        texture_ids[texture.index()].map(|texture| TextureSlot { texture, uv_set })
+    };
+
+    let pbr = material.pbr_metallic_roughness();
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+
+    MaterialData {
+        name: material.name().unwrap_or_default().to_string(),
+        base_color_factor: pbr.base_color_factor().into(),
+        base_color_texture: pbr.base_color_texture().and_then(|t| slot(t.texture(), t.tex_coord())),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .and_then(|t| slot(t.texture(), t.tex_coord())),
+        normal_texture: normal.as_ref().and_then(|t| slot(t.texture(), t.tex_coord())),
+        normal_scale: normal.as_ref().map_or(1.0, |t| t.scale()),
+        occlusion_texture: occlusion.as_ref().and_then(|t| slot(t.texture(), t.tex_coord())),
+        occlusion_strength: occlusion.as_ref().map_or(1.0, |t| t.strength()),
+        emissive_factor: material.emissive_factor().into(),
+        emissive_texture: material.emissive_texture().and_then(|t| slot(t.texture(), t.tex_coord())),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask { cutoff: material.alpha_cutoff().unwrap_or(0.5) },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn mesh_data(primitive: &gltf::Primitive, buffers: &[Vec<u8>]) -> Result<MeshData> {
+    let reader = primitive.reader(|b| buffers.get(b.index()).map(Vec::as_slice));
+
+    let mut vertices = reader
+        .read_positions()
+        .ok_or_else(|| anyhow!("Missing positions."))?
+        .map(|p| Vertex { position: p.into(), ..Vertex::default() })
+        .collect::<Vec<_>>();
+
+    let normals = reader.read_normals();
+    let has_normals = normals.is_some();
+    for (vertex, normal) in vertices.iter_mut().zip(normals.into_iter().flatten()) {
+        vertex.normal = normal.into();
+    }
+    for (vertex, tangent) in vertices.iter_mut().zip(reader.read_tangents().into_iter().flatten()) {
+        vertex.tangent = Vector4::from(tangent);
+    }
+    for (vertex, color) in vertices.iter_mut().zip(reader.read_colors(0).into_iter().flat_map(|c| c.into_rgba_f32())) {
+        vertex.color = Vector4::from(color);
+    }
+    for (vertex, uv) in vertices.iter_mut().zip(reader.read_tex_coords(0).into_iter().flat_map(|t| t.into_f32())) {
+        vertex.uv0 = Vector2::from(uv);
+    }
+    for (vertex, uv) in vertices.iter_mut().zip(reader.read_tex_coords(1).into_iter().flat_map(|t| t.into_f32())) {
+        vertex.uv1 = Vector2::from(uv);
+    }
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    if indices.len() % 3 != 0 {
+        bail!("{} indices don't make whole triangles.", indices.len());
+    }
+    if let Some(index) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+        bail!("Index {} is out of range for {} vertices.", index, vertices.len());
+    }
+
+    let mut mesh = MeshData { vertices, indices };
+    // glTF asks for flat normals when a primitive has none.
+    if !has_normals {
+        mesh.flatten_normals();
+    }
+
+    Ok(mesh)
+}
+
+fn add_node(
+    scene: &mut Scene,
+    parent: Option<NodeId>,
+    node: &gltf::Node,
+    primitives: &[Vec<(MeshId, Option<MaterialId>)>],
+) {
+    let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+    let local = Transform {
+        translation: translation.into(),
+        rotation: Quaternion::new(w, x, y, z),
+        scale: scale.into(),
+    };
+    let name = node.name().map_or_else(|| format!("node {}", node.index()), str::to_string);
+    let id = scene.add_node(parent, name.clone(), local);
+
+    if let Some(mesh) = node.mesh() {
+        match primitives[mesh.index()].as_slice() {
+            [(mesh, material)] => scene.set_mesh(id, *mesh, *material),
+            primitives => {
+                for (index, (mesh, material)) in primitives.iter().enumerate() {
+                    let child = scene.add_node(Some(id), format!("{} primitive {}", name, index), Transform::default());
+                    scene.set_mesh(child, *mesh, *material);
+                }
+            }
+        }
+    }
+
+    if let Some(light) = node.light() {
+        let kind = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+            gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+            gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => LightKind::Spot {
+                inner_cone: Rad(inner_cone_angle),
+                outer_cone: Rad(outer_cone_angle),
+            },
+        };
+        // glTF doesn't say which lights cast shadows, so every light that can
+        // does; the shadow plan leaves out those past its budget.
+        scene.set_light(id, Some(Light {
+            kind,
+            color: light.color().into(),
+            intensity: light.intensity(),
+            range: light.range(),
+            cast_shadows: kind != LightKind::Point,
+        }));
+    }
+
+    for child in node.children() {
+        add_node(scene, Some(id), &child, primitives);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, point3, vec2, vec3, vec4, Transform as _};
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn loads_node_hierarchy_and_primitives() {
+        let mut model = load_gltf(fixture("hierarchy.gltf")).unwrap();
+        let scene = &mut model.scene;
+
+        let root = scene.find("root").unwrap();
+        let child = scene.find("child").unwrap();
+        let leaf = scene.find("node 2").unwrap();
+        assert_eq!(scene.roots(), &[root]);
+        assert!(scene.find("not in scene").is_none());
+
+        // Two primitives become two child nodes, the points primitive is skipped.
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(scene.node(child).mesh, None);
+        let parts = scene.node(child).children().to_vec();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(scene.node(parts[0]).name, "child primitive 0");
+        assert_eq!(scene.node(parts[0]).mesh, Some(MeshId(0)));
+        assert_eq!(scene.node(parts[0]).material, Some(MaterialId(0)));
+        assert_eq!(scene.node(parts[1]).mesh, Some(MeshId(1)));
+        assert_eq!(scene.node(parts[1]).material, None);
+        assert_eq!(parts[2], leaf);
+        assert_eq!(scene.node(leaf).mesh, None);
+
+        scene.update_world_transforms();
+        let origin = point3(0.0, 0.0, 0.0);
+        assert_abs_diff_eq!(scene.node(child).world().transform_point(origin), point3(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(
+            scene.node(leaf).world().transform_point(point3(0.0, 0.0, 1.0)),
+            point3(3.0, 0.0, 0.0),
+            epsilon = 1e-5,
+        );
+    }
+
+    #[test]
+    fn loads_vertex_attributes() {
+        let model = load_gltf(fixture("hierarchy.gltf")).unwrap();
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.indices, vec![2, 1, 0]);
+        let vertex = mesh.vertices[1];
+        assert_eq!(vertex.position, vec3(1.0, 0.0, 0.0));
+        assert_eq!(vertex.normal, vec3(0.0, 0.0, 1.0));
+        assert_eq!(vertex.tangent, vec4(1.0, 0.0, 0.0, -1.0));
+        assert_eq!(vertex.uv0, vec2(1.0, 0.0));
+        assert_eq!(vertex.uv1, vec2(0.5, 0.5));
+        assert_eq!(vertex.color, vec4(0.0, 1.0, 0.0, 0.5));
+
+        // No normals or indices: flat normals and sequential indices.
+        let mesh = &model.meshes[1];
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        for vertex in &mesh.vertices {
+            assert_abs_diff_eq!(vertex.normal, vec3(1.0, 0.0, 0.0));
+            assert_eq!(vertex.color, vec4(1.0, 1.0, 1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn loads_materials_and_textures() {
+        let model = load_gltf(fixture("hierarchy.gltf")).unwrap();
+
+        // The JPEG texture is skipped.
+        assert_eq!(model.textures.len(), 2);
+        let embedded = &model.textures[0];
+        assert_eq!((embedded.width, embedded.height), (2, 2));
+        assert_eq!(&embedded.pixels[..8], &[255, 0, 0, 255, 0, 255, 0, 128]);
+        assert_eq!(embedded.sampler, SamplerInfo {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT,
+        });
+        // The external RGB image is expanded to RGBA.
+        let external = &model.textures[1];
+        assert_eq!(external.pixels, vec![10, 20, 30, 255, 40, 50, 60, 255]);
+        assert_eq!(external.sampler, SamplerInfo::default());
+
+        let material = &model.materials[0];
+        assert_eq!(material.name, "textured");
+        assert_eq!(material.base_color_factor, vec4(1.0, 0.5, 0.25, 1.0));
+        assert_eq!(material.base_color_texture, Some(TextureSlot { texture: TextureId(0), uv_set: 1 }));
+        assert_eq!(material.metallic_factor, 0.25);
+        assert_eq!(material.roughness_factor, 0.75);
+        assert_eq!(material.normal_texture, Some(TextureSlot { texture: TextureId(1), uv_set: 0 }));
+        assert_eq!(material.normal_scale, 0.5);
+        assert_eq!(material.occlusion_texture, None);
+        assert_eq!(material.emissive_factor, vec3(1.0, 1.0, 0.0));
+        assert_eq!(material.alpha_mode, AlphaMode::Mask { cutoff: 0.3 });
+        assert!(material.double_sided);
+
+        let material = &model.materials[1];
+        assert_eq!(material.base_color_texture, None);
+        assert_eq!(material.metallic_factor, 1.0);
+    }
+
+    #[test]
+    fn loads_glb_with_embedded_image() {
+        let bytes = std::fs::read(fixture("quad.glb")).unwrap();
+        // Everything is embedded, so no base directory is needed.
+        let model = parse_gltf(&bytes, None).unwrap();
+
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].vertices.len(), 4);
+        assert_eq!(model.meshes[0].indices, vec![0, 1, 2, 2, 3, 0]);
+        assert_eq!(model.textures.len(), 1);
+        assert_eq!(model.textures[0].pixels.len(), 2 * 2 * 4);
+        assert_eq!(model.materials[0].base_color_texture.map(|s| s.texture), Some(TextureId(0)));
+        assert_eq!(model.scene.node(model.scene.find("quad").unwrap()).mesh, Some(MeshId(0)));
+    }
+
+    #[test]
+    fn loads_external_buffer() {
+        let model = load_gltf(fixture("triangle.gltf")).unwrap();
+        assert_eq!(model.meshes[0].vertices[2].position, vec3(0.0, 1.0, 0.0));
+
+        let bytes = std::fs::read(fixture("triangle.gltf")).unwrap();
+        assert!(parse_gltf(&bytes, None).is_err());
+    }
+
+    #[test]
+    fn loads_punctual_lights() {
+        let model = load_gltf(fixture("triangle.gltf")).unwrap();
+
+        let lamp = model.scene.find("lamp").unwrap();
+        assert_eq!(model.scene.node(lamp).light, Some(Light {
+            kind: LightKind::Spot { inner_cone: Rad(0.25), outer_cone: Rad(0.5) },
+            color: vec3(1.0, 0.5, 0.25),
+            intensity: 20.0,
+            range: Some(5.0),
+            cast_shadows: true,
+        }));
+        assert_eq!(model.scene.node(model.scene.find("triangle").unwrap()).light, None);
+    }
+}
@@ -0,0 +1,16 @@
+pub mod gltf;
+
+use crate::material::MaterialData;
+use crate::mesh::MeshData;
+use crate::scene::Scene;
+use crate::texture::TextureData;
+
+/// Meshes, materials and textures loaded from a file, and the scene that
+/// places them. The ids in the scene and materials index into these lists.
+#[derive(Clone, Debug, Default)]
+pub struct Model {
+    pub scene: Scene,
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub textures: Vec<TextureData>,
+}
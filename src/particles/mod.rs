@@ -0,0 +1,267 @@
+use anyhow::Result;
+
+use std::f32::consts::PI;
+use std::mem::size_of;
+
+use cgmath::{vec2, vec4};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::{create_device_local_buffer, slice_as_bytes, UploadTarget};
+use crate::core::debug_utils::set_object_name;
+use crate::descriptor;
+use crate::pipeline::compute;
+use crate::pipeline::graphics::{create_graphics_pipeline, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range, PushConstants};
+
+type Vec2 = cgmath::Vector2<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+pub const PARTICLE_COUNT: u32 = 8192;
+
+/// Must match `local_size_x` in `particle.comp`.
+pub const PARTICLE_WORKGROUP_SIZE: u32 = 256;
+
+/// A particle as laid out in the storage buffer (std430) and read as a vertex.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Particle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub color: Vec4,
+}
+
+impl Particle {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Particle>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        let position = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(0)
+            .build();
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((size_of::<Vec2>() * 2) as u32)
+            .build();
+        [position, color]
+    }
+}
+
+/// Matches the push constant block in `particle.comp`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SimulationPushConstants {
+    delta_time: f32,
+    count: u32,
+}
+
+const _: () = assert!(size_of::<SimulationPushConstants>() == 8);
+
+// SAFETY: `#[repr(C)]` and an `f32` and a `u32`, both 4 bytes, so there is no padding.
+unsafe impl PushConstants for SimulationPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::COMPUTE;
+}
+
+/// A storage buffer of particles that a compute shader moves every frame and
+/// the graphics pipeline then draws as points.
+#[derive(Clone, Debug, Default)]
+pub struct ParticleSystem {
+    pub count: u32,
+    pub buffer: vk::Buffer,
+    pub buffer_memory: vk::DeviceMemory,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub compute_pipeline_layout: vk::PipelineLayout,
+    pub compute_pipeline: vk::Pipeline,
+    pub graphics_pipeline_layout: vk::PipelineLayout,
+    pub graphics_pipeline: vk::Pipeline,
+}
+
+/// Particles on a golden-angle spiral moving outwards, colored by angle.
+pub fn initial_particles(count: u32) -> Vec<Particle> {
+    let golden_angle = PI * (3.0 - 5.0f32.sqrt());
+    (0..count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let angle = i as f32 * golden_angle;
+            let (sin, cos) = angle.sin_cos();
+            let radius = 0.25 * t.sqrt();
+            let speed = 0.1 + 0.2 * t;
+            Particle {
+                position: vec2(cos, sin) * radius,
+                velocity: vec2(cos, sin) * speed,
+                color: vec4(0.5 + 0.5 * cos, 0.5 + 0.5 * sin, 1.0 - t, 1.0),
+            }
+        })
+        .collect()
+}
+
+pub unsafe fn create_particle_system(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let mut system = ParticleSystem { count: PARTICLE_COUNT, ..Default::default() };
+
+    // Storage buffer, also bound as a vertex buffer when drawing.
+    let particles = initial_particles(system.count);
+    (system.buffer, system.buffer_memory) = create_device_local_buffer(
+        instance,
+        device,
+        data,
+        // SAFETY: `Particle` is `#[repr(C)]` and all `f32`s, so it has no padding.
+        slice_as_bytes(&particles),
+        vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        ),
+    )?;
+
+    // Descriptors
+    let bindings = &[descriptor::storage_buffer_binding(0, vk::ShaderStageFlags::COMPUTE)];
+    system.descriptor_set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+
+    let sizes = &[vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .build()];
+    system.descriptor_pool = descriptor::create_descriptor_pool(device, sizes, 1)?;
+    system.descriptor_set = descriptor::allocate_descriptor_set(
+        device,
+        system.descriptor_pool,
+        system.descriptor_set_layout,
+    )?;
+    descriptor::write_storage_buffer(device, system.descriptor_set, 0, system.buffer);
+
+    // Simulation
+    let push_constant_range = push_constant_range::<SimulationPushConstants>(instance, data)?;
+    (system.compute_pipeline, system.compute_pipeline_layout) = compute::create_compute_pipeline(
+        device,
+        &include_bytes!("../shaders/particle_comp.spv")[..],
+        &[system.descriptor_set_layout],
+        &[push_constant_range],
+    )?;
+
+    // Drawing
+    let layout_info = vk::PipelineLayoutCreateInfo::builder();
+    system.graphics_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let bindings = &[Particle::binding_description()];
+    let attributes = &Particle::attribute_descriptions();
+    let info = GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        topology: vk::PrimitiveTopology::POINT_LIST,
+        cull_mode: vk::CullModeFlags::NONE,
+        samples: data.msaa_samples,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/particle_vert.spv")[..],
+            &include_bytes!("../shaders/particle_frag.spv")[..],
+            system.graphics_pipeline_layout,
+            data.render_pass,
+        )
+    };
+    system.graphics_pipeline = create_graphics_pipeline(device, &info)?;
+
+    data.particles = system;
+
+    let system = &data.particles;
+    set_object_name(device, data, system.buffer, "particles");
+    set_object_name(device, data, system.compute_pipeline_layout, "particle simulation pipeline layout");
+    set_object_name(device, data, system.compute_pipeline, "particle simulation pipeline");
+    set_object_name(device, data, system.graphics_pipeline_layout, "particle pipeline layout");
+    set_object_name(device, data, system.graphics_pipeline, "particle pipeline");
+
+    Ok(())
+}
+
+/// Records the simulation step. Must be recorded outside of a render pass.
+pub unsafe fn cmd_simulate(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    system: &ParticleSystem,
+    delta_time: f32,
+) {
+    // The previous frame's vertex fetch must finish before the buffer is
+    // overwritten (write-after-read, so an execution dependency suffices).
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::VERTEX_INPUT,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, system.compute_pipeline);
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::COMPUTE,
+        system.compute_pipeline_layout,
+        0,
+        &[system.descriptor_set],
+        &[],
+    );
+
+    let push_constants = SimulationPushConstants { delta_time, count: system.count };
+    cmd_push_constants(device, command_buffer, system.compute_pipeline_layout, &push_constants);
+
+    compute::cmd_dispatch_items(device, command_buffer, system.count, PARTICLE_WORKGROUP_SIZE);
+
+    // Make the shader writes visible to the vertex fetch in the render pass.
+    let barrier = vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(system.buffer)
+        .offset(0)
+        .size(vk::WHOLE_SIZE as vk::DeviceSize);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::VERTEX_INPUT,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[barrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+}
+
+/// Records drawing the particles as points. Must be recorded inside the render pass.
+pub unsafe fn cmd_draw(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    system: &ParticleSystem,
+) {
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, system.graphics_pipeline);
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[system.buffer], &[0]);
+    device.cmd_draw(command_buffer, system.count, 1, 0, 0);
+}
+
+pub unsafe fn destroy_particle_system(device: &Device, system: &mut ParticleSystem) {
+    device.destroy_pipeline(system.graphics_pipeline, None);
+    device.destroy_pipeline_layout(system.graphics_pipeline_layout, None);
+    device.destroy_pipeline(system.compute_pipeline, None);
+    device.destroy_pipeline_layout(system.compute_pipeline_layout, None);
+    device.destroy_descriptor_pool(system.descriptor_pool, None);
+    device.destroy_descriptor_set_layout(system.descriptor_set_layout, None);
+    device.destroy_buffer(system.buffer, None);
+    device.free_memory(system.buffer_memory, None);
+    *system = ParticleSystem::default();
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+
+use vulkanalia::prelude::v1_4::*;
+
+use crate::pipeline::shaders;
+
+
+/// Creates a compute pipeline and its layout from SPIR-V bytecode.
+pub unsafe fn create_compute_pipeline(
+    device: &Device,
+    bytecode: &[u8],
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> Result<(vk::Pipeline, vk::PipelineLayout)> {
+    let shader_module = shaders::create_shader_module(device, bytecode)?;
+
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    let layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(b"main\0");
+
+    let info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage)
+        .layout(layout)
+        .base_pipeline_handle(vk::Pipeline::null())
+        .base_pipeline_index(-1);
+
+    let pipeline = device.create_compute_pipelines(
+        vk::PipelineCache::null(),
+        &[info],
+        None,
+    )?.0[0];
+
+    device.destroy_shader_module(shader_module, None);
+    Ok((pipeline, layout))
+}
+
+/// Number of workgroups of `local_size` invocations needed to cover `items`.
+pub fn workgroup_count(items: u32, local_size: u32) -> u32 {
+    items.div_ceil(local_size)
+}
+
+/// Workgroup counts covering a 2D `extent` with `local_size` (x, y) workgroups.
+pub fn workgroup_count_2d(extent: vk::Extent2D, local_size: (u32, u32)) -> (u32, u32) {
+    (
+        workgroup_count(extent.width, local_size.0),
+        workgroup_count(extent.height, local_size.1),
+    )
+}
+
+/// Dispatches enough `local_size` workgroups to cover `items` invocations.
+///
+/// The shader is expected to bounds check against `items` itself.
+pub unsafe fn cmd_dispatch_items(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    items: u32,
+    local_size: u32,
+) {
+    device.cmd_dispatch(command_buffer, workgroup_count(items, local_size), 1, 1);
+}
+
+/// Dispatches enough `local_size` workgroups to cover every pixel of `extent`.
+pub unsafe fn cmd_dispatch_extent(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+    local_size: (u32, u32),
+) {
+    let (x, y) = workgroup_count_2d(extent, local_size);
+    device.cmd_dispatch(command_buffer, x, y, 1);
+}
@@ -1,29 +1,163 @@
 use anyhow::{Result};
 
+use std::mem::size_of;
+
 use vulkanalia::prelude::v1_4::*;
 
 use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_name;
+use crate::pipeline::push_constants::{push_constant_range, PushConstants};
 use crate::pipeline::shaders;
 
+type Mat4 = cgmath::Matrix4<f32>;
+
+/// Per-draw data for `create_pipeline`, pushed instead of written to descriptors.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ObjectPushConstants {
+    pub model: Mat4,
+    pub material_index: u32,
+}
+
+const _: () = assert!(size_of::<ObjectPushConstants>() == 68);
+
+// SAFETY: `#[repr(C)]` and `Mat4` is all `f32`s and is followed by a `u32`, both 4-byte aligned, so there is no padding.
+unsafe impl PushConstants for ObjectPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_bits_truncate(
+        vk::ShaderStageFlags::VERTEX.bits() | vk::ShaderStageFlags::FRAGMENT.bits(),
+    );
+}
+
+/// Offsets written depths by `constant_factor` times the smallest resolvable
+/// depth difference plus `slope_factor` times the polygon's depth slope, e.g.
+/// to keep surfaces from shadowing themselves.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+    /// Anything but 0 needs the `depthBiasClamp` feature.
+    pub clamp: f32,
+}
+
+/// How a pipeline's colors are combined with what the attachment holds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites it.
+    #[default]
+    Opaque,
+    /// `src * src.a + dst * (1 - src.a)`, for colors with straight alpha.
+    Alpha,
+}
+
+/// The parts of a graphics pipeline that differ between our pipelines.
+///
+/// Viewport and scissor are dynamic state, so pipelines built from this
+/// survive swapchain recreation as long as the render pass stays compatible.
+#[derive(Clone, Debug)]
+pub struct GraphicsPipelineInfo<'a> {
+    pub vert: &'a [u8],
+    /// Empty for pipelines without a fragment stage, e.g. depth only.
+    pub frag: &'a [u8],
+    pub vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    pub vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    pub topology: vk::PrimitiveTopology,
+    /// Anything but `FILL` needs the `fillModeNonSolid` feature.
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+    pub depth_bias: Option<DepthBias>,
+    pub blend_mode: BlendMode,
+    /// How many color attachments the subpass has.
+    pub color_attachment_count: u32,
+    /// Must match the render pass's attachments, e.g. `AppData::msaa_samples`
+    /// for the scene pass.
+    pub samples: vk::SampleCountFlags,
+    pub layout: vk::PipelineLayout,
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+}
+
+impl<'a> GraphicsPipelineInfo<'a> {
+    pub fn new(
+        vert: &'a [u8],
+        frag: &'a [u8],
+        layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> Self {
+        Self {
+            vert,
+            frag,
+            vertex_bindings: &[],
+            vertex_attributes: &[],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::CompareOp::LESS,
+            depth_bias: None,
+            blend_mode: BlendMode::Opaque,
+            color_attachment_count: 1,
+            samples: vk::SampleCountFlags::_1,
+            layout,
+            render_pass,
+            subpass: 0,
+        }
+    }
+}
+
 pub unsafe fn create_pipeline(
+    instance: &Instance,
     device: &Device,
     data: &mut AppData
 ) -> Result<()> {
     let vert = include_bytes!("../shaders/vert.spv");
     let frag = include_bytes!("../shaders/frag.spv");
 
-    let vert_shader_module = shaders::create_shader_module(device, &vert[..])?;
-    let frag_shader_module = shaders::create_shader_module(device, &frag[..])?;
+    // Pipeline Layout
+    let push_constant_ranges = &[push_constant_range::<ObjectPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .push_constant_ranges(push_constant_ranges);
+    data.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let info = GraphicsPipelineInfo {
+        samples: data.msaa_samples,
+        ..GraphicsPipelineInfo::new(&vert[..], &frag[..], data.pipeline_layout, data.render_pass)
+    };
+    data.pipeline = create_graphics_pipeline(device, &info)?;
+
+    set_object_name(device, data, data.pipeline_layout, "triangle pipeline layout");
+    set_object_name(device, data, data.pipeline, "triangle pipeline");
+
+    Ok(())
+}
+
+pub unsafe fn create_graphics_pipeline(
+    device: &Device,
+    info: &GraphicsPipelineInfo,
+) -> Result<vk::Pipeline> {
+    let vert_shader_module = shaders::create_shader_module(device, info.vert)?;
+    let frag_shader_module = if info.frag.is_empty() {
+        vk::ShaderModule::null()
+    } else {
+        shaders::create_shader_module(device, info.frag)?
+    };
 
     // =====================
     //  PIPELINE STAGES
     // =====================
     // vertex input state
-    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(info.vertex_bindings)
+        .vertex_attribute_descriptions(info.vertex_attributes);
 
     // input assembly
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(info.topology)
         .primitive_restart_enable(false);
 
     // vertex stage
@@ -32,22 +166,10 @@ pub unsafe fn create_pipeline(
         .module(vert_shader_module)
         .name(b"main\0");
 
-    // Viewport and scissors
-    let viewport = vk::Viewport::builder()
-        .x(0.0)
-        .y(0.0)
-        .width(data.swapchain_extent.width as f32)
-        .height(data.swapchain_extent.height as f32)
-        .min_depth(0.0)
-        .max_depth(1.0);
-    let scissor = vk::Rect2D::builder()
-        .offset(vk::Offset2D { x: 0, y: 0 })
-        .extent(data.swapchain_extent);
-    let viewports = &[viewport];
-    let scissors = &[scissor];
+    // Viewport and scissors (set when recording, see `DYNAMIC_STATES`)
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(viewports)
-        .scissors(scissors);
+        .viewport_count(1)
+        .scissor_count(1);
 
     // fragment stage
     let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
@@ -59,39 +181,58 @@ pub unsafe fn create_pipeline(
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
+        .polygon_mode(info.polygon_mode)
         .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
-        .depth_bias_enable(false);
+        .cull_mode(info.cull_mode)
+        .front_face(info.front_face)
+        .depth_bias_enable(info.depth_bias.is_some());
+    let rasterization_state = match info.depth_bias {
+        Some(bias) => rasterization_state
+            .depth_bias_constant_factor(bias.constant_factor)
+            .depth_bias_slope_factor(bias.slope_factor)
+            .depth_bias_clamp(bias.clamp),
+        None => rasterization_state,
+    };
 
     // Multisampling (AA)
     let multisampling_state = vk::PipelineMultisampleStateCreateInfo::builder()
         .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::_1);
+        .rasterization_samples(info.samples);
+
+    // Depth and stencil
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(info.depth_test)
+        .depth_write_enable(info.depth_write)
+        .depth_compare_op(info.depth_compare_op)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
 
     // Color Blending
+    let (blend_enable, src_color_factor, dst_factor) = match info.blend_mode {
+        BlendMode::Opaque => (false, vk::BlendFactor::ONE, vk::BlendFactor::ZERO),
+        BlendMode::Alpha => (true, vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+    };
     let attachment = vk::PipelineColorBlendAttachmentState::builder()
         .color_write_mask(vk::ColorComponentFlags::all())
-        .blend_enable(false)
-        .src_color_blend_factor(vk::BlendFactor::ONE)
-        .dst_color_blend_factor(vk::BlendFactor::ZERO)
+        .blend_enable(blend_enable)
+        .src_color_blend_factor(src_color_factor)
+        .dst_color_blend_factor(dst_factor)
         .color_blend_op(vk::BlendOp::ADD)
         .src_alpha_blend_factor(vk::BlendFactor::ONE)
-        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(dst_factor)
         .alpha_blend_op(vk::BlendOp::ADD);
-    let attachments = &[attachment];
+    let attachments = vec![attachment; info.color_attachment_count as usize];
     let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
         .logic_op_enable(false)
         .logic_op(vk::LogicOp::COPY)
-        .attachments(attachments)
+        .attachments(&attachments)
         .blend_constants([0.0, 0.0, 0.0, 0.0]);
 
-    // Pipeline Layout
-    let layout_info = vk::PipelineLayoutCreateInfo::builder();
-    data.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    // Dynamic state
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(DYNAMIC_STATES);
 
-    let stages = &[vert_stage, frag_stage];
+    let stages = if frag_shader_module.is_null() { &[vert_stage][..] } else { &[vert_stage, frag_stage][..] };
     let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
         // Shader stages
         .stages(stages)
@@ -101,17 +242,19 @@ pub unsafe fn create_pipeline(
         .viewport_state(&viewport_state)
         .rasterization_state(&rasterization_state)
         .multisample_state(&multisampling_state)
+        .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
         // Pipeline layout
-        .layout(data.pipeline_layout)
+        .layout(info.layout)
         // Render pass
-        .render_pass(data.render_pass)
-        .subpass(0)
+        .render_pass(info.render_pass)
+        .subpass(info.subpass)
         // Parent pipeline (if any)
         .base_pipeline_handle(vk::Pipeline::null())
         .base_pipeline_index(-1);
 
-    data.pipeline = device.create_graphics_pipelines(
+    let pipeline = device.create_graphics_pipelines(
         vk::PipelineCache::null(),
         &[pipeline_info],
         None
@@ -121,5 +264,32 @@ pub unsafe fn create_pipeline(
     // Cleanup (now the shader modiles are not required as it is already loaded into the pipeline)
     device.destroy_shader_module(vert_shader_module, None);
     device.destroy_shader_module(frag_shader_module, None);
-    Ok(())
+    Ok(pipeline)
+}
+
+/// Pipeline state that is set while recording instead of baked into pipelines.
+pub const DYNAMIC_STATES: &[vk::DynamicState] = &[
+    vk::DynamicState::VIEWPORT,
+    vk::DynamicState::SCISSOR,
+];
+
+/// Sets the dynamic viewport and scissor to cover `extent`.
+pub unsafe fn cmd_set_viewport_and_scissor(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+) {
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
 }
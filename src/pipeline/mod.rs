@@ -1,4 +1,5 @@
+pub mod compute;
 pub mod graphics;
+pub mod push_constants;
 pub mod render_pass;
 pub mod shaders;
-
@@ -0,0 +1,74 @@
+use anyhow::{anyhow, Result};
+
+use std::mem::size_of;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+
+
+/// A `#[repr(C)]` struct pushed as one push constant range.
+///
+/// The struct must match the `layout(push_constant)` block of every shader in
+/// `STAGES` byte for byte, starting at `OFFSET`.
+///
+/// # Safety
+///
+/// `as_bytes` views every byte of the struct, so implementors must be
+/// `#[repr(C)]` with no padding bytes; spell any padding out as fields.
+pub unsafe trait PushConstants: Copy + 'static {
+    const STAGES: vk::ShaderStageFlags;
+    const OFFSET: u32 = 0;
+
+    fn range() -> vk::PushConstantRange {
+        vk::PushConstantRange::builder()
+            .stage_flags(Self::STAGES)
+            .offset(Self::OFFSET)
+            .size(size_of::<Self>() as u32)
+            .build()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts((self as *const Self).cast::<u8>(), size_of::<Self>())
+        }
+    }
+}
+
+/// Returns the range for `T`, checked against the device's `maxPushConstantsSize`.
+pub unsafe fn push_constant_range<T: PushConstants>(
+    instance: &Instance,
+    data: &AppData,
+) -> Result<vk::PushConstantRange> {
+    let range = T::range();
+    let max = instance
+        .get_physical_device_properties(data.physical_device)
+        .limits
+        .max_push_constants_size;
+
+    if range.offset % 4 != 0 || range.size % 4 != 0 {
+        return Err(anyhow!(
+            "Push constants `{}` must have an offset and size that are multiples of 4 (offset {}, size {}).",
+            std::any::type_name::<T>(), range.offset, range.size,
+        ));
+    }
+
+    if range.offset + range.size > max {
+        return Err(anyhow!(
+            "Push constants `{}` end at byte {} but the device only supports {}.",
+            std::any::type_name::<T>(), range.offset + range.size, max,
+        ));
+    }
+
+    Ok(range)
+}
+
+/// Records pushing `values` for all of `T::STAGES`.
+pub unsafe fn cmd_push_constants<T: PushConstants>(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    values: &T,
+) {
+    device.cmd_push_constants(command_buffer, layout, T::STAGES, T::OFFSET, values.as_bytes());
+}
@@ -0,0 +1,237 @@
+pub mod processor;
+
+use std::mem::size_of;
+
+use cgmath::{vec4, Vector2};
+use vulkanalia::vk;
+
+use crate::pipeline::push_constants::PushConstants;
+use crate::swapchain::support::OutputEncoding;
+
+type Vec4 = cgmath::Vector4<f32>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// `c / (1 + c)` per channel.
+    Reinhard,
+}
+
+/// A fullscreen pass and its parameters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    /// Scales colors by 2^`stops`.
+    Exposure { stops: f32 },
+    /// Maps HDR colors into [0, 1].
+    Tonemap { operator: Tonemapper },
+    /// Raises colors to `1 / gamma`. The output pass already encodes colors
+    /// for the swapchain, so this is only a creative adjustment.
+    Gamma { gamma: f32 },
+    /// Fast approximate anti-aliasing, best run on tonemapped colors.
+    /// `span_max` is the farthest it blurs in pixels; `reduce_mul` and
+    /// `reduce_min` keep it from blurring along weak edges.
+    Fxaa { span_max: f32, reduce_mul: f32, reduce_min: f32 },
+    /// Darkens by up to `intensity` outside `radius` (from the center, 1 is
+    /// a corner), fading in over `softness`.
+    Vignette { intensity: f32, radius: f32, softness: f32 },
+}
+
+impl PostEffect {
+    pub const NAMES: &'static [&'static str] = &["exposure", "tonemap", "gamma", "fxaa", "vignette"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.shader() - 1]
+    }
+
+    /// The index of the effect's shader; 0 is the output pass.
+    pub(crate) fn shader(&self) -> usize {
+        match self {
+            PostEffect::Exposure { .. } => 1,
+            PostEffect::Tonemap { .. } => 2,
+            PostEffect::Gamma { .. } => 3,
+            PostEffect::Fxaa { .. } => 4,
+            PostEffect::Vignette { .. } => 5,
+        }
+    }
+
+    /// The parameters as read by the effect's shader.
+    pub fn params(&self) -> Vec4 {
+        match *self {
+            PostEffect::Exposure { stops } => vec4(stops.exp2(), 0.0, 0.0, 0.0),
+            PostEffect::Tonemap { operator } => {
+                let operator = match operator {
+                    Tonemapper::Aces => 0.0,
+                    Tonemapper::Reinhard => 1.0,
+                };
+                vec4(operator, 0.0, 0.0, 0.0)
+            }
+            PostEffect::Gamma { gamma } => vec4(1.0 / gamma, 0.0, 0.0, 0.0),
+            PostEffect::Fxaa { span_max, reduce_mul, reduce_min } => vec4(span_max, reduce_mul, reduce_min, 0.0),
+            PostEffect::Vignette { intensity, radius, softness } => vec4(intensity, radius, softness, 0.0),
+        }
+    }
+}
+
+/// An effect in a `PostChain`, skipped while disabled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PostPass {
+    pub effect: PostEffect,
+    pub enabled: bool,
+}
+
+/// Where a pass reads from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PostSource {
+    /// The HDR color the scene was rendered to.
+    Scene,
+    /// One of the two intermediate images.
+    Intermediate(usize),
+}
+
+/// Where a pass writes to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PostTarget {
+    Intermediate(usize),
+    Swapchain,
+}
+
+/// A pass to record: an effect, or the output pass if `None`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PostStep {
+    pub effect: Option<PostEffect>,
+    pub source: PostSource,
+    pub target: PostTarget,
+}
+
+/// The passes that take the scene's HDR color to the swapchain, in order.
+/// Changes apply from the next recorded frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PostChain {
+    pub passes: Vec<PostPass>,
+}
+
+impl PostChain {
+    /// Exposure, ACES tonemapping, FXAA and a vignette, with a disabled gamma
+    /// adjustment.
+    pub fn standard() -> Self {
+        let pass = |effect, enabled| PostPass { effect, enabled };
+        Self {
+            passes: vec![
+                pass(PostEffect::Exposure { stops: 0.0 }, true),
+                pass(PostEffect::Tonemap { operator: Tonemapper::Aces }, true),
+                pass(PostEffect::Fxaa { span_max: 8.0, reduce_mul: 1.0 / 8.0, reduce_min: 1.0 / 128.0 }, true),
+                pass(PostEffect::Vignette { intensity: 0.3, radius: 0.75, softness: 0.5 }, true),
+                pass(PostEffect::Gamma { gamma: 1.0 }, false),
+            ],
+        }
+    }
+
+    /// The first pass with an effect called `name`, see `PostEffect::name`.
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut PostPass> {
+        self.passes.iter_mut().find(|p| p.effect.name() == name)
+    }
+
+    /// The enabled passes ping-ponging between the intermediate images, the
+    /// last one writing to the swapchain. That's the output pass if
+    /// `encode_output` (see `OutputEncoding`) or there are no enabled passes.
+    pub fn steps(&self, encode_output: bool) -> Vec<PostStep> {
+        let mut effects = self.passes.iter().filter(|p| p.enabled).map(|p| Some(p.effect)).collect::<Vec<_>>();
+        if encode_output || effects.is_empty() {
+            effects.push(None);
+        }
+
+        let last = effects.len() - 1;
+        effects
+            .into_iter()
+            .enumerate()
+            .map(|(index, effect)| PostStep {
+                effect,
+                source: if index == 0 { PostSource::Scene } else { PostSource::Intermediate((index - 1) % 2) },
+                target: if index == last { PostTarget::Swapchain } else { PostTarget::Intermediate(index % 2) },
+            })
+            .collect()
+    }
+}
+
+/// The brightness of white in SDR content shown on an HDR display, in nits.
+pub const DEFAULT_PAPER_WHITE_NITS: f32 = 200.0;
+
+/// The output pass's parameters: the encoding and how much to scale colors
+/// by so 1 is `paper_white_nits` bright.
+pub fn output_params(encoding: OutputEncoding, paper_white_nits: f32) -> Vec4 {
+    match encoding {
+        OutputEncoding::Hardware => vec4(0.0, 1.0, 0.0, 0.0),
+        OutputEncoding::Srgb => vec4(1.0, 1.0, 0.0, 0.0),
+        OutputEncoding::Pq => vec4(2.0, paper_white_nits / 10_000.0, 0.0, 0.0),
+        OutputEncoding::ScRgb => vec4(3.0, paper_white_nits / 80.0, 0.0, 0.0),
+    }
+}
+
+/// Per-pass data for the post-processing shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PostPushConstants {
+    /// See `PostEffect::params`.
+    pub params: Vec4,
+    /// The size of a pixel in texture coordinates.
+    pub texel_size: Vector2<f32>,
+    pub _padding: [f32; 2],
+}
+
+const _: () = assert!(size_of::<PostPushConstants>() == 32);
+
+// SAFETY: `#[repr(C)]` and all `f32`s, with the alignment padding spelled out
+// as `_padding`.
+unsafe impl PushConstants for PostPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::FRAGMENT;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_ping_pong_and_end_in_the_swapchain() {
+        let mut chain = PostChain::standard();
+        chain.pass_mut("fxaa").unwrap().enabled = false;
+        chain.pass_mut("gamma").unwrap().enabled = true;
+
+        let steps = chain.steps(false);
+        let names = steps.iter().map(|s| s.effect.unwrap().name()).collect::<Vec<_>>();
+        assert_eq!(names, ["exposure", "tonemap", "vignette", "gamma"]);
+
+        let route = steps.iter().map(|s| (s.source, s.target)).collect::<Vec<_>>();
+        assert_eq!(route, [
+            (PostSource::Scene, PostTarget::Intermediate(0)),
+            (PostSource::Intermediate(0), PostTarget::Intermediate(1)),
+            (PostSource::Intermediate(1), PostTarget::Intermediate(0)),
+            (PostSource::Intermediate(0), PostTarget::Swapchain),
+        ]);
+    }
+
+    #[test]
+    fn encoding_adds_an_output_pass() {
+        let steps = PostChain::standard().steps(true);
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[4], PostStep { effect: None, source: PostSource::Intermediate(1), target: PostTarget::Swapchain });
+        assert_eq!(steps[3].target, PostTarget::Intermediate(1));
+    }
+
+    #[test]
+    fn empty_chain_only_runs_the_output_pass() {
+        let expected = [PostStep { effect: None, source: PostSource::Scene, target: PostTarget::Swapchain }];
+        assert_eq!(PostChain::default().steps(false), expected);
+        assert_eq!(PostChain::default().steps(true), expected);
+    }
+
+    #[test]
+    fn params_are_precomputed_for_shaders() {
+        assert_eq!(PostEffect::Exposure { stops: 2.0 }.params().x, 4.0);
+        assert_eq!(PostEffect::Gamma { gamma: 2.0 }.params().x, 0.5);
+        assert_eq!(PostEffect::Tonemap { operator: Tonemapper::Reinhard }.params().x, 1.0);
+        assert_eq!(output_params(OutputEncoding::ScRgb, 160.0), vec4(3.0, 2.0, 0.0, 0.0));
+        assert_eq!(output_params(OutputEncoding::Pq, 200.0).y, 0.02);
+        assert_eq!(std::mem::size_of::<PostPushConstants>(), 32);
+    }
+}
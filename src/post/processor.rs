@@ -0,0 +1,451 @@
+use anyhow::Result;
+
+use cgmath::vec2;
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::{set_object_name, set_object_names};
+use crate::descriptor;
+use crate::frame::color::HDR_FORMAT;
+use crate::image::{create_image, create_image_view};
+use crate::pipeline::graphics::{self, create_graphics_pipeline, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range};
+use crate::post::{self, PostChain, PostEffect, PostPushConstants, PostSource, PostTarget, DEFAULT_PAPER_WHITE_NITS};
+use crate::swapchain::support::OutputEncoding;
+use crate::texture::{create_sampler, SamplerInfo};
+
+/// The fragment shader of each effect, indexed by `PostEffect::shader`.
+const SHADERS: [&[u8]; 6] = [
+    include_bytes!("../shaders/post_output_frag.spv"),
+    include_bytes!("../shaders/post_exposure_frag.spv"),
+    include_bytes!("../shaders/post_tonemap_frag.spv"),
+    include_bytes!("../shaders/post_gamma_frag.spv"),
+    include_bytes!("../shaders/post_fxaa_frag.spv"),
+    include_bytes!("../shaders/post_vignette_frag.spv"),
+];
+
+/// Runs a `PostChain` of fullscreen passes over the scene's HDR color,
+/// ping-ponging between two intermediate images and ending in the swapchain.
+#[derive(Clone, Debug, Default)]
+pub struct PostProcessor {
+    pub chain: PostChain,
+    /// How bright white is on HDR displays, in nits.
+    pub paper_white_nits: f32,
+    /// The swapchain format and encoding the present pipelines were created for.
+    pub present_format: vk::Format,
+    pub output_encoding: OutputEncoding,
+    /// The input image at binding 0 and a clamped linear sampler at binding 1.
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    /// Reading the scene color, then each intermediate image.
+    pub sets: Vec<vk::DescriptorSet>,
+    pub sampler: vk::Sampler,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub intermediate_render_pass: vk::RenderPass,
+    pub present_render_pass: vk::RenderPass,
+    /// Draws over the finished swapchain image, see `cmd_begin_overlay`.
+    pub overlay_render_pass: vk::RenderPass,
+    /// One pipeline per shader for each render pass.
+    pub intermediate_pipelines: Vec<vk::Pipeline>,
+    pub present_pipelines: Vec<vk::Pipeline>,
+    /// Swapchain-sized targets, recreated with the swapchain.
+    pub intermediate_images: Vec<vk::Image>,
+    pub intermediate_images_memory: Vec<vk::DeviceMemory>,
+    pub intermediate_views: Vec<vk::ImageView>,
+    pub intermediate_framebuffers: Vec<vk::Framebuffer>,
+    /// One per swapchain image.
+    pub present_framebuffers: Vec<vk::Framebuffer>,
+    pub overlay_framebuffers: Vec<vk::Framebuffer>,
+}
+
+/// Overwrites a color attachment of `format` (every pass covers the whole
+/// image) and leaves it in `final_layout`. The dependencies order it after
+/// earlier passes reading or writing the same image, and before later ones
+/// sampling it.
+unsafe fn create_post_render_pass(
+    device: &Device,
+    format: vk::Format,
+    final_layout: vk::ImageLayout,
+) -> Result<vk::RenderPass> {
+    let attachment = vk::AttachmentDescription::builder()
+        .format(format)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(final_layout);
+
+    let attachment_ref = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+    let attachments = &[attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+
+    Ok(device.create_render_pass(&info, None)?)
+}
+
+/// Keeps a presentable color attachment of `format` and draws over it, e.g.
+/// text that shouldn't be tonemapped. Ordered after the present pass.
+unsafe fn create_overlay_render_pass(device: &Device, format: vk::Format) -> Result<vk::RenderPass> {
+    let attachment = vk::AttachmentDescription::builder()
+        .format(format)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+
+    let attachment_ref = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+
+    let dependency = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+
+    let attachments = &[attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[dependency];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+
+    Ok(device.create_render_pass(&info, None)?)
+}
+
+/// A fullscreen triangle running each of `SHADERS`.
+unsafe fn create_post_pipelines(
+    device: &Device,
+    layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> Result<Vec<vk::Pipeline>> {
+    SHADERS
+        .iter()
+        .map(|frag| {
+            let info = GraphicsPipelineInfo {
+                cull_mode: vk::CullModeFlags::NONE,
+                ..GraphicsPipelineInfo::new(
+                    &include_bytes!("../shaders/fullscreen_vert.spv")[..],
+                    frag,
+                    layout,
+                    render_pass,
+                )
+            };
+            create_graphics_pipeline(device, &info)
+        })
+        .collect()
+}
+
+/// Creates the post-processor running `chain`, and its targets. Needs the
+/// scene color attachment and swapchain image views.
+pub unsafe fn create_post_processor(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    chain: PostChain,
+) -> Result<()> {
+    let stages = vk::ShaderStageFlags::FRAGMENT;
+    let bindings = &[
+        descriptor::sampled_image_binding(0, stages),
+        descriptor::sampler_binding(1, stages),
+    ];
+    let set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+
+    // The scene color and two intermediate images.
+    let set_count = 3;
+    let size = |type_| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(set_count)
+            .build()
+    };
+    let sizes = &[size(vk::DescriptorType::SAMPLED_IMAGE), size(vk::DescriptorType::SAMPLER)];
+    let descriptor_pool = descriptor::create_descriptor_pool(device, sizes, set_count)?;
+    let sets = (0..set_count)
+        .map(|_| descriptor::allocate_descriptor_set(device, descriptor_pool, set_layout))
+        .collect::<Result<Vec<_>>>()?;
+
+    let sampler = create_sampler(device, &SamplerInfo {
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        ..SamplerInfo::default()
+    })?;
+    for set in &sets {
+        descriptor::write_sampler(device, *set, 1, sampler);
+    }
+
+    let set_layouts = &[set_layout];
+    let push_constant_ranges = &[push_constant_range::<PostPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    let pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let intermediate_render_pass = create_post_render_pass(device, HDR_FORMAT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+
+    data.post = PostProcessor {
+        chain,
+        paper_white_nits: DEFAULT_PAPER_WHITE_NITS,
+        set_layout,
+        descriptor_pool,
+        sets,
+        sampler,
+        pipeline_layout,
+        intermediate_render_pass,
+        intermediate_pipelines: create_post_pipelines(device, pipeline_layout, intermediate_render_pass)?,
+        ..PostProcessor::default()
+    };
+
+    create_post_targets(instance, device, data)
+}
+
+/// Creates the intermediate images and the framebuffers for them and the
+/// swapchain images, and points the descriptor sets at the current images.
+/// Recreates the present render pass and pipelines if the swapchain format changed.
+pub unsafe fn create_post_targets(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let post = &mut data.post;
+    if post.present_format != data.swapchain_format {
+        post.present_pipelines
+            .drain(..)
+            .for_each(|p| device.destroy_pipeline(p, None));
+        device.destroy_render_pass(post.present_render_pass, None);
+        device.destroy_render_pass(post.overlay_render_pass, None);
+
+        post.present_render_pass = create_post_render_pass(device, data.swapchain_format, vk::ImageLayout::PRESENT_SRC_KHR)?;
+        post.overlay_render_pass = create_overlay_render_pass(device, data.swapchain_format)?;
+        post.present_pipelines = create_post_pipelines(device, post.pipeline_layout, post.present_render_pass)?;
+        post.present_format = data.swapchain_format;
+    }
+    post.output_encoding = data.output_encoding;
+
+    let extent = data.swapchain_extent;
+    let framebuffer = |render_pass, view| {
+        let attachments = &[view];
+        let info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        device.create_framebuffer(&info, None)
+    };
+
+    for _ in 0..2 {
+        let (image, image_memory) = create_image(
+            instance,
+            device,
+            data,
+            extent.width,
+            extent.height,
+            HDR_FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+        let view = create_image_view(device, image, HDR_FORMAT, vk::ImageAspectFlags::COLOR)?;
+
+        let post = &mut data.post;
+        post.intermediate_images.push(image);
+        post.intermediate_images_memory.push(image_memory);
+        post.intermediate_views.push(view);
+        post.intermediate_framebuffers.push(framebuffer(post.intermediate_render_pass, view)?);
+    }
+
+    data.post.present_framebuffers = data
+        .swapchain_image_views
+        .iter()
+        .map(|v| framebuffer(data.post.present_render_pass, *v))
+        .collect::<Result<Vec<_>, _>>()?;
+    data.post.overlay_framebuffers = data
+        .swapchain_image_views
+        .iter()
+        .map(|v| framebuffer(data.post.overlay_render_pass, *v))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let post = &data.post;
+    descriptor::write_sampled_image(device, post.sets[0], 0, data.color_image_view);
+    for (set, view) in post.sets[1..].iter().zip(&post.intermediate_views) {
+        descriptor::write_sampled_image(device, *set, 0, *view);
+    }
+
+    set_post_object_names(device, data);
+
+    Ok(())
+}
+
+unsafe fn set_post_object_names(device: &Device, data: &AppData) {
+    let post = &data.post;
+    set_object_name(device, data, post.pipeline_layout, "post pipeline layout");
+    set_object_name(device, data, post.sampler, "post sampler");
+    set_object_name(device, data, post.intermediate_render_pass, "post intermediate render pass");
+    set_object_name(device, data, post.present_render_pass, "post present render pass");
+    set_object_name(device, data, post.overlay_render_pass, "overlay render pass");
+    set_object_names(device, data, &post.intermediate_images, "post intermediate");
+    set_object_names(device, data, &post.intermediate_views, "post intermediate view");
+    set_object_names(device, data, &post.intermediate_framebuffers, "post intermediate framebuffer");
+    set_object_names(device, data, &post.present_framebuffers, "post present framebuffer");
+    set_object_names(device, data, &post.overlay_framebuffers, "overlay framebuffer");
+
+    let names = std::iter::once("output").chain(PostEffect::NAMES.iter().copied());
+    for (name, (intermediate, present)) in names.zip(post.intermediate_pipelines.iter().zip(&post.present_pipelines)) {
+        set_object_name(device, data, *intermediate, &format!("post {} (intermediate)", name));
+        set_object_name(device, data, *present, &format!("post {} (present)", name));
+    }
+}
+
+/// Records the chain's passes from the scene color to swapchain image
+/// `image_index`. Must be recorded after the scene's render pass.
+pub unsafe fn cmd_post_process(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    post: &PostProcessor,
+    image_index: usize,
+    extent: vk::Extent2D,
+) {
+    let render_area = vk::Rect2D::builder()
+        .offset(vk::Offset2D::default())
+        .extent(extent);
+    let texel_size = vec2(1.0 / extent.width as f32, 1.0 / extent.height as f32);
+
+    let encode_output = post.output_encoding != OutputEncoding::Hardware;
+    for step in post.chain.steps(encode_output) {
+        let (render_pass, framebuffer, pipelines) = match step.target {
+            PostTarget::Intermediate(i) => (
+                post.intermediate_render_pass,
+                post.intermediate_framebuffers[i],
+                &post.intermediate_pipelines,
+            ),
+            PostTarget::Swapchain => (
+                post.present_render_pass,
+                post.present_framebuffers[image_index],
+                &post.present_pipelines,
+            ),
+        };
+        let set = match step.source {
+            PostSource::Scene => post.sets[0],
+            PostSource::Intermediate(i) => post.sets[1 + i],
+        };
+        let (shader, params) = match step.effect {
+            Some(effect) => (effect.shader(), effect.params()),
+            None => (0, post::output_params(post.output_encoding, post.paper_white_nits)),
+        };
+
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(framebuffer)
+            .render_area(render_area);
+        device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+        graphics::cmd_set_viewport_and_scissor(device, command_buffer, extent);
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipelines[shader]);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            post.pipeline_layout,
+            0,
+            &[set],
+            &[],
+        );
+        let push_constants = PostPushConstants { params, texel_size, _padding: [0.0; 2] };
+        cmd_push_constants(device, command_buffer, post.pipeline_layout, &push_constants);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        device.cmd_end_render_pass(command_buffer);
+    }
+}
+
+/// Begins the overlay render pass on swapchain image `image_index`, with the
+/// viewport covering it. Must be recorded after `cmd_post_process`.
+pub unsafe fn cmd_begin_overlay(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    post: &PostProcessor,
+    image_index: usize,
+    extent: vk::Extent2D,
+) {
+    let render_area = vk::Rect2D::builder()
+        .offset(vk::Offset2D::default())
+        .extent(extent);
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(post.overlay_render_pass)
+        .framebuffer(post.overlay_framebuffers[image_index])
+        .render_area(render_area);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    graphics::cmd_set_viewport_and_scissor(device, command_buffer, extent);
+}
+
+pub unsafe fn destroy_post_targets(device: &Device, post: &mut PostProcessor) {
+    post.present_framebuffers
+        .drain(..)
+        .chain(post.overlay_framebuffers.drain(..))
+        .for_each(|f| device.destroy_framebuffer(f, None));
+    post.intermediate_framebuffers
+        .drain(..)
+        .for_each(|f| device.destroy_framebuffer(f, None));
+    post.intermediate_views
+        .drain(..)
+        .for_each(|v| device.destroy_image_view(v, None));
+    post.intermediate_images
+        .drain(..)
+        .for_each(|i| device.destroy_image(i, None));
+    post.intermediate_images_memory
+        .drain(..)
+        .for_each(|m| device.free_memory(m, None));
+}
+
+/// Destroys the post-processor, whose targets must already be destroyed.
+pub unsafe fn destroy_post_processor(device: &Device, post: &mut PostProcessor) {
+    post.intermediate_pipelines
+        .iter()
+        .chain(&post.present_pipelines)
+        .for_each(|p| device.destroy_pipeline(*p, None));
+    device.destroy_render_pass(post.overlay_render_pass, None);
+    device.destroy_render_pass(post.present_render_pass, None);
+    device.destroy_render_pass(post.intermediate_render_pass, None);
+    device.destroy_pipeline_layout(post.pipeline_layout, None);
+    device.destroy_sampler(post.sampler, None);
+    device.destroy_descriptor_pool(post.descriptor_pool, None);
+    device.destroy_descriptor_set_layout(post.set_layout, None);
+    *post = PostProcessor::default();
+}
@@ -0,0 +1,200 @@
+use anyhow::{anyhow, Result};
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// How many frames the statistics are taken over.
+pub const FRAME_STATS_WINDOW: usize = 240;
+
+/// How often the window title is updated with the statistics.
+pub const TITLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Where a frame's time on the CPU went, in milliseconds.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameTiming {
+    pub frame: u64,
+    /// Since the previous frame began.
+    pub frame_ms: f64,
+    /// Spent rendering the frame, including the waits below.
+    pub cpu_ms: f64,
+    /// Waiting for the frame in flight's fence and acquiring an image.
+    pub acquire_ms: f64,
+    pub submit_ms: f64,
+    pub present_ms: f64,
+}
+
+impl FrameTiming {
+    pub const CSV_HEADER: &'static str = "frame,frame_ms,cpu_ms,acquire_ms,submit_ms,present_ms";
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{:.4},{:.4},{:.4},{:.4},{:.4}",
+            self.frame, self.frame_ms, self.cpu_ms, self.acquire_ms, self.submit_ms, self.present_ms,
+        )
+    }
+}
+
+/// The distribution of a timing over a window of frames.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Summary {
+    /// `None` if there are no samples. Percentiles are nearest-rank.
+    pub fn of(samples: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut sorted = samples.into_iter().collect::<Vec<_>>();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+
+        let percentile = |p: f64| {
+            let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some(Self {
+            min: sorted[0],
+            avg: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            max: sorted[sorted.len() - 1],
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+        })
+    }
+}
+
+/// Frame timings over the last `FRAME_STATS_WINDOW` frames, and optionally
+/// every frame for writing to CSV.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    recent: VecDeque<FrameTiming>,
+    /// Every frame since the app started, if they're being kept.
+    pub all: Option<Vec<FrameTiming>>,
+    next_frame: u64,
+}
+
+impl FrameStats {
+    /// Keeps every frame if `keep_all`, e.g. for `to_csv`.
+    pub fn new(keep_all: bool) -> Self {
+        Self { all: keep_all.then(Vec::new), ..Self::default() }
+    }
+
+    /// Adds a frame, numbering it.
+    pub fn push(&mut self, mut timing: FrameTiming) {
+        timing.frame = self.next_frame;
+        self.next_frame += 1;
+
+        if self.recent.len() == FRAME_STATS_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(timing);
+        if let Some(all) = &mut self.all {
+            all.push(timing);
+        }
+    }
+
+    /// The distribution of `field` over recent frames.
+    pub fn summary(&self, field: impl Fn(&FrameTiming) -> f64) -> Option<Summary> {
+        Summary::of(self.recent.iter().map(field))
+    }
+
+    /// A line for the window title, e.g.
+    /// `60.0 fps, 16.67 ms (min 16.1, p99 17.9, max 18.4), cpu 1.20 ms, ...`.
+    pub fn display(&self) -> String {
+        let Some(frame) = self.summary(|t| t.frame_ms) else { return String::new() };
+        let mut text = format!(
+            "{:.1} fps, {:.2} ms (min {:.1}, p99 {:.1}, max {:.1})",
+            if frame.avg > 0.0 { 1000.0 / frame.avg } else { 0.0 },
+            frame.avg,
+            frame.min,
+            frame.p99,
+            frame.max,
+        );
+        let stages = [
+            ("cpu", self.summary(|t| t.cpu_ms)),
+            ("acquire", self.summary(|t| t.acquire_ms)),
+            ("submit", self.summary(|t| t.submit_ms)),
+            ("present", self.summary(|t| t.present_ms)),
+        ];
+        for (name, summary) in stages {
+            if let Some(summary) = summary {
+                let _ = write!(text, ", {} {:.2} ms", name, summary.avg);
+            }
+        }
+        text
+    }
+
+    /// Every kept frame as CSV, with a header row.
+    pub fn to_csv(&self) -> Option<String> {
+        let all = self.all.as_ref()?;
+        let mut csv = String::from(FrameTiming::CSV_HEADER);
+        csv.push('\n');
+        for timing in all {
+            csv.push_str(&timing.csv_row());
+            csv.push('\n');
+        }
+        Some(csv)
+    }
+
+    pub fn save_csv(&self, path: &str) -> Result<()> {
+        let csv = self.to_csv().ok_or_else(|| anyhow!("Frame timings weren't kept."))?;
+        std::fs::write(path, csv)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(frame_ms: f64) -> FrameTiming {
+        FrameTiming { frame_ms, cpu_ms: frame_ms / 2.0, ..FrameTiming::default() }
+    }
+
+    #[test]
+    fn summary_has_nearest_rank_percentiles() {
+        let summary = Summary::of((1..=100).rev().map(f64::from)).unwrap();
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.max, 100.0);
+        assert_eq!(summary.avg, 50.5);
+        assert_eq!(summary.p50, 50.0);
+        assert_eq!(summary.p95, 95.0);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(Summary::of([7.0]).unwrap().p99, 7.0);
+        assert_eq!(Summary::of([]), None);
+    }
+
+    #[test]
+    fn window_slides_but_all_frames_are_kept() {
+        let mut stats = FrameStats::new(true);
+        for i in 0..FRAME_STATS_WINDOW + 10 {
+            stats.push(timing(if i < 10 { 100.0 } else { 10.0 }));
+        }
+        assert_eq!(stats.summary(|t| t.frame_ms).unwrap().max, 10.0);
+        assert_eq!(stats.all.as_ref().unwrap().len(), FRAME_STATS_WINDOW + 10);
+        assert!(stats.display().starts_with("100.0 fps, 10.00 ms"), "{}", stats.display());
+        assert!(stats.display().contains("cpu 5.00 ms"), "{}", stats.display());
+    }
+
+    #[test]
+    fn csv_has_a_row_per_frame() {
+        let mut stats = FrameStats::new(true);
+        stats.push(timing(16.0));
+        stats.push(FrameTiming { present_ms: 0.25, ..timing(17.0) });
+        let csv = stats.to_csv().unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines, [
+            FrameTiming::CSV_HEADER,
+            "0,16.0000,8.0000,0.0000,0.0000,0.0000",
+            "1,17.0000,8.5000,0.0000,0.0000,0.2500",
+        ]);
+        assert_eq!(FrameStats::new(false).to_csv(), None);
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::Result;
+use log::*;
+
+use std::collections::VecDeque;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_names;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::profiler::{timestamp_delta_ms, FrameTimings, ScopeAverages, ScopeTiming, AVERAGE_FRAMES, TRACE_FRAMES};
+
+/// How many scopes a frame can have, including the frame itself. Scopes past
+/// this aren't timed.
+pub const MAX_SCOPES: u32 = 32;
+
+/// The scope covering a whole frame.
+pub const FRAME_SCOPE: &str = "frame";
+
+/// The timestamps recorded for a frame in flight. Scope `i` writes queries
+/// `2i` (begin) and `2i + 1` (end).
+#[derive(Clone, Debug, Default)]
+pub struct FrameQueries {
+    pub pool: vk::QueryPool,
+    frame: u64,
+    /// Names and depths.
+    scopes: Vec<(&'static str, u32)>,
+    /// Indices into `scopes`, `None` for scopes past `MAX_SCOPES`.
+    open: Vec<Option<usize>>,
+    /// Whether the queries were recorded and haven't been read back.
+    pending: bool,
+}
+
+/// Times scopes of each frame's commands with timestamp queries.
+///
+/// Results are read back when a frame in flight's slot comes around again,
+/// after its fence was waited on, so reading never stalls the GPU. Timings
+/// therefore lag `MAX_FRAMES_IN_FLIGHT` frames behind.
+#[derive(Clone, Debug, Default)]
+pub struct GpuProfiler {
+    /// Whether the graphics queue supports timestamps.
+    pub supported: bool,
+    /// Records timestamps while true (and supported).
+    pub enabled: bool,
+    /// Nanoseconds per timestamp tick.
+    pub period_ns: f32,
+    /// How many low bits of a timestamp are meaningful.
+    pub valid_bits: u32,
+    pub frames: Vec<FrameQueries>,
+    /// The frame in flight being recorded.
+    current: Option<usize>,
+    next_frame: u64,
+    /// The first frame's begin timestamp, which trace times are relative to.
+    origin: Option<u64>,
+    /// The last `TRACE_FRAMES` frames read back, oldest first.
+    pub history: VecDeque<FrameTimings>,
+    pub averages: ScopeAverages,
+}
+
+pub unsafe fn create_gpu_profiler(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let properties = instance.get_physical_device_properties(data.physical_device);
+    let families = instance.get_physical_device_queue_family_properties(data.physical_device);
+    let valid_bits = families
+        .get(data.queue_families.graphics as usize)
+        .map(|f| f.timestamp_valid_bits)
+        .unwrap_or(0);
+
+    let mut profiler = GpuProfiler {
+        supported: valid_bits > 0,
+        enabled: valid_bits > 0,
+        period_ns: properties.limits.timestamp_period,
+        valid_bits,
+        averages: ScopeAverages::new(AVERAGE_FRAMES),
+        ..GpuProfiler::default()
+    };
+
+    if profiler.supported {
+        let info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(MAX_SCOPES * 2);
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let pool = device.create_query_pool(&info, None)?;
+            profiler.frames.push(FrameQueries { pool, ..FrameQueries::default() });
+        }
+        debug!("GPU timestamps: {} ns per tick, {} valid bits.", profiler.period_ns, valid_bits);
+    } else {
+        warn!("The graphics queue has no timestamps, so GPU profiling is unavailable.");
+    }
+
+    data.profiler = profiler;
+
+    let pools = data.profiler.frames.iter().map(|f| f.pool).collect::<Vec<_>>();
+    set_object_names(device, data, &pools, "timestamps");
+
+    Ok(())
+}
+
+/// Reads back the timings recorded the last time frame in flight `frame` was
+/// used. Its fence must have been waited on.
+pub unsafe fn read_frame(device: &Device, profiler: &mut GpuProfiler, frame: usize) -> Result<()> {
+    let Some(queries) = profiler.frames.get_mut(frame) else { return Ok(()) };
+    if !queries.pending {
+        return Ok(());
+    }
+    queries.pending = false;
+
+    // Each query's value followed by whether it's available.
+    let count = queries.scopes.len() as u32 * 2;
+    let mut results = vec![0u64; count as usize * 2];
+    let bytes = std::slice::from_raw_parts_mut(results.as_mut_ptr() as *mut u8, results.len() * 8);
+    device.get_query_pool_results(
+        queries.pool,
+        0,
+        count,
+        bytes,
+        16,
+        vk::QueryResultFlags::_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+    )?;
+    let timestamp = |query: usize| {
+        let (value, available) = (results[query * 2], results[query * 2 + 1]);
+        (available != 0).then_some(value)
+    };
+
+    // Scope 0 is the frame, so everything else is relative to it.
+    let Some(frame_begin) = timestamp(0) else { return Ok(()) };
+    let origin = *profiler.origin.get_or_insert(frame_begin);
+    let (period, bits) = (profiler.period_ns, profiler.valid_bits);
+
+    let timings = FrameTimings {
+        frame: queries.frame,
+        start_ms: timestamp_delta_ms(origin, frame_begin, period, bits),
+        scopes: queries
+            .scopes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, depth))| {
+                let (begin, end) = (timestamp(i * 2)?, timestamp(i * 2 + 1)?);
+                Some(ScopeTiming {
+                    name,
+                    depth: *depth,
+                    start_ms: timestamp_delta_ms(frame_begin, begin, period, bits),
+                    duration_ms: timestamp_delta_ms(begin, end, period, bits),
+                })
+            })
+            .collect(),
+    };
+
+    profiler.averages.push_frame(&timings);
+    if profiler.history.len() == TRACE_FRAMES {
+        profiler.history.pop_front();
+    }
+    profiler.history.push_back(timings);
+
+    Ok(())
+}
+
+/// Starts recording frame in flight `frame`'s timestamps and its frame scope.
+/// Must be recorded outside of a render pass, before any other scope.
+pub unsafe fn cmd_begin_frame(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    profiler: &mut GpuProfiler,
+    frame: usize,
+) {
+    profiler.current = None;
+    if !profiler.enabled {
+        return;
+    }
+    let Some(queries) = profiler.frames.get_mut(frame) else { return };
+
+    device.cmd_reset_query_pool(command_buffer, queries.pool, 0, MAX_SCOPES * 2);
+    queries.frame = profiler.next_frame;
+    queries.scopes.clear();
+    queries.open.clear();
+    queries.pending = true;
+    profiler.next_frame += 1;
+    profiler.current = Some(frame);
+
+    cmd_begin_scope(device, command_buffer, profiler, FRAME_SCOPE);
+}
+
+/// Ends the frame scope and any scopes left open.
+pub unsafe fn cmd_end_frame(device: &Device, command_buffer: vk::CommandBuffer, profiler: &mut GpuProfiler) {
+    let Some(frame) = profiler.current else { return };
+    if profiler.frames[frame].open.len() > 1 {
+        warn!("GPU profiler scopes were left open at the end of a frame.");
+    }
+    while !profiler.frames[frame].open.is_empty() {
+        cmd_end_scope(device, command_buffer, profiler);
+    }
+    profiler.current = None;
+}
+
+/// Starts timing a scope, which must be ended with `cmd_end_scope`. Scopes nest.
+pub unsafe fn cmd_begin_scope(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    profiler: &mut GpuProfiler,
+    name: &'static str,
+) {
+    let Some(frame) = profiler.current else { return };
+    let queries = &mut profiler.frames[frame];
+
+    if queries.scopes.len() as u32 >= MAX_SCOPES {
+        queries.open.push(None);
+        return;
+    }
+    let index = queries.scopes.len();
+    queries.scopes.push((name, queries.open.len() as u32));
+    queries.open.push(Some(index));
+    let query = index as u32 * 2;
+    device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, queries.pool, query);
+}
+
+pub unsafe fn cmd_end_scope(device: &Device, command_buffer: vk::CommandBuffer, profiler: &mut GpuProfiler) {
+    let Some(frame) = profiler.current else { return };
+    let queries = &mut profiler.frames[frame];
+
+    if let Some(Some(index)) = queries.open.pop() {
+        let query = index as u32 * 2 + 1;
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, queries.pool, query);
+    }
+}
+
+/// Writes the frames in `history` to `path` as a Chrome trace, see `chrome_trace`.
+pub fn save_trace(profiler: &GpuProfiler, path: &str) -> Result<()> {
+    std::fs::write(path, super::chrome_trace(&profiler.history))?;
+    Ok(())
+}
+
+pub unsafe fn destroy_gpu_profiler(device: &Device, profiler: &mut GpuProfiler) {
+    profiler.frames.iter().for_each(|f| device.destroy_query_pool(f.pool, None));
+    *profiler = GpuProfiler::default();
+}
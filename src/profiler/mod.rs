@@ -0,0 +1,226 @@
+pub mod frame_stats;
+pub mod gpu;
+pub mod statistics;
+
+use std::collections::VecDeque;
+
+/// How many frames of timings are kept for `chrome_trace`.
+pub const TRACE_FRAMES: usize = 600;
+
+/// How many frames the rolling averages are taken over.
+pub const AVERAGE_FRAMES: usize = 60;
+
+/// Converts the time between two timestamps to milliseconds. Only the low
+/// `valid_bits` of a timestamp are meaningful, and they may have wrapped
+/// around between `begin` and `end`.
+pub fn timestamp_delta_ms(begin: u64, end: u64, period_ns: f32, valid_bits: u32) -> f64 {
+    let mask = if valid_bits >= 64 { u64::MAX } else { (1 << valid_bits) - 1 };
+    let ticks = end.wrapping_sub(begin) & mask;
+    ticks as f64 * period_ns as f64 / 1_000_000.0
+}
+
+/// How long a scope took on the GPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeTiming {
+    pub name: &'static str,
+    /// How many scopes it's nested in, 0 for the whole frame.
+    pub depth: u32,
+    /// Since the frame began, in milliseconds.
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+/// A frame's scopes, in the order they began.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameTimings {
+    /// Counts the frames recorded since the profiler was created.
+    pub frame: u64,
+    /// When the frame began on the GPU, in milliseconds since the first
+    /// frame that was read back.
+    pub start_ms: f64,
+    pub scopes: Vec<ScopeTiming>,
+}
+
+/// The average of the last `window` samples.
+#[derive(Clone, Debug, Default)]
+pub struct RollingAverage {
+    window: usize,
+    samples: VecDeque<f64>,
+    sum: f64,
+}
+
+impl RollingAverage {
+    pub fn new(window: usize) -> Self {
+        Self { window: window.max(1), samples: VecDeque::new(), sum: 0.0 }
+    }
+
+    pub fn push(&mut self, sample: f64) {
+        if self.samples.len() == self.window
+            && let Some(oldest) = self.samples.pop_front()
+        {
+            self.sum -= oldest;
+        }
+        self.samples.push_back(sample);
+        self.sum += sample;
+    }
+
+    /// `None` until there's a sample.
+    pub fn average(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(self.sum / self.samples.len() as f64)
+        }
+    }
+}
+
+/// Rolling averages of each scope's duration, in the order the scopes were
+/// first seen. A scope that runs more than once a frame is summed first.
+#[derive(Clone, Debug, Default)]
+pub struct ScopeAverages {
+    window: usize,
+    averages: Vec<(&'static str, RollingAverage)>,
+}
+
+impl ScopeAverages {
+    pub fn new(window: usize) -> Self {
+        Self { window, averages: Vec::new() }
+    }
+
+    pub fn push_frame(&mut self, frame: &FrameTimings) {
+        let mut totals: Vec<(&'static str, f64)> = Vec::new();
+        for scope in &frame.scopes {
+            match totals.iter_mut().find(|(n, _)| *n == scope.name) {
+                Some((_, total)) => *total += scope.duration_ms,
+                None => totals.push((scope.name, scope.duration_ms)),
+            }
+        }
+
+        for (name, total) in totals {
+            let index = match self.averages.iter().position(|(n, _)| *n == name) {
+                Some(index) => index,
+                None => {
+                    self.averages.push((name, RollingAverage::new(self.window)));
+                    self.averages.len() - 1
+                }
+            };
+            self.averages[index].1.push(total);
+        }
+    }
+
+    /// The average duration of the scope called `name`, in milliseconds.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.averages.iter().find(|(n, _)| *n == name).and_then(|(_, a)| a.average())
+    }
+
+    /// Each scope's name and average duration in milliseconds.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, f64)> + '_ {
+        self.averages.iter().filter_map(|(n, a)| a.average().map(|a| (*n, a)))
+    }
+
+    /// E.g. `frame 4.20 ms, shadows 0.81 ms`.
+    pub fn summary(&self) -> String {
+        self.iter()
+            .map(|(name, ms)| format!("{} {:.2} ms", name, ms))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The frames as JSON in the Chrome trace event format, which
+/// `chrome://tracing` and Perfetto open. Each scope is a complete event on
+/// the "GPU" thread.
+pub fn chrome_trace<'a>(frames: impl IntoIterator<Item = &'a FrameTimings>) -> String {
+    let mut events = vec![
+        r#"{"name":"process_name","ph":"M","pid":1,"args":{"name":"vulkan-tutorial"}}"#.to_string(),
+        r#"{"name":"thread_name","ph":"M","pid":1,"tid":1,"args":{"name":"GPU"}}"#.to_string(),
+    ];
+    for frame in frames {
+        for scope in &frame.scopes {
+            events.push(format!(
+                r#"{{"name":"{}","cat":"gpu","ph":"X","pid":1,"tid":1,"ts":{:.3},"dur":{:.3},"args":{{"frame":{}}}}}"#,
+                escape_json(scope.name),
+                (frame.start_ms + scope.start_ms) * 1000.0,
+                scope.duration_ms * 1000.0,
+                frame.frame,
+            ));
+        }
+    }
+    format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n", events.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(name: &'static str, depth: u32, start_ms: f64, duration_ms: f64) -> ScopeTiming {
+        ScopeTiming { name, depth, start_ms, duration_ms }
+    }
+
+    #[test]
+    fn timestamps_are_masked_and_may_wrap() {
+        assert_eq!(timestamp_delta_ms(1_000, 3_000, 1.0, 64), 0.002);
+        assert_eq!(timestamp_delta_ms(0, 1_000_000, 2.5, 64), 2.5);
+        // 36 valid bits: the end wrapped around past zero.
+        let max = (1u64 << 36) - 1;
+        assert_eq!(timestamp_delta_ms(max - 499_999, 500_000, 1.0, 36), 1.0);
+        // Junk above the valid bits is ignored.
+        assert_eq!(timestamp_delta_ms(0xff00_0000_0000_0000, 1_000_000, 1.0, 36), 1.0);
+    }
+
+    #[test]
+    fn rolling_average_forgets_old_samples() {
+        let mut average = RollingAverage::new(2);
+        assert_eq!(average.average(), None);
+        average.push(1.0);
+        average.push(3.0);
+        assert_eq!(average.average(), Some(2.0));
+        average.push(5.0);
+        assert_eq!(average.average(), Some(4.0));
+    }
+
+    #[test]
+    fn scope_averages_sum_repeated_scopes() {
+        let mut averages = ScopeAverages::new(AVERAGE_FRAMES);
+        averages.push_frame(&FrameTimings {
+            frame: 0,
+            start_ms: 0.0,
+            scopes: vec![scope("frame", 0, 0.0, 4.0), scope("shadows", 1, 0.5, 1.0), scope("shadows", 1, 2.0, 0.5)],
+        });
+        averages.push_frame(&FrameTimings {
+            frame: 1,
+            start_ms: 16.0,
+            scopes: vec![scope("frame", 0, 0.0, 6.0), scope("shadows", 1, 0.5, 0.5)],
+        });
+        assert_eq!(averages.get("frame"), Some(5.0));
+        assert_eq!(averages.get("shadows"), Some(1.0));
+        assert_eq!(averages.summary(), "frame 5.00 ms, shadows 1.00 ms");
+    }
+
+    #[test]
+    fn chrome_trace_has_an_event_per_scope() {
+        let frame = FrameTimings {
+            frame: 7,
+            start_ms: 16.0,
+            scopes: vec![scope("frame", 0, 0.0, 4.0), scope("post \"fx\"", 1, 3.0, 0.25)],
+        };
+        let trace = chrome_trace([&frame]);
+        assert!(trace.starts_with("{\"traceEvents\":["), "{}", trace);
+        assert!(trace.contains(r#""name":"frame","cat":"gpu","ph":"X","pid":1,"tid":1,"ts":16000.000,"dur":4000.000,"args":{"frame":7}"#), "{}", trace);
+        assert!(trace.contains(r#""name":"post \"fx\"""#), "{}", trace);
+        assert!(trace.contains(r#""ts":19000.000,"dur":250.000"#), "{}", trace);
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use log::*;
+
+use std::fmt;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_names;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+
+/// The counters queried, in the order Vulkan writes them (by flag bit).
+pub const STATISTICS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_bits_truncate(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.bits()
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.bits()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.bits()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.bits()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.bits()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.bits()
+        | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.bits(),
+);
+
+/// How many values a result has: one per counter, then its availability.
+const RESULT_VALUES: usize = 8;
+
+/// What the GPU did over a frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    pub input_vertices: u64,
+    pub input_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    /// Primitives that reached clipping.
+    pub clipping_invocations: u64,
+    /// Primitives that came out of clipping, which may split them.
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+    pub compute_shader_invocations: u64,
+}
+
+impl PipelineStatistics {
+    /// Reads the counters in `STATISTICS` order.
+    pub fn from_values(values: &[u64; 7]) -> Self {
+        Self {
+            input_vertices: values[0],
+            input_primitives: values[1],
+            vertex_shader_invocations: values[2],
+            clipping_invocations: values[3],
+            clipping_primitives: values[4],
+            fragment_shader_invocations: values[5],
+            compute_shader_invocations: values[6],
+        }
+    }
+}
+
+impl fmt::Display for PipelineStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} vertices ({} shaded), {} primitives ({} clipped to {}), {} fragments, {} compute invocations",
+            self.input_vertices,
+            self.vertex_shader_invocations,
+            self.input_primitives,
+            self.clipping_invocations,
+            self.clipping_primitives,
+            self.fragment_shader_invocations,
+            self.compute_shader_invocations,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameStatistics {
+    pub pool: vk::QueryPool,
+    /// Whether the query was recorded and hasn't been read back.
+    pending: bool,
+}
+
+/// Counts what each frame's commands did with a pipeline statistics query,
+/// read back like `GpuProfiler`'s timestamps.
+#[derive(Clone, Debug, Default)]
+pub struct StatisticsQueries {
+    /// Whether the device has `pipelineStatisticsQuery`.
+    pub supported: bool,
+    pub enabled: bool,
+    pub frames: Vec<FrameStatistics>,
+    current: Option<usize>,
+    /// The most recent frame's statistics.
+    pub latest: Option<PipelineStatistics>,
+}
+
+pub unsafe fn create_statistics_queries(device: &Device, data: &mut AppData) -> Result<()> {
+    let supported = data.features.pipeline_statistics_query == vk::TRUE;
+    let mut statistics = StatisticsQueries { supported, enabled: supported, ..StatisticsQueries::default() };
+
+    if supported {
+        let info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::PIPELINE_STATISTICS)
+            .query_count(1)
+            .pipeline_statistics(STATISTICS);
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let pool = device.create_query_pool(&info, None)?;
+            statistics.frames.push(FrameStatistics { pool, pending: false });
+        }
+    } else {
+        info!("The device has no pipeline statistics queries.");
+    }
+
+    data.pipeline_statistics = statistics;
+
+    let pools = data.pipeline_statistics.frames.iter().map(|f| f.pool).collect::<Vec<_>>();
+    set_object_names(device, data, &pools, "pipeline statistics");
+
+    Ok(())
+}
+
+/// Reads back the statistics recorded the last time frame in flight `frame`
+/// was used. Its fence must have been waited on.
+pub unsafe fn read_frame(device: &Device, statistics: &mut StatisticsQueries, frame: usize) -> Result<()> {
+    let Some(queries) = statistics.frames.get_mut(frame) else { return Ok(()) };
+    if !queries.pending {
+        return Ok(());
+    }
+    queries.pending = false;
+
+    let mut results = [0u64; RESULT_VALUES];
+    let bytes = std::slice::from_raw_parts_mut(results.as_mut_ptr() as *mut u8, RESULT_VALUES * 8);
+    device.get_query_pool_results(
+        queries.pool,
+        0,
+        1,
+        bytes,
+        (RESULT_VALUES * 8) as vk::DeviceSize,
+        vk::QueryResultFlags::_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+    )?;
+    if results[RESULT_VALUES - 1] != 0 {
+        let values = results[..RESULT_VALUES - 1].try_into().unwrap_or_default();
+        statistics.latest = Some(PipelineStatistics::from_values(&values));
+    }
+
+    Ok(())
+}
+
+/// Starts counting frame in flight `frame`'s commands. Must be recorded
+/// outside of a render pass.
+pub unsafe fn cmd_begin_frame(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    statistics: &mut StatisticsQueries,
+    frame: usize,
+) {
+    statistics.current = None;
+    if !statistics.enabled {
+        return;
+    }
+    let Some(queries) = statistics.frames.get_mut(frame) else { return };
+
+    device.cmd_reset_query_pool(command_buffer, queries.pool, 0, 1);
+    device.cmd_begin_query(command_buffer, queries.pool, 0, vk::QueryControlFlags::empty());
+    queries.pending = true;
+    statistics.current = Some(frame);
+}
+
+/// Must be recorded outside of a render pass.
+pub unsafe fn cmd_end_frame(device: &Device, command_buffer: vk::CommandBuffer, statistics: &mut StatisticsQueries) {
+    if let Some(frame) = statistics.current.take() {
+        device.cmd_end_query(command_buffer, statistics.frames[frame].pool, 0);
+    }
+}
+
+pub unsafe fn destroy_statistics_queries(device: &Device, statistics: &mut StatisticsQueries) {
+    statistics.frames.iter().for_each(|f| device.destroy_query_pool(f.pool, None));
+    *statistics = StatisticsQueries::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_read_in_flag_bit_order() {
+        let statistics = PipelineStatistics::from_values(&[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(statistics.vertex_shader_invocations, 3);
+        assert_eq!(statistics.clipping_primitives, 5);
+        assert_eq!(statistics.compute_shader_invocations, 7);
+        assert_eq!(STATISTICS.bits().count_ones() as usize, RESULT_VALUES - 1);
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_name;
+use crate::material::{self, DefaultTextures, Material, MaterialData, SRGB_TEXTURE_SLOTS};
+use crate::mesh::{self, Mesh, MeshData, Vertex};
+use crate::model::Model;
+use crate::pipeline::graphics::{create_graphics_pipeline, GraphicsPipelineInfo, ObjectPushConstants};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range};
+use crate::scene::occlusion::{self, OcclusionQueries};
+use crate::scene::{DrawItem, MaterialId, MeshId, NodeId, Scene};
+use crate::texture::{self, Texture, TextureData, TextureId};
+
+/// How many materials a `SceneRenderer` can hold, not counting the default.
+pub const MAX_MATERIALS: u32 = 256;
+
+/// GPU resources for drawing a `Scene`: the meshes and materials its nodes
+/// refer to by `MeshId` and `MaterialId`, the textures the materials refer to
+/// by `TextureId` and the pipelines to draw them with.
+#[derive(Clone, Debug, Default)]
+pub struct SceneRenderer {
+    pub meshes: Vec<Mesh>,
+    pub textures: Vec<Texture>,
+    pub materials: Vec<Material>,
+    /// Used for nodes without a material.
+    pub default_material: Material,
+    pub default_textures: DefaultTextures,
+    pub material_set_layout: vk::DescriptorSetLayout,
+    pub material_descriptor_pool: vk::DescriptorPool,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    /// Like `pipeline` but without back face culling.
+    pub double_sided_pipeline: vk::Pipeline,
+    /// Null if the device has no `fillModeNonSolid`.
+    pub wireframe_pipeline: vk::Pipeline,
+}
+
+unsafe fn create_scene_pipeline(
+    device: &Device,
+    data: &AppData,
+    layout: vk::PipelineLayout,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+) -> Result<vk::Pipeline> {
+    let bindings = &[Vertex::binding_description()];
+    let attributes = &Vertex::attribute_descriptions();
+    let info = GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        polygon_mode,
+        cull_mode,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        depth_test: true,
+        depth_write: true,
+        samples: data.msaa_samples,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/scene_vert.spv")[..],
+            &include_bytes!("../shaders/scene_frag.spv")[..],
+            layout,
+            data.render_pass,
+        )
+    };
+    create_graphics_pipeline(device, &info)
+}
+
+pub unsafe fn create_scene_renderer(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let material_set_layout = material::create_material_set_layout(device)?;
+    let material_descriptor_pool = material::create_material_descriptor_pool(device, MAX_MATERIALS + 1)?;
+
+    let set_layouts = &[
+        data.frame_set_layout,
+        material_set_layout,
+        data.shadows.set_layout,
+        data.skybox.set_layout,
+    ];
+    let push_constant_ranges = &[push_constant_range::<ObjectPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    let pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let fill = vk::PolygonMode::FILL;
+    let pipeline = create_scene_pipeline(device, data, pipeline_layout, fill, vk::CullModeFlags::BACK)?;
+    let double_sided_pipeline = create_scene_pipeline(device, data, pipeline_layout, fill, vk::CullModeFlags::NONE)?;
+    let wireframe_pipeline = if data.features.fill_mode_non_solid == vk::TRUE {
+        create_scene_pipeline(device, data, pipeline_layout, vk::PolygonMode::LINE, vk::CullModeFlags::NONE)?
+    } else {
+        vk::Pipeline::null()
+    };
+
+    let default_textures = material::create_default_textures(instance, device, data)?;
+    let default_material = material::create_material(
+        instance,
+        device,
+        data,
+        material_descriptor_pool,
+        material_set_layout,
+        &MaterialData::default(),
+        &[],
+        &default_textures,
+    )?;
+
+    data.scene_renderer = SceneRenderer {
+        meshes: Vec::new(),
+        textures: Vec::new(),
+        materials: Vec::new(),
+        default_material,
+        default_textures,
+        material_set_layout,
+        material_descriptor_pool,
+        pipeline_layout,
+        pipeline,
+        double_sided_pipeline,
+        wireframe_pipeline,
+    };
+
+    let renderer = &data.scene_renderer;
+    set_object_name(device, data, renderer.pipeline_layout, "scene pipeline layout");
+    set_object_name(device, data, renderer.pipeline, "scene pipeline");
+    set_object_name(device, data, renderer.double_sided_pipeline, "scene double sided pipeline");
+    set_object_name(device, data, renderer.wireframe_pipeline, "scene wireframe pipeline");
+    set_object_name(device, data, renderer.default_material.uniform_buffer, "default material");
+
+    Ok(())
+}
+
+/// Uploads a mesh and returns the id scene nodes can refer to it by.
+pub unsafe fn add_mesh(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    mesh: &MeshData,
+) -> Result<MeshId> {
+    let mesh = mesh::create_mesh(instance, device, data, mesh)?;
+    data.scene_renderer.meshes.push(mesh);
+    let id = data.scene_renderer.meshes.len() - 1;
+    set_object_name(device, data, mesh.vertex_buffer, &format!("mesh {} vertices", id));
+    set_object_name(device, data, mesh.index_buffer, &format!("mesh {} indices", id));
+    Ok(MeshId(id))
+}
+
+/// Uploads a texture for materials to refer to. `srgb` selects whether the
+/// texture holds colors (base color, emissive) or data.
+pub unsafe fn add_texture(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    texture: &TextureData,
+    srgb: bool,
+) -> Result<TextureId> {
+    let format = if srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM };
+    let texture = texture::create_texture(instance, device, data, texture, format)?;
+    data.scene_renderer.textures.push(texture);
+    let id = data.scene_renderer.textures.len() - 1;
+    set_object_name(device, data, texture.image, &format!("texture {}", id));
+    set_object_name(device, data, texture.image_view, &format!("texture {} view", id));
+    Ok(TextureId(id))
+}
+
+/// Creates a material whose texture slots refer to textures added with `add_texture`.
+pub unsafe fn add_material(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    material: &MaterialData,
+) -> Result<MaterialId> {
+    let renderer = &data.scene_renderer;
+    if renderer.materials.len() >= MAX_MATERIALS as usize {
+        return Err(anyhow!("A scene can't have more than {} materials.", MAX_MATERIALS));
+    }
+
+    let material = material::create_material(
+        instance,
+        device,
+        data,
+        renderer.material_descriptor_pool,
+        renderer.material_set_layout,
+        material,
+        &renderer.textures,
+        &renderer.default_textures,
+    )?;
+    data.scene_renderer.materials.push(material);
+    let id = data.scene_renderer.materials.len() - 1;
+    set_object_name(device, data, material.uniform_buffer, &format!("material {}", id));
+    set_object_name(device, data, material.set, &format!("material {} set", id));
+    Ok(MaterialId(id))
+}
+
+/// Uploads a model's textures, materials and meshes and adds its nodes to
+/// `scene` under `parent`. Returns the ids of the model's root nodes.
+pub unsafe fn add_model(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    scene: &mut Scene,
+    model: &Model,
+    parent: Option<NodeId>,
+) -> Result<Vec<NodeId>> {
+    // A texture is sRGB if any material uses it for colors.
+    let mut srgb = vec![false; model.textures.len()];
+    for material in &model.materials {
+        for (slot, is_srgb) in material.texture_slots().iter().zip(SRGB_TEXTURE_SLOTS) {
+            if let Some(slot) = slot {
+                srgb[slot.texture.0] |= is_srgb;
+            }
+        }
+    }
+
+    let texture_offset = data.scene_renderer.textures.len();
+    for (texture, srgb) in model.textures.iter().zip(srgb) {
+        add_texture(instance, device, data, texture, srgb)?;
+    }
+
+    let material_offset = data.scene_renderer.materials.len();
+    for material in &model.materials {
+        let mut material = material.clone();
+        for slot in material.texture_slots_mut().into_iter().flatten() {
+            slot.texture = TextureId(slot.texture.0 + texture_offset);
+        }
+        add_material(instance, device, data, &material)?;
+    }
+
+    let mesh_offset = data.scene_renderer.meshes.len();
+    for mesh in &model.meshes {
+        add_mesh(instance, device, data, mesh)?;
+    }
+
+    Ok(scene.append(&model.scene, parent, mesh_offset, material_offset))
+}
+
+/// Records drawing `items` (see `Scene::draw_list`) with the frame's uniforms
+/// in `frame_set`, its shadow maps in `shadow_set` and the environment to
+/// reflect in `environment_set` (see `Skybox::set`).
+pub unsafe fn cmd_draw_scene(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &SceneRenderer,
+    frame_set: vk::DescriptorSet,
+    shadow_set: vk::DescriptorSet,
+    environment_set: vk::DescriptorSet,
+    items: &[DrawItem],
+    wireframe: bool,
+    occlusion: &mut OcclusionQueries,
+) {
+    let wireframe = wireframe && !renderer.wireframe_pipeline.is_null();
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        renderer.pipeline_layout,
+        0,
+        &[frame_set],
+        &[],
+    );
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        renderer.pipeline_layout,
+        2,
+        &[shadow_set, environment_set],
+        &[],
+    );
+
+    let mut bound_pipeline = vk::Pipeline::null();
+    let mut bound_set = vk::DescriptorSet::null();
+    for item in items {
+        if !occlusion.should_draw(item.node) {
+            continue;
+        }
+
+        let material = item.material.map_or(&renderer.default_material, |m| &renderer.materials[m.0]);
+        let pipeline = if wireframe {
+            renderer.wireframe_pipeline
+        } else if material.double_sided {
+            renderer.double_sided_pipeline
+        } else {
+            renderer.pipeline
+        };
+        if pipeline != bound_pipeline {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            bound_pipeline = pipeline;
+        }
+        if material.set != bound_set {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                renderer.pipeline_layout,
+                1,
+                &[material.set],
+                &[],
+            );
+            bound_set = material.set;
+        }
+
+        let mesh = &renderer.meshes[item.mesh.0];
+        let push_constants = ObjectPushConstants {
+            model: item.model,
+            material_index: item.material.map_or(0, |m| m.0 as u32),
+        };
+        cmd_push_constants(device, command_buffer, renderer.pipeline_layout, &push_constants);
+        mesh::cmd_bind_mesh(device, command_buffer, mesh);
+        let query = occlusion::cmd_begin_query(device, command_buffer, occlusion, item.node);
+        device.cmd_draw_indexed(command_buffer, mesh.index_count, 1, 0, 0, 0);
+        occlusion::cmd_end_query(device, command_buffer, occlusion, query);
+    }
+}
+
+pub unsafe fn destroy_scene_renderer(device: &Device, renderer: &mut SceneRenderer) {
+    device.destroy_pipeline(renderer.wireframe_pipeline, None);
+    device.destroy_pipeline(renderer.double_sided_pipeline, None);
+    device.destroy_pipeline(renderer.pipeline, None);
+    device.destroy_pipeline_layout(renderer.pipeline_layout, None);
+    renderer.meshes
+        .iter_mut()
+        .for_each(|m| mesh::destroy_mesh(device, m));
+    renderer.materials
+        .iter_mut()
+        .for_each(|m| material::destroy_material(device, m));
+    material::destroy_material(device, &mut renderer.default_material);
+    renderer.textures
+        .iter_mut()
+        .for_each(|t| texture::destroy_texture(device, t));
+    material::destroy_default_textures(device, &mut renderer.default_textures);
+    device.destroy_descriptor_pool(renderer.material_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(renderer.material_set_layout, None);
+    *renderer = SceneRenderer::default();
+}
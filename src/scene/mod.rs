@@ -0,0 +1,482 @@
+pub mod draw;
+pub mod occlusion;
+
+use cgmath::{vec3, Matrix4, One, SquareMatrix};
+
+use crate::light::Light;
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Quat = cgmath::Quaternion<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+
+/// Index of a mesh in the renderer's mesh list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshId(pub usize);
+
+/// Index of a material in the renderer's material list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub usize);
+
+/// Index of a node in its `Scene`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(pub usize);
+
+/// A translation, rotation and scale, applied in the order scale, rotate, translate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: vec3(0.0, 0.0, 0.0),
+            rotation: Quat::one(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self { translation, ..Self::default() }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    local: Transform,
+    world: Mat4,
+    /// Set when `world` is stale, i.e. this node's local transform or parent changed.
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    pub mesh: Option<MeshId>,
+    pub material: Option<MaterialId>,
+    pub light: Option<Light>,
+    pub visible: bool,
+}
+
+impl Node {
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    /// The world matrix as of the last `Scene::update_world_transforms`.
+    pub fn world(&self) -> Mat4 {
+        self.world
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Something to draw, produced by `Scene::draw_list`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DrawItem {
+    pub node: NodeId,
+    pub mesh: MeshId,
+    pub material: Option<MaterialId>,
+    pub model: Mat4,
+}
+
+/// A light to shade with, produced by `Scene::light_list`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightItem {
+    pub node: NodeId,
+    pub light: Light,
+    pub world: Mat4,
+}
+
+/// A hierarchy of nodes whose world transforms are their local transform
+/// applied after their parent's world transform.
+///
+/// Changing a local transform only marks the node dirty; world transforms are
+/// recomputed for dirty nodes and their descendants in `update_world_transforms`.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeId>, name: impl Into<String>, local: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name: name.into(),
+            local,
+            world: Mat4::identity(),
+            dirty: true,
+            parent,
+            children: Vec::new(),
+            mesh: None,
+            material: None,
+            light: None,
+            visible: true,
+        });
+
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().map(|(i, n)| (NodeId(i), n))
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|n| n.name == name).map(NodeId)
+    }
+
+    pub fn set_local(&mut self, id: NodeId, local: Transform) {
+        let node = &mut self.nodes[id.0];
+        node.local = local;
+        node.dirty = true;
+    }
+
+    /// Changes a node's local transform in place.
+    pub fn update_local(&mut self, id: NodeId, f: impl FnOnce(&mut Transform)) {
+        let node = &mut self.nodes[id.0];
+        f(&mut node.local);
+        node.dirty = true;
+    }
+
+    /// Attaches a mesh and optionally a material to a node.
+    pub fn set_mesh(&mut self, id: NodeId, mesh: MeshId, material: Option<MaterialId>) {
+        let node = &mut self.nodes[id.0];
+        node.mesh = Some(mesh);
+        node.material = material;
+    }
+
+    /// Moves `id` under `parent` (or to the roots), keeping its local transform.
+    ///
+    /// # Panics
+    ///
+    /// If `parent` is `id` or one of its descendants.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        if let Some(parent) = parent {
+            assert!(!self.is_ancestor(id, parent), "A node can't be parented to its own subtree.");
+        }
+
+        match self.nodes[id.0].parent {
+            Some(old) => self.nodes[old.0].children.retain(|c| *c != id),
+            None => self.roots.retain(|r| *r != id),
+        }
+        match parent {
+            Some(new) => self.nodes[new.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        let node = &mut self.nodes[id.0];
+        node.parent = parent;
+        node.dirty = true;
+    }
+
+    pub fn set_light(&mut self, id: NodeId, light: Option<Light>) {
+        self.nodes[id.0].light = light;
+    }
+
+    /// Copies all of `other`'s nodes under `parent` (or to the roots), e.g. to
+    /// place a loaded model. Mesh and material ids are offset by where
+    /// `other`'s meshes and materials were added to the renderer.
+    ///
+    /// Returns the new ids of `other`'s roots.
+    pub fn append(
+        &mut self,
+        other: &Scene,
+        parent: Option<NodeId>,
+        mesh_offset: usize,
+        material_offset: usize,
+    ) -> Vec<NodeId> {
+        let offset = self.nodes.len();
+        self.nodes.extend(other.nodes.iter().map(|node| Node {
+            name: node.name.clone(),
+            local: node.local,
+            world: Mat4::identity(),
+            dirty: true,
+            parent: node.parent.map(|p| NodeId(p.0 + offset)).or(parent),
+            children: node.children.iter().map(|c| NodeId(c.0 + offset)).collect(),
+            mesh: node.mesh.map(|m| MeshId(m.0 + mesh_offset)),
+            material: node.material.map(|m| MaterialId(m.0 + material_offset)),
+            light: node.light,
+            visible: node.visible,
+        }));
+
+        let roots = other.roots.iter().map(|r| NodeId(r.0 + offset)).collect::<Vec<_>>();
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.extend(&roots),
+            None => self.roots.extend(&roots),
+        }
+        roots
+    }
+
+    /// Whether `ancestor` is `id` or one of its ancestors.
+    fn is_ancestor(&self, ancestor: NodeId, mut id: NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.nodes[id.0].parent {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Recomputes world matrices of dirty nodes and their descendants.
+    ///
+    /// Returns how many nodes were recomputed.
+    pub fn update_world_transforms(&mut self) -> usize {
+        let mut updated = 0;
+        let mut stack = self.roots
+            .iter()
+            .rev()
+            .map(|r| (*r, Mat4::identity(), false))
+            .collect::<Vec<_>>();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.local.matrix();
+                node.dirty = false;
+                updated += 1;
+            }
+
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|c| (*c, world, changed)));
+        }
+
+        updated
+    }
+
+    /// Visible nodes in depth-first order. Invisible nodes hide their whole subtree.
+    fn visible_nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        std::iter::from_fn(move || {
+            while let Some(id) = stack.pop() {
+                let node = &self.nodes[id.0];
+                if node.visible {
+                    stack.extend(node.children.iter().rev());
+                    return Some((id, node));
+                }
+            }
+            None
+        })
+    }
+
+    /// Visible nodes with a mesh, in depth-first order. Invisible nodes hide
+    /// their whole subtree.
+    ///
+    /// World matrices are used as they are, so call `update_world_transforms` first.
+    pub fn draw_list(&self) -> Vec<DrawItem> {
+        self.visible_nodes()
+            .filter_map(|(id, node)| {
+                let mesh = node.mesh?;
+                Some(DrawItem { node: id, mesh, material: node.material, model: node.world })
+            })
+            .collect()
+    }
+
+    /// Visible nodes with a light, in depth-first order, like `draw_list`.
+    pub fn light_list(&self) -> Vec<LightItem> {
+        self.visible_nodes()
+            .filter_map(|(id, node)| Some(LightItem { node: id, light: node.light?, world: node.world }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, point3, Deg, Rotation3, Transform as _};
+
+    fn translation(x: f32, y: f32, z: f32) -> Transform {
+        Transform::from_translation(vec3(x, y, z))
+    }
+
+    #[test]
+    fn transform_applies_scale_then_rotation_then_translation() {
+        let transform = Transform {
+            translation: vec3(10.0, 0.0, 0.0),
+            rotation: Quat::from_angle_z(Deg(90.0)),
+            scale: vec3(2.0, 2.0, 2.0),
+        };
+        let point = transform.matrix().transform_point(point3(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(point, point3(10.0, 2.0, 0.0), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn world_transforms_compose_down_the_hierarchy() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(None, "root", translation(1.0, 0.0, 0.0));
+        let child = scene.add_node(Some(root), "child", Transform {
+            rotation: Quat::from_angle_y(Deg(90.0)),
+            ..translation(0.0, 2.0, 0.0)
+        });
+        let grandchild = scene.add_node(Some(child), "grandchild", translation(0.0, 0.0, 3.0));
+
+        assert_eq!(scene.update_world_transforms(), 3);
+
+        let origin = point3(0.0, 0.0, 0.0);
+        assert_abs_diff_eq!(scene.node(root).world().transform_point(origin), point3(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(scene.node(child).world().transform_point(origin), point3(1.0, 2.0, 0.0));
+        // +Z rotated 90° around Y is +X.
+        assert_abs_diff_eq!(
+            scene.node(grandchild).world().transform_point(origin),
+            point3(4.0, 2.0, 0.0),
+            epsilon = 1e-5,
+        );
+    }
+
+    #[test]
+    fn only_dirty_subtrees_are_recomputed() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(None, "a", Transform::default());
+        let a_child = scene.add_node(Some(a), "a child", translation(0.0, 1.0, 0.0));
+        let b = scene.add_node(None, "b", Transform::default());
+        let b_child = scene.add_node(Some(b), "b child", translation(0.0, 1.0, 0.0));
+        scene.update_world_transforms();
+
+        assert_eq!(scene.update_world_transforms(), 0);
+
+        scene.set_local(a, translation(5.0, 0.0, 0.0));
+        assert_eq!(scene.update_world_transforms(), 2);
+        assert_abs_diff_eq!(scene.node(a_child).world(), Matrix4::from_translation(vec3(5.0, 1.0, 0.0)));
+        assert_abs_diff_eq!(scene.node(b_child).world(), Matrix4::from_translation(vec3(0.0, 1.0, 0.0)));
+
+        scene.update_local(b_child, |t| t.translation.y = 2.0);
+        assert_eq!(scene.update_world_transforms(), 1);
+        assert_abs_diff_eq!(scene.node(b_child).world(), Matrix4::from_translation(vec3(0.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn reparenting_uses_the_new_parent() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(None, "a", translation(1.0, 0.0, 0.0));
+        let b = scene.add_node(None, "b", translation(0.0, 0.0, 7.0));
+        let child = scene.add_node(Some(a), "child", translation(0.0, 1.0, 0.0));
+        scene.update_world_transforms();
+
+        scene.set_parent(child, Some(b));
+        scene.update_world_transforms();
+
+        assert_eq!(scene.node(child).parent(), Some(b));
+        assert!(scene.node(a).children().is_empty());
+        assert_abs_diff_eq!(scene.node(child).world(), Matrix4::from_translation(vec3(0.0, 1.0, 7.0)));
+
+        scene.set_parent(child, None);
+        scene.update_world_transforms();
+        assert_eq!(scene.roots(), &[a, b, child]);
+        assert_abs_diff_eq!(scene.node(child).world(), Matrix4::from_translation(vec3(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn light_list_has_visible_lights_with_world_transforms() {
+        use crate::light::Light;
+
+        let light = Light::point(vec3(1.0, 1.0, 1.0), 10.0, None);
+        let mut scene = Scene::new();
+        let root = scene.add_node(None, "root", translation(0.0, 5.0, 0.0));
+        let lamp = scene.add_node(Some(root), "lamp", translation(1.0, 0.0, 0.0));
+        let hidden = scene.add_node(Some(root), "hidden", Transform::default());
+        scene.set_light(lamp, Some(light));
+        scene.set_light(hidden, Some(light));
+        scene.node_mut(hidden).visible = false;
+        scene.update_world_transforms();
+
+        let lights = scene.light_list();
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights[0].node, lamp);
+        assert_eq!(lights[0].light, light);
+        assert_abs_diff_eq!(lights[0].world, Matrix4::from_translation(vec3(1.0, 5.0, 0.0)));
+    }
+
+    #[test]
+    fn append_offsets_ids_and_attaches_roots() {
+        let mut other = Scene::new();
+        let model_root = other.add_node(None, "model", translation(0.0, 1.0, 0.0));
+        let part = other.add_node(Some(model_root), "part", Transform::default());
+        other.set_mesh(part, MeshId(0), Some(MaterialId(1)));
+
+        let mut scene = Scene::new();
+        let anchor = scene.add_node(None, "anchor", translation(2.0, 0.0, 0.0));
+        let roots = scene.append(&other, Some(anchor), 5, 10);
+        scene.update_world_transforms();
+
+        assert_eq!(roots, vec![NodeId(1)]);
+        assert_eq!(scene.roots(), &[anchor]);
+        assert_eq!(scene.node(anchor).children(), &[NodeId(1)]);
+        assert_eq!(scene.node(NodeId(2)).parent(), Some(NodeId(1)));
+
+        let items = scene.draw_list();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].mesh, MeshId(5));
+        assert_eq!(items[0].material, Some(MaterialId(11)));
+        assert_abs_diff_eq!(items[0].model, Matrix4::from_translation(vec3(2.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn parenting_to_a_descendant_panics() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(None, "a", Transform::default());
+        let b = scene.add_node(Some(a), "b", Transform::default());
+        scene.set_parent(a, Some(b));
+    }
+
+    #[test]
+    fn draw_list_skips_nodes_without_meshes_and_hidden_subtrees() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(None, "root", translation(1.0, 0.0, 0.0));
+        let drawn = scene.add_node(Some(root), "drawn", translation(0.0, 1.0, 0.0));
+        let hidden = scene.add_node(Some(root), "hidden", Transform::default());
+        let under_hidden = scene.add_node(Some(hidden), "under hidden", Transform::default());
+        scene.set_mesh(drawn, MeshId(3), Some(MaterialId(1)));
+        scene.set_mesh(hidden, MeshId(0), None);
+        scene.set_mesh(under_hidden, MeshId(0), None);
+        scene.node_mut(hidden).visible = false;
+        scene.update_world_transforms();
+
+        let items = scene.draw_list();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].node, drawn);
+        assert_eq!(items[0].mesh, MeshId(3));
+        assert_eq!(items[0].material, Some(MaterialId(1)));
+        assert_abs_diff_eq!(items[0].model, Matrix4::from_translation(vec3(1.0, 1.0, 0.0)));
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use log::*;
+
+use std::collections::HashMap;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_names;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::scene::NodeId;
+
+/// How many objects a frame can query. Objects past this are drawn without
+/// a query, and always drawn.
+pub const MAX_OCCLUSION_QUERIES: u32 = 1024;
+
+/// How many frames an occluded object is skipped for before it's drawn
+/// again, to find out whether it still is.
+pub const RETEST_INTERVAL: u64 = 8;
+
+/// The result of an object's last occlusion query.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Visibility {
+    /// Samples that passed the depth test. Only zero or not is meaningful
+    /// unless queries are precise.
+    pub samples: u64,
+    /// The frame the object was drawn in.
+    pub frame: u64,
+}
+
+/// Whether an object should be drawn in `frame` when culling: it was
+/// visible, or hasn't been tested for `RETEST_INTERVAL` frames.
+pub fn should_draw(visibility: Option<Visibility>, frame: u64) -> bool {
+    match visibility {
+        Some(v) => v.samples > 0 || frame >= v.frame + RETEST_INTERVAL,
+        None => true,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameOcclusion {
+    pub pool: vk::QueryPool,
+    frame: u64,
+    /// The object each query was for.
+    nodes: Vec<NodeId>,
+    /// Whether the queries were recorded and haven't been read back.
+    pending: bool,
+}
+
+/// An occlusion query per drawn object, so objects hidden behind others can
+/// be skipped.
+///
+/// Results are read back like `GpuProfiler`'s timestamps, so they describe
+/// an earlier frame and culling lags behind the camera. Queries only count
+/// what was drawn before them: an object drawn before its occluders always
+/// counts as visible.
+#[derive(Clone, Debug, Default)]
+pub struct OcclusionQueries {
+    /// Whether sample counts are exact (`occlusionQueryPrecise`).
+    pub precise: bool,
+    /// Records queries while true.
+    pub enabled: bool,
+    /// Skips objects that weren't visible, see `should_draw`. Needs `enabled`.
+    pub culling: bool,
+    pub frames: Vec<FrameOcclusion>,
+    current: Option<usize>,
+    next_frame: u64,
+    /// The latest result per object.
+    pub visibility: HashMap<NodeId, Visibility>,
+}
+
+impl OcclusionQueries {
+    /// Whether `node` should be drawn in the frame being recorded.
+    pub fn should_draw(&self, node: NodeId) -> bool {
+        !self.culling || self.current.is_none() || should_draw(self.visibility.get(&node).copied(), self.next_frame - 1)
+    }
+
+    /// How many of the objects in the latest results were occluded, and how
+    /// many objects there were.
+    pub fn occluded_count(&self) -> (usize, usize) {
+        let occluded = self.visibility.values().filter(|v| v.samples == 0).count();
+        (occluded, self.visibility.len())
+    }
+}
+
+pub unsafe fn create_occlusion_queries(device: &Device, data: &mut AppData) -> Result<()> {
+    let info = vk::QueryPoolCreateInfo::builder()
+        .query_type(vk::QueryType::OCCLUSION)
+        .query_count(MAX_OCCLUSION_QUERIES);
+    let mut occlusion = OcclusionQueries {
+        precise: data.features.occlusion_query_precise == vk::TRUE,
+        enabled: true,
+        ..OcclusionQueries::default()
+    };
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let pool = device.create_query_pool(&info, None)?;
+        occlusion.frames.push(FrameOcclusion { pool, ..FrameOcclusion::default() });
+    }
+    debug!("Occlusion queries are {}.", if occlusion.precise { "precise" } else { "not precise" });
+
+    data.occlusion = occlusion;
+
+    let pools = data.occlusion.frames.iter().map(|f| f.pool).collect::<Vec<_>>();
+    set_object_names(device, data, &pools, "occlusion queries");
+
+    Ok(())
+}
+
+/// Reads back the results recorded the last time frame in flight `frame` was
+/// used. Its fence must have been waited on.
+pub unsafe fn read_frame(device: &Device, occlusion: &mut OcclusionQueries, frame: usize) -> Result<()> {
+    let Some(queries) = occlusion.frames.get_mut(frame) else { return Ok(()) };
+    if !queries.pending {
+        return Ok(());
+    }
+    queries.pending = false;
+    if queries.nodes.is_empty() {
+        return Ok(());
+    }
+
+    // Each query's sample count followed by whether it's available.
+    let count = queries.nodes.len();
+    let mut results = vec![0u64; count * 2];
+    let bytes = std::slice::from_raw_parts_mut(results.as_mut_ptr() as *mut u8, results.len() * 8);
+    device.get_query_pool_results(
+        queries.pool,
+        0,
+        count as u32,
+        bytes,
+        16,
+        vk::QueryResultFlags::_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+    )?;
+
+    for (node, result) in queries.nodes.iter().zip(results.chunks_exact(2)) {
+        if result[1] != 0 {
+            occlusion.visibility.insert(*node, Visibility { samples: result[0], frame: queries.frame });
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts recording frame in flight `frame`'s queries. Must be recorded
+/// outside of a render pass.
+pub unsafe fn cmd_begin_frame(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    occlusion: &mut OcclusionQueries,
+    frame: usize,
+) {
+    occlusion.current = None;
+    if !occlusion.enabled {
+        return;
+    }
+    let Some(queries) = occlusion.frames.get_mut(frame) else { return };
+
+    device.cmd_reset_query_pool(command_buffer, queries.pool, 0, MAX_OCCLUSION_QUERIES);
+    queries.frame = occlusion.next_frame;
+    queries.nodes.clear();
+    queries.pending = true;
+    occlusion.next_frame += 1;
+    occlusion.current = Some(frame);
+}
+
+/// Starts counting the samples drawn for `node`, returning the query to pass
+/// to `cmd_end_query` if there is one. Must be recorded in a render pass.
+pub unsafe fn cmd_begin_query(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    occlusion: &mut OcclusionQueries,
+    node: NodeId,
+) -> Option<u32> {
+    let queries = &mut occlusion.frames[occlusion.current?];
+    let query = queries.nodes.len() as u32;
+    if query >= MAX_OCCLUSION_QUERIES {
+        return None;
+    }
+
+    let flags = if occlusion.precise { vk::QueryControlFlags::PRECISE } else { vk::QueryControlFlags::empty() };
+    device.cmd_begin_query(command_buffer, queries.pool, query, flags);
+    queries.nodes.push(node);
+    Some(query)
+}
+
+pub unsafe fn cmd_end_query(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    occlusion: &OcclusionQueries,
+    query: Option<u32>,
+) {
+    if let (Some(frame), Some(query)) = (occlusion.current, query) {
+        device.cmd_end_query(command_buffer, occlusion.frames[frame].pool, query);
+    }
+}
+
+pub unsafe fn destroy_occlusion_queries(device: &Device, occlusion: &mut OcclusionQueries) {
+    occlusion.frames.iter().for_each(|f| device.destroy_query_pool(f.pool, None));
+    *occlusion = OcclusionQueries::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occluded_objects_are_retested_periodically() {
+        let visible = Visibility { samples: 12, frame: 10 };
+        let occluded = Visibility { samples: 0, frame: 10 };
+        assert!(should_draw(None, 11));
+        assert!(should_draw(Some(visible), 11));
+        assert!(!should_draw(Some(occluded), 11));
+        assert!(!should_draw(Some(occluded), 10 + RETEST_INTERVAL - 1));
+        assert!(should_draw(Some(occluded), 10 + RETEST_INTERVAL));
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{anyhow, Result};
+use log::*;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::create_buffer;
+
+/// A host visible copy of a swapchain image that is being read back.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Screenshot {
+    pub buffer: vk::Buffer,
+    pub buffer_memory: vk::DeviceMemory,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+}
+
+/// Whether the swapchain format is one `save_screenshot` knows how to encode.
+pub fn is_supported_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::R8G8B8A8_UNORM
+    )
+}
+
+pub unsafe fn create_screenshot(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+) -> Result<Screenshot> {
+    if !is_supported_format(data.swapchain_format) {
+        return Err(anyhow!("Screenshots of {:?} swapchains are not supported.", data.swapchain_format));
+    }
+
+    let extent = data.swapchain_extent;
+    let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+    let (buffer, buffer_memory) = create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+    )?;
+
+    Ok(Screenshot { buffer, buffer_memory, extent, format: data.swapchain_format })
+}
+
+/// Records copying a rendered swapchain image (in `PRESENT_SRC_KHR`) into the
+/// screenshot buffer, leaving the image ready to present again.
+pub unsafe fn cmd_copy_swapchain_image(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    screenshot: &Screenshot,
+) {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+    let barrier = |old_layout, new_layout, src_access, dst_access| {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+    };
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        )],
+    );
+
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D::default())
+        .image_extent(vk::Extent3D {
+            width: screenshot.extent.width,
+            height: screenshot.extent.height,
+            depth: 1,
+        });
+    device.cmd_copy_image_to_buffer(
+        command_buffer,
+        image,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        screenshot.buffer,
+        &[region],
+    );
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+        vk::DependencyFlags::empty(),
+        &[vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::empty(),
+        )],
+    );
+}
+
+/// Writes a screenshot whose copy has completed to a PNG file.
+pub unsafe fn save_screenshot(
+    device: &Device,
+    screenshot: &Screenshot,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let vk::Extent2D { width, height } = screenshot.extent;
+    let size = width as usize * height as usize * 4;
+
+    let memory = device.map_memory(
+        screenshot.buffer_memory,
+        0,
+        size as vk::DeviceSize,
+        vk::MemoryMapFlags::empty(),
+    )?;
+    let mut pixels = std::slice::from_raw_parts(memory.cast::<u8>(), size).to_vec();
+    device.unmap_memory(screenshot.buffer_memory);
+
+    if matches!(screenshot.format, vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM) {
+        pixels.chunks_exact_mut(4).for_each(|p| p.swap(0, 2));
+    }
+    // The swapchain is opaque, whatever ended up in the alpha channel.
+    pixels.chunks_exact_mut(4).for_each(|p| p[3] = 255);
+
+    let path = path.as_ref();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+
+    info!("Saved screenshot to `{}`.", path.display());
+    Ok(())
+}
+
+pub unsafe fn destroy_screenshot(device: &Device, screenshot: &mut Screenshot) {
+    device.destroy_buffer(screenshot.buffer, None);
+    device.free_memory(screenshot.buffer_memory, None);
+    *screenshot = Screenshot::default();
+}
@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) out vec2 fragUv;
+
+// A triangle covering the screen, with UVs from 0 to 1 across it.
+vec2 positions[3] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2(3.0, -1.0),
+    vec2(-1.0, 3.0)
+);
+
+void main() {
+    vec2 position = positions[gl_VertexIndex];
+    gl_Position = vec4(position, 0.0, 1.0);
+    fragUv = position * 0.5 + 0.5;
+}
@@ -0,0 +1,64 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D image;
+layout(set = 0, binding = 1) uniform sampler imageSampler;
+
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+    vec4 outputParams;
+} pc;
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 srgbDecode(vec3 color) {
+    vec3 curve = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(color / 12.92, curve, step(vec3(0.04045), color));
+}
+
+vec3 srgbEncode(vec3 color) {
+    vec3 curve = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(color * 12.92, curve, step(vec3(0.0031308), color));
+}
+
+// The ST 2084 curve, for colors where 1 is 10000 nits.
+vec3 pqEncode(vec3 color) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 p = pow(color, vec3(m1));
+    return pow((c1 + c2 * p) / (1.0 + c3 * p), vec3(m2));
+}
+
+// BT.709 to BT.2020 primaries.
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// egui's vertex colors and textures are premultiplied sRGB, multiplied
+// together like egui does. The result is blended with straight alpha and
+// encoded like text.frag (outputParams.x: the encoding, outputParams.y: the
+// paper white scale), so the GUI matches the swapchain.
+void main() {
+    vec4 premultiplied = fragColor * texture(sampler2D(image, imageSampler), fragUv);
+    float alpha = premultiplied.a;
+    vec3 color = vec3(0.0);
+    if (alpha > 0.0) {
+        color = srgbDecode(clamp(premultiplied.rgb / alpha, 0.0, 1.0));
+    }
+    int encoding = int(pc.outputParams.x + 0.5);
+    if (encoding == 1) {
+        color = srgbEncode(color);
+    } else if (encoding == 2) {
+        color = pqEncode(REC709_TO_REC2020 * color * pc.outputParams.y);
+    } else if (encoding == 3) {
+        color = color * pc.outputParams.y;
+    }
+    outColor = vec4(color, alpha);
+}
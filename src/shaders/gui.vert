@@ -0,0 +1,21 @@
+#version 450
+
+// egui meshes are in points; the transform maps them to clip space. Colors
+// are premultiplied sRGB bytes, read as normalized floats.
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+    vec4 outputParams;
+} pc;
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec2 inUv;
+layout(location = 2) in vec4 inColor;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragColor;
+
+void main() {
+    gl_Position = pc.transform * vec4(inPosition, 0.0, 1.0);
+    fragUv = inUv;
+    fragColor = inColor;
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_proj;
+} pcs;
+
+// Per-vertex (binding 0)
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec4 inColor;
+
+// Per-instance (binding 1)
+layout(location = 6) in vec4 instanceModel0;
+layout(location = 7) in vec4 instanceModel1;
+layout(location = 8) in vec4 instanceModel2;
+layout(location = 9) in vec4 instanceModel3;
+layout(location = 10) in vec4 instanceColor;
+
+layout(location = 0) out vec4 fragColor;
+
+void main() {
+    mat4 model = mat4(instanceModel0, instanceModel1, instanceModel2, instanceModel3);
+    gl_Position = pcs.view_proj * model * vec4(inPosition, 1.0);
+    fragColor = inColor * instanceColor;
+}
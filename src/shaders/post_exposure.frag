@@ -0,0 +1,22 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D inputImage;
+layout(set = 0, binding = 1) uniform sampler inputSampler;
+
+layout(push_constant) uniform PushConstants {
+    vec4 params;
+    vec2 texelSize;
+} pc;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 inputColor(vec2 uv) {
+    return texture(sampler2D(inputImage, inputSampler), uv).rgb;
+}
+
+// params.x: the exposure scale, 2^stops.
+void main() {
+    outColor = vec4(inputColor(fragUv) * pc.params.x, 1.0);
+}
@@ -0,0 +1,58 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D inputImage;
+layout(set = 0, binding = 1) uniform sampler inputSampler;
+
+layout(push_constant) uniform PushConstants {
+    vec4 params;
+    vec2 texelSize;
+} pc;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 inputColor(vec2 uv) {
+    return texture(sampler2D(inputImage, inputSampler), uv).rgb;
+}
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+// FXAA in the style of Lottes' console version: blur along the edge
+// direction estimated from the luma of the four diagonal neighbours.
+// params: span max, reduce mul, reduce min.
+void main() {
+    float spanMax = pc.params.x;
+    float reduceMul = pc.params.y;
+    float reduceMin = pc.params.z;
+
+    vec3 rgbM = inputColor(fragUv);
+    float lumaNW = luma(inputColor(fragUv + vec2(-1.0, -1.0) * pc.texelSize));
+    float lumaNE = luma(inputColor(fragUv + vec2(1.0, -1.0) * pc.texelSize));
+    float lumaSW = luma(inputColor(fragUv + vec2(-1.0, 1.0) * pc.texelSize));
+    float lumaSE = luma(inputColor(fragUv + vec2(1.0, 1.0) * pc.texelSize));
+    float lumaM = luma(rgbM);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE)
+    );
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * reduceMul, reduceMin);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-spanMax), vec2(spanMax)) * pc.texelSize;
+
+    vec3 rgbA = 0.5 * (
+        inputColor(fragUv + dir * (1.0 / 3.0 - 0.5)) +
+        inputColor(fragUv + dir * (2.0 / 3.0 - 0.5)));
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        inputColor(fragUv - dir * 0.5) +
+        inputColor(fragUv + dir * 0.5));
+
+    float lumaB = luma(rgbB);
+    outColor = vec4((lumaB < lumaMin || lumaB > lumaMax) ? rgbA : rgbB, 1.0);
+}
@@ -0,0 +1,32 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D inputImage;
+layout(set = 0, binding = 1) uniform sampler inputSampler;
+
+layout(push_constant) uniform PushConstants {
+    vec4 params;
+    vec2 texelSize;
+} pc;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 inputColor(vec2 uv) {
+    return texture(sampler2D(inputImage, inputSampler), uv).rgb;
+}
+
+// Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+// params.x: 0 for ACES, 1 for Reinhard.
+void main() {
+    vec3 color = max(inputColor(fragUv), vec3(0.0));
+    outColor = vec4(pc.params.x < 0.5 ? aces(color) : reinhard(color), 1.0);
+}
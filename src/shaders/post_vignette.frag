@@ -0,0 +1,25 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D inputImage;
+layout(set = 0, binding = 1) uniform sampler inputSampler;
+
+layout(push_constant) uniform PushConstants {
+    vec4 params;
+    vec2 texelSize;
+} pc;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 inputColor(vec2 uv) {
+    return texture(sampler2D(inputImage, inputSampler), uv).rgb;
+}
+
+// params: intensity, radius, softness. The distance from the center is 1 at
+// the corners.
+void main() {
+    float distance = length(fragUv - 0.5) * sqrt(2.0);
+    float falloff = smoothstep(pc.params.y, pc.params.y + pc.params.z, distance);
+    outColor = vec4(inputColor(fragUv) * (1.0 - pc.params.x * falloff), 1.0);
+}
@@ -0,0 +1,292 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view;
+    mat4 proj;
+    mat4 view_proj;
+    vec4 camera_position;
+} frame;
+
+struct Light {
+    vec4 position_range;
+    vec4 direction;
+    vec4 color;
+    uint kind;
+    float cos_inner_cone;
+    float cos_outer_cone;
+    int shadow_layer;
+};
+
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+const uint PBR = 0u;
+const uint BLINN_PHONG = 1u;
+
+layout(std430, set = 0, binding = 1) readonly buffer Lights {
+    uint light_count;
+    uint shading_model;
+    Light lights[];
+};
+
+layout(set = 1, binding = 0) uniform MaterialUniforms {
+    vec4 base_color_factor;
+    vec4 emissive_factor;
+    float metallic_factor;
+    float roughness_factor;
+    float normal_scale;
+    float occlusion_strength;
+    float alpha_cutoff;
+    uint flags;
+    uint uv_sets;
+} material;
+
+// Texture slots, see `MaterialData::texture_slots`.
+const uint BASE_COLOR = 0u;
+const uint METALLIC_ROUGHNESS = 1u;
+const uint NORMAL = 2u;
+const uint OCCLUSION = 3u;
+const uint EMISSIVE = 4u;
+
+layout(set = 1, binding = 1) uniform texture2D baseColorTexture;
+layout(set = 1, binding = 2) uniform texture2D metallicRoughnessTexture;
+layout(set = 1, binding = 3) uniform texture2D normalTexture;
+layout(set = 1, binding = 4) uniform texture2D occlusionTexture;
+layout(set = 1, binding = 5) uniform texture2D emissiveTexture;
+layout(set = 1, binding = 6) uniform sampler baseColorSampler;
+layout(set = 1, binding = 7) uniform sampler metallicRoughnessSampler;
+layout(set = 1, binding = 8) uniform sampler normalSampler;
+layout(set = 1, binding = 9) uniform sampler occlusionSampler;
+layout(set = 1, binding = 10) uniform sampler emissiveSampler;
+
+const uint HAS_NORMAL_TEXTURE = 1u;
+
+layout(set = 2, binding = 0) uniform ShadowUniforms {
+    mat4 matrices[8];
+    vec4 cascade_splits;
+    uint cascade_count;
+    float texel_size;
+} shadows;
+
+layout(set = 2, binding = 1) uniform texture2DArray shadowMap;
+layout(set = 2, binding = 2) uniform samplerShadow shadowSampler;
+
+layout(set = 3, binding = 0) uniform textureCube environmentMap;
+layout(set = 3, binding = 1) uniform sampler environmentSampler;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec4 fragColor;
+layout(location = 4) in vec2 fragUv0;
+layout(location = 5) in vec2 fragUv1;
+
+layout(location = 0) out vec4 outColor;
+
+const float PI = 3.14159265359;
+
+vec2 uv(uint slot) {
+    return ((material.uv_sets >> slot) & 1u) == 0u ? fragUv0 : fragUv1;
+}
+
+// Trowbridge-Reitz GGX normal distribution, `alpha` is roughness squared.
+float distributionGgx(float nDotH, float alpha) {
+    float alpha2 = alpha * alpha;
+    float d = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Smith's method with the Schlick-GGX approximation for each direction.
+float geometrySmith(float nDotV, float nDotL, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float view = nDotV / (nDotV * (1.0 - k) + k);
+    float light = nDotL / (nDotL * (1.0 - k) + k);
+    return view * light;
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// The environment reflected off the surface. Without prefiltered mip levels
+// rough surfaces can't blur the reflection, so they reflect less instead.
+vec3 environmentReflection(vec3 n, vec3 v, vec3 baseColor, float metallic, float roughness) {
+    vec3 f0 = mix(vec3(0.04), baseColor, metallic);
+    float nDotV = max(dot(n, v), 0.0);
+    vec3 f = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - nDotV, 5.0);
+    vec3 reflection = texture(samplerCube(environmentMap, environmentSampler), reflect(-v, n)).rgb;
+    float smoothness = 1.0 - roughness;
+    return reflection * f * smoothness * smoothness;
+}
+
+// Cook-Torrance specular plus Lambert diffuse for light arriving from `l`.
+vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 baseColor, float metallic, float roughness) {
+    vec3 h = normalize(v + l);
+    float nDotL = max(dot(n, l), 0.0);
+    float nDotV = max(dot(n, v), 1e-4);
+    float nDotH = max(dot(n, h), 0.0);
+
+    vec3 f0 = mix(vec3(0.04), baseColor, metallic);
+    vec3 f = fresnelSchlick(max(dot(h, v), 0.0), f0);
+    float d = distributionGgx(nDotH, roughness * roughness);
+    float g = geometrySmith(nDotV, nDotL, roughness);
+
+    vec3 specular = d * g * f / (4.0 * nDotV * max(nDotL, 1e-4));
+    vec3 diffuse = (1.0 - f) * (1.0 - metallic) * baseColor / PI;
+    return (diffuse + specular) * nDotL;
+}
+
+// Blinn-Phong with a shininess matched to the GGX roughness.
+vec3 blinnPhong(vec3 n, vec3 v, vec3 l, vec3 baseColor, float metallic, float roughness) {
+    vec3 h = normalize(v + l);
+    float nDotL = max(dot(n, l), 0.0);
+    float alpha = roughness * roughness;
+    float shininess = max(2.0 / (alpha * alpha) - 2.0, 1.0);
+
+    vec3 specularColor = mix(vec3(0.04), baseColor, metallic);
+    vec3 specular = specularColor * (shininess + 8.0) / (8.0 * PI) * pow(max(dot(n, h), 0.0), shininess);
+    vec3 diffuse = (1.0 - metallic) * baseColor / PI;
+    return (diffuse + specular) * nDotL;
+}
+
+// The direction towards light `i` and the light arriving from it, with
+// glTF's inverse square falloff, range window and spot cone.
+vec3 incomingLight(uint i, out vec3 l) {
+    Light light = lights[i];
+    if (light.kind == DIRECTIONAL) {
+        l = -light.direction.xyz;
+        return light.color.rgb;
+    }
+
+    vec3 toLight = light.position_range.xyz - fragPosition;
+    float distance2 = max(dot(toLight, toLight), 1e-4);
+    l = toLight * inversesqrt(distance2);
+
+    float attenuation = 1.0 / distance2;
+    float range = light.position_range.w;
+    if (range > 0.0) {
+        float ratio = distance2 / (range * range);
+        attenuation *= pow(clamp(1.0 - ratio * ratio, 0.0, 1.0), 2.0);
+    }
+    if (light.kind == SPOT) {
+        float cd = dot(light.direction.xyz, -l);
+        float cone = clamp((cd - light.cos_outer_cone) / max(light.cos_inner_cone - light.cos_outer_cone, 1e-4), 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+    return light.color.rgb * attenuation;
+}
+
+// How much of light `i` reaches the fragment: the shadow map comparison
+// averaged over 3x3 texels (each filtered over 2x2 if the format allows).
+// Directional lights pick the cascade covering the fragment's view depth.
+float shadowFactor(uint i) {
+    Light light = lights[i];
+    if (light.shadow_layer < 0) {
+        return 1.0;
+    }
+
+    int layer = light.shadow_layer;
+    if (light.kind == DIRECTIONAL) {
+        float depth = -(frame.view * vec4(fragPosition, 1.0)).z;
+        uint cascade = 0u;
+        while (cascade < shadows.cascade_count && depth > shadows.cascade_splits[cascade]) {
+            cascade++;
+        }
+        if (cascade == shadows.cascade_count) {
+            return 1.0;
+        }
+        layer += int(cascade);
+    }
+
+    vec4 clip = shadows.matrices[layer] * vec4(fragPosition, 1.0);
+    if (clip.w <= 0.0) {
+        return 1.0;
+    }
+    vec3 coords = clip.xyz / clip.w;
+    if (coords.z >= 1.0) {
+        return 1.0;
+    }
+    vec2 uv = coords.xy * 0.5 + 0.5;
+
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(float(x), float(y)) * shadows.texel_size;
+            lit += texture(sampler2DArrayShadow(shadowMap, shadowSampler), vec4(uv + offset, float(layer), coords.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// The normal map applied to `n`, using the vertex tangents if there are any
+// and a tangent frame from screen space derivatives otherwise.
+vec3 mapNormal(vec3 n) {
+    vec2 normalUv = uv(NORMAL);
+    vec3 mapped = texture(sampler2D(normalTexture, normalSampler), normalUv).xyz * 2.0 - 1.0;
+    mapped.xy *= material.normal_scale;
+
+    vec3 t;
+    vec3 b;
+    if (fragTangent.w != 0.0) {
+        t = normalize(fragTangent.xyz - n * dot(n, fragTangent.xyz));
+        b = cross(n, t) * fragTangent.w;
+    } else {
+        vec3 dp1 = dFdx(fragPosition);
+        vec3 dp2 = dFdy(fragPosition);
+        vec2 duv1 = dFdx(normalUv);
+        vec2 duv2 = dFdy(normalUv);
+        vec3 dp2perp = cross(dp2, n);
+        vec3 dp1perp = cross(n, dp1);
+        t = dp2perp * duv1.x + dp1perp * duv2.x;
+        // glTF normal maps point +Y towards decreasing V.
+        b = -(dp2perp * duv1.y + dp1perp * duv2.y);
+        float scale = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-12));
+        t *= scale;
+        b *= scale;
+    }
+
+    return normalize(mat3(t, b, n) * mapped);
+}
+
+void main() {
+    vec4 baseColor = material.base_color_factor * fragColor
+        * texture(sampler2D(baseColorTexture, baseColorSampler), uv(BASE_COLOR));
+    if (baseColor.a < material.alpha_cutoff) {
+        discard;
+    }
+
+    vec4 metallicRoughness = texture(sampler2D(metallicRoughnessTexture, metallicRoughnessSampler), uv(METALLIC_ROUGHNESS));
+    float metallic = clamp(material.metallic_factor * metallicRoughness.b, 0.0, 1.0);
+    float roughness = clamp(material.roughness_factor * metallicRoughness.g, 0.04, 1.0);
+
+    vec3 n = normalize(fragNormal);
+    if (!gl_FrontFacing) {
+        n = -n;
+    }
+    if ((material.flags & HAS_NORMAL_TEXTURE) != 0u) {
+        n = mapNormal(n);
+    }
+    vec3 v = normalize(frame.camera_position.xyz - fragPosition);
+
+    float occlusion = texture(sampler2D(occlusionTexture, occlusionSampler), uv(OCCLUSION)).r;
+    occlusion = 1.0 + material.occlusion_strength * (occlusion - 1.0);
+    vec3 emissive = material.emissive_factor.rgb
+        * texture(sampler2D(emissiveTexture, emissiveSampler), uv(EMISSIVE)).rgb;
+
+    vec3 color = vec3(0.0);
+    for (uint i = 0u; i < light_count; i++) {
+        vec3 l;
+        vec3 radiance = incomingLight(i, l) * shadowFactor(i);
+        if (shading_model == BLINN_PHONG) {
+            color += blinnPhong(n, v, l, baseColor.rgb, metallic, roughness) * radiance;
+        } else {
+            color += brdf(n, v, l, baseColor.rgb, metallic, roughness) * radiance;
+        }
+    }
+
+    // A constant diffuse ambient term until there is image based lighting.
+    vec3 ambient = vec3(0.03) * baseColor.rgb * occlusion;
+    ambient += environmentReflection(n, v, baseColor.rgb, metallic, roughness) * occlusion;
+    outColor = vec4(color + ambient + emissive, baseColor.a);
+}
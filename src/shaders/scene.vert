@@ -0,0 +1,40 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view;
+    mat4 proj;
+    mat4 view_proj;
+    vec4 camera_position;
+} frame;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    uint material_index;
+} pcs;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec4 inColor;
+layout(location = 3) in vec4 inTangent;
+layout(location = 4) in vec2 inUv0;
+layout(location = 5) in vec2 inUv1;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 fragTangent;
+layout(location = 3) out vec4 fragColor;
+layout(location = 4) out vec2 fragUv0;
+layout(location = 5) out vec2 fragUv1;
+
+void main() {
+    vec4 position = pcs.model * vec4(inPosition, 1.0);
+    gl_Position = frame.view_proj * position;
+
+    mat3 normalMatrix = transpose(inverse(mat3(pcs.model)));
+    fragPosition = position.xyz;
+    fragNormal = normalMatrix * inNormal;
+    fragTangent = vec4(mat3(pcs.model) * inTangent.xyz, inTangent.w);
+    fragColor = inColor;
+    fragUv0 = inUv0;
+    fragUv1 = inUv1;
+}
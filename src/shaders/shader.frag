@@ -1,9 +1,21 @@
 #version 450
 
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    uint material_index;
+} pcs;
+
 layout(location = 0) in vec3 fragColor;
 
 layout(location = 0) out vec4 outColor;
 
+vec3 tints[4] = vec3[](
+    vec3(1.0, 1.0, 1.0),
+    vec3(1.0, 0.6, 0.6),
+    vec3(0.6, 1.0, 0.6),
+    vec3(0.6, 0.6, 1.0)
+);
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
+    outColor = vec4(fragColor * tints[pcs.material_index % 4], 1.0);
 }
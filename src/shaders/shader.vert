@@ -1,5 +1,10 @@
 #version 450
 
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    uint material_index;
+} pcs;
+
 layout(location=0) out vec3 fragColor;
 
 vec2 positions[3] = vec2[](
@@ -15,7 +20,6 @@ vec3 colors[3] = vec3[](
 );
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
+    gl_Position = pcs.model * vec4(positions[gl_VertexIndex], 0.0, 1.0);
     fragColor = colors[gl_VertexIndex];
 }
-
@@ -0,0 +1,11 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    mat4 light_mvp;
+} pcs;
+
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = pcs.light_mvp * vec4(inPosition, 1.0);
+}
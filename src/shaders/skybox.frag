@@ -0,0 +1,12 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform textureCube environmentMap;
+layout(set = 1, binding = 1) uniform sampler environmentSampler;
+
+layout(location = 0) in vec3 fragDirection;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = vec4(texture(samplerCube(environmentMap, environmentSampler), fragDirection).rgb, 1.0);
+}
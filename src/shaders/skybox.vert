@@ -0,0 +1,27 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view;
+    mat4 proj;
+    mat4 view_proj;
+    vec4 camera_position;
+} frame;
+
+layout(location = 0) out vec3 fragDirection;
+
+// A triangle covering the screen on the far plane.
+vec2 positions[3] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2(3.0, -1.0),
+    vec2(-1.0, 3.0)
+);
+
+void main() {
+    vec2 position = positions[gl_VertexIndex];
+    gl_Position = vec4(position, 1.0, 1.0);
+
+    // Unproject to a view space point on the far plane and rotate the
+    // direction to it into world space.
+    vec4 view = inverse(frame.proj) * vec4(position, 1.0, 1.0);
+    fragDirection = transpose(mat3(frame.view)) * (view.xyz / view.w);
+}
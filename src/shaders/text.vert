@@ -0,0 +1,21 @@
+#version 450
+
+// Maps glyph quads to clip space: pixels for screen text, the scene's view
+// projection for world text.
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+    vec4 outputParams;
+} pc;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec2 inUv;
+layout(location = 2) in vec4 inColor;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragColor;
+
+void main() {
+    gl_Position = pc.transform * vec4(inPosition, 1.0);
+    fragUv = inUv;
+    fragColor = inColor;
+}
@@ -0,0 +1,423 @@
+use anyhow::{anyhow, Result};
+
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::create_buffer;
+use crate::core::debug_utils::{set_object_name, set_object_names};
+use crate::command::single_time::{begin_single_time_commands, end_single_time_commands};
+use crate::descriptor;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::image::{create_layer_view, create_layered_image, get_supported_format};
+use crate::mesh::{self, Vertex};
+use crate::pipeline::graphics::{self, create_graphics_pipeline, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range, PushConstants};
+use crate::scene::DrawItem;
+use crate::scene::draw::SceneRenderer;
+use crate::shadow::{ShadowSettings, ShadowUniforms, ShadowView, SHADOW_LAYERS};
+
+type Mat4 = cgmath::Matrix4<f32>;
+
+/// Per-draw data for the shadow pipeline.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowPushConstants {
+    /// Model to shadow map clip space.
+    pub light_mvp: Mat4,
+}
+
+const _: () = assert!(size_of::<ShadowPushConstants>() == 64);
+
+// SAFETY: `#[repr(C)]` and `Mat4` is all `f32`s, so there is no padding.
+unsafe impl PushConstants for ShadowPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+/// A layered depth image with one layer per `ShadowView`, the depth-only
+/// pass that renders scene meshes into it and the descriptor sets the scene
+/// samples it through (set 2).
+#[derive(Clone, Debug, Default)]
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+    pub format: vk::Format,
+    pub image: vk::Image,
+    pub image_memory: vk::DeviceMemory,
+    /// A `2D_ARRAY` view of every layer, for sampling.
+    pub array_view: vk::ImageView,
+    /// One view and framebuffer per layer, for rendering.
+    pub layer_views: Vec<vk::ImageView>,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub render_pass: vk::RenderPass,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    /// Compares against the stored depth, filtering the results when the
+    /// format supports it.
+    pub sampler: vk::Sampler,
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub sets: Vec<vk::DescriptorSet>,
+    pub uniform_buffers: Vec<vk::Buffer>,
+    pub uniform_buffers_memory: Vec<vk::DeviceMemory>,
+}
+
+/// Clears a layer, writes depth and leaves it ready for sampling. The
+/// dependencies also order it after the previous frame's sampling.
+unsafe fn create_shadow_render_pass(device: &Device, format: vk::Format) -> Result<vk::RenderPass> {
+    let attachment = vk::AttachmentDescription::builder()
+        .format(format)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+    let attachment_ref = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&attachment_ref);
+
+    let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_stage_mask(fragment_tests)
+        .dst_access_mask(
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        );
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(fragment_tests)
+        .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+    let attachments = &[attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+
+    Ok(device.create_render_pass(&info, None)?)
+}
+
+/// Moves every layer to `SHADER_READ_ONLY_OPTIMAL`, so layers that aren't
+/// rendered in a frame can still be bound.
+unsafe fn transition_to_shader_read(device: &Device, data: &AppData, image: vk::Image) -> Result<()> {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(SHADOW_LAYERS as u32);
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range);
+
+    let command_buffer = begin_single_time_commands(device, data.command_pool)?;
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TOP_OF_PIPE,
+        vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+    end_single_time_commands(device, data.command_pool, data.graphics_queue, command_buffer, None, None)
+}
+
+/// Draws scene meshes with only their positions and a depth bias against
+/// shadow acne, without culling so thin geometry still casts shadows.
+unsafe fn create_shadow_pipeline(
+    device: &Device,
+    layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+    settings: &ShadowSettings,
+) -> Result<vk::Pipeline> {
+    let bindings = &[Vertex::binding_description()];
+    let attributes = &Vertex::attribute_descriptions()[..1];
+    let info = GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        cull_mode: vk::CullModeFlags::NONE,
+        depth_test: true,
+        depth_write: true,
+        depth_bias: Some(settings.depth_bias),
+        color_attachment_count: 0,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/shadow_vert.spv")[..],
+            &[],
+            layout,
+            render_pass,
+        )
+    };
+    create_graphics_pipeline(device, &info)
+}
+
+/// Samples return 1 where the reference depth is less than or equal to the
+/// stored one (lit), and outside the map.
+unsafe fn create_comparison_sampler(device: &Device, filter: vk::Filter) -> Result<vk::Sampler> {
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .compare_enable(true)
+        .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .min_lod(0.0)
+        .max_lod(0.0)
+        .unnormalized_coordinates(false);
+
+    Ok(device.create_sampler(&info, None)?)
+}
+
+pub unsafe fn create_shadow_maps(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    settings: ShadowSettings,
+) -> Result<()> {
+    let format = get_supported_format(
+        instance,
+        data,
+        &[vk::Format::D32_SFLOAT, vk::Format::D16_UNORM],
+        vk::ImageTiling::OPTIMAL,
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+    ).ok_or_else(|| anyhow!("Failed to find a depth format that can be sampled for shadow maps."))?;
+    let filterable = get_supported_format(
+        instance,
+        data,
+        &[format],
+        vk::ImageTiling::OPTIMAL,
+        vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+    ).is_some();
+
+    let resolution = settings.resolution;
+    let layers = SHADOW_LAYERS as u32;
+    let (image, image_memory) = create_layered_image(
+        instance,
+        device,
+        data,
+        resolution,
+        resolution,
+        layers,
+        vk::ImageCreateFlags::empty(),
+        format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+    transition_to_shader_read(device, data, image)?;
+
+    let depth = vk::ImageAspectFlags::DEPTH;
+    let array_view = create_layer_view(device, image, format, depth, vk::ImageViewType::_2D_ARRAY, 0, layers)?;
+    let layer_views = (0..layers)
+        .map(|layer| create_layer_view(device, image, format, depth, vk::ImageViewType::_2D, layer, 1))
+        .collect::<Result<Vec<_>>>()?;
+
+    let render_pass = create_shadow_render_pass(device, format)?;
+    let framebuffers = layer_views
+        .iter()
+        .map(|view| {
+            let attachments = &[*view];
+            let info = vk::FramebufferCreateInfo::builder()
+                .render_pass(render_pass)
+                .attachments(attachments)
+                .width(resolution)
+                .height(resolution)
+                .layers(1);
+            device.create_framebuffer(&info, None)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let push_constant_ranges = &[push_constant_range::<ShadowPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .push_constant_ranges(push_constant_ranges);
+    let pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let pipeline = create_shadow_pipeline(device, pipeline_layout, render_pass, &settings)?;
+
+    let filter = if filterable { vk::Filter::LINEAR } else { vk::Filter::NEAREST };
+    let sampler = create_comparison_sampler(device, filter)?;
+
+    let stages = vk::ShaderStageFlags::FRAGMENT;
+    let bindings = &[
+        descriptor::uniform_buffer_binding(0, stages),
+        descriptor::sampled_image_binding(1, stages),
+        descriptor::sampler_binding(2, stages),
+    ];
+    let set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+
+    let size = |type_| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32)
+            .build()
+    };
+    let sizes = &[
+        size(vk::DescriptorType::UNIFORM_BUFFER),
+        size(vk::DescriptorType::SAMPLED_IMAGE),
+        size(vk::DescriptorType::SAMPLER),
+    ];
+    let descriptor_pool = descriptor::create_descriptor_pool(device, sizes, MAX_FRAMES_IN_FLIGHT as u32)?;
+
+    let mut shadows = ShadowMaps {
+        settings,
+        format,
+        image,
+        image_memory,
+        array_view,
+        layer_views,
+        framebuffers,
+        render_pass,
+        pipeline_layout,
+        pipeline,
+        sampler,
+        set_layout,
+        descriptor_pool,
+        ..ShadowMaps::default()
+    };
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            size_of::<ShadowUniforms>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        let set = descriptor::allocate_descriptor_set(device, descriptor_pool, set_layout)?;
+        descriptor::write_uniform_buffer(device, set, 0, buffer);
+        descriptor::write_sampled_image(device, set, 1, array_view);
+        descriptor::write_sampler(device, set, 2, sampler);
+
+        shadows.uniform_buffers.push(buffer);
+        shadows.uniform_buffers_memory.push(buffer_memory);
+        shadows.sets.push(set);
+    }
+
+    data.shadows = shadows;
+
+    let shadows = &data.shadows;
+    set_object_name(device, data, shadows.image, "shadow maps");
+    set_object_name(device, data, shadows.array_view, "shadow maps array view");
+    set_object_names(device, data, &shadows.layer_views, "shadow map view");
+    set_object_names(device, data, &shadows.framebuffers, "shadow map framebuffer");
+    set_object_name(device, data, shadows.render_pass, "shadow render pass");
+    set_object_name(device, data, shadows.pipeline_layout, "shadow pipeline layout");
+    set_object_name(device, data, shadows.pipeline, "shadow pipeline");
+    set_object_name(device, data, shadows.sampler, "shadow sampler");
+    set_object_names(device, data, &shadows.uniform_buffers, "shadow uniforms");
+
+    Ok(())
+}
+
+/// Writes the shadow uniforms for `frame`, whose previous submission must have completed.
+pub unsafe fn update_shadow_uniforms(
+    device: &Device,
+    shadows: &ShadowMaps,
+    frame: usize,
+    uniforms: &ShadowUniforms,
+) -> Result<()> {
+    let memory = device.map_memory(
+        shadows.uniform_buffers_memory[frame],
+        0,
+        size_of::<ShadowUniforms>() as vk::DeviceSize,
+        vk::MemoryMapFlags::empty(),
+    )?;
+    memcpy(uniforms, memory.cast(), 1);
+    device.unmap_memory(shadows.uniform_buffers_memory[frame]);
+
+    Ok(())
+}
+
+/// Records rendering `items` into each of `views`' layers. Must be recorded
+/// outside of a render pass, before the scene is drawn.
+pub unsafe fn cmd_render_shadows(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    shadows: &ShadowMaps,
+    renderer: &SceneRenderer,
+    views: &[ShadowView],
+    items: &[DrawItem],
+) {
+    let extent = vk::Extent2D { width: shadows.settings.resolution, height: shadows.settings.resolution };
+    let render_area = vk::Rect2D::builder()
+        .offset(vk::Offset2D::default())
+        .extent(extent);
+    let clear_values = &[vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+    }];
+
+    for view in views {
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(shadows.render_pass)
+            .framebuffer(shadows.framebuffers[view.layer])
+            .render_area(render_area)
+            .clear_values(clear_values);
+        device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+        graphics::cmd_set_viewport_and_scissor(device, command_buffer, extent);
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, shadows.pipeline);
+
+        for item in items {
+            let mesh = &renderer.meshes[item.mesh.0];
+            let push_constants = ShadowPushConstants { light_mvp: view.view_projection * item.model };
+            cmd_push_constants(device, command_buffer, shadows.pipeline_layout, &push_constants);
+            mesh::cmd_bind_mesh(device, command_buffer, mesh);
+            device.cmd_draw_indexed(command_buffer, mesh.index_count, 1, 0, 0, 0);
+        }
+
+        device.cmd_end_render_pass(command_buffer);
+    }
+}
+
+pub unsafe fn destroy_shadow_maps(device: &Device, shadows: &mut ShadowMaps) {
+    shadows.uniform_buffers
+        .iter()
+        .for_each(|b| device.destroy_buffer(*b, None));
+    shadows.uniform_buffers_memory
+        .iter()
+        .for_each(|m| device.free_memory(*m, None));
+    device.destroy_descriptor_pool(shadows.descriptor_pool, None);
+    device.destroy_descriptor_set_layout(shadows.set_layout, None);
+    device.destroy_sampler(shadows.sampler, None);
+    device.destroy_pipeline(shadows.pipeline, None);
+    device.destroy_pipeline_layout(shadows.pipeline_layout, None);
+    shadows.framebuffers
+        .iter()
+        .for_each(|f| device.destroy_framebuffer(*f, None));
+    device.destroy_render_pass(shadows.render_pass, None);
+    shadows.layer_views
+        .iter()
+        .for_each(|v| device.destroy_image_view(*v, None));
+    device.destroy_image_view(shadows.array_view, None);
+    device.destroy_image(shadows.image, None);
+    device.free_memory(shadows.image_memory, None);
+    *shadows = ShadowMaps::default();
+}
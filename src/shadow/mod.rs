@@ -0,0 +1,353 @@
+pub mod maps;
+
+use cgmath::{vec3, vec4, EuclideanSpace, InnerSpace, Matrix4, Rad, SquareMatrix};
+
+use crate::camera::{Camera, OPENGL_TO_VULKAN};
+use crate::light::{world_direction, LightKind};
+use crate::pipeline::graphics::DepthBias;
+use crate::scene::LightItem;
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Point3 = cgmath::Point3<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// How many cascades a directional light can be split into.
+pub const MAX_CASCADES: usize = 4;
+/// How many spot lights can cast shadows at once.
+pub const MAX_SPOT_SHADOWS: usize = 4;
+/// Layers of the shadow map: the cascades of one directional light followed
+/// by one layer per spot light.
+pub const SHADOW_LAYERS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS;
+
+/// Spot light shadows start this far from the light.
+const SPOT_NEAR: f32 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of each shadow map layer in texels.
+    pub resolution: u32,
+    /// How many cascades the directional light uses, at most `MAX_CASCADES`.
+    pub cascade_count: usize,
+    /// Blends cascade splits from uniform (0) to logarithmic (1).
+    pub split_lambda: f32,
+    /// How far from the camera directional light shadows reach, and how far
+    /// spot lights without a range cast shadows.
+    pub max_distance: f32,
+    /// How far towards a directional light occluders outside the view still
+    /// cast shadows into it.
+    pub caster_distance: f32,
+    pub depth_bias: DepthBias,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            cascade_count: MAX_CASCADES,
+            split_lambda: 0.8,
+            max_distance: 250.0,
+            caster_distance: 100.0,
+            depth_bias: DepthBias { constant_factor: 2.0, slope_factor: 2.5, clamp: 0.0 },
+        }
+    }
+}
+
+/// The view space distances at which each of `count` cascades between `near`
+/// and `far` ends, using the practical split scheme: a blend by `lambda` of
+/// logarithmic splits (even texel density) and uniform ones.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let log = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * log + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// The world space corners of the part of `camera`'s view volume between the
+/// view space distances `near` and `far`: the near corners, then the far ones.
+pub fn frustum_corners(camera: &Camera, near: f32, far: f32) -> [Point3; 8] {
+    let projection = camera.projection.with_depth_range(near, far).matrix(camera.aspect);
+    let inverse = (projection * camera.view()).invert().unwrap_or(Matrix4::identity());
+
+    let mut corners = [Point3::origin(); 8];
+    for (index, corner) in corners.iter_mut().enumerate() {
+        let x = if index & 1 == 0 { -1.0 } else { 1.0 };
+        let y = if index & 2 == 0 { -1.0 } else { 1.0 };
+        let z = if index & 4 == 0 { 0.0 } else { 1.0 };
+        let world = inverse * vec4(x, y, z, 1.0);
+        *corner = Point3::from_homogeneous(world);
+    }
+    corners
+}
+
+/// An up vector for looking along `direction` that isn't parallel to it.
+fn up_for(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 { vec3(0.0, 0.0, 1.0) } else { vec3(0.0, 1.0, 0.0) }
+}
+
+/// The view projection of a directional light shining along `direction` onto
+/// a shadow map of `resolution` texels that covers `corners`.
+///
+/// The map fits the corners' bounding sphere, so its size doesn't change as
+/// the camera turns, and moves in whole texels, so shadow edges don't shimmer
+/// as the camera moves. Occluders up to `caster_distance` beyond the sphere
+/// towards the light are included.
+pub fn directional_light_matrix(direction: Vec3, corners: &[Point3; 8], resolution: u32, caster_distance: f32) -> Mat4 {
+    let direction = direction.normalize();
+    let center = Point3::centroid(corners);
+    let radius = corners.iter().map(|c| (c - center).magnitude()).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+    // Leave room for the snapping below.
+    let radius = radius + 2.0 * radius / resolution as f32;
+
+    let view = Matrix4::look_to_rh(center, direction, up_for(direction));
+    let projection = cgmath::ortho(-radius, radius, -radius, radius, -(radius + caster_distance), radius);
+    let mut matrix = OPENGL_TO_VULKAN * projection * view;
+
+    let half_resolution = resolution as f32 / 2.0;
+    let origin = matrix * vec4(0.0, 0.0, 0.0, 1.0);
+    matrix.w.x += (origin.x * half_resolution).round() / half_resolution - origin.x;
+    matrix.w.y += (origin.y * half_resolution).round() / half_resolution - origin.y;
+    matrix
+}
+
+/// The view projection of a spot light at `position` shining along
+/// `direction`, covering its outer cone up to `range`.
+pub fn spot_light_matrix(position: Point3, direction: Vec3, outer_cone: Rad<f32>, range: f32) -> Mat4 {
+    let direction = direction.normalize();
+    let view = Matrix4::look_to_rh(position, direction, up_for(direction));
+    let fovy = Rad((outer_cone.0 * 2.0).clamp(0.01, 3.1));
+    OPENGL_TO_VULKAN * cgmath::perspective(fovy, 1.0, SPOT_NEAR, range.max(SPOT_NEAR * 2.0)) * view
+}
+
+/// The shadow data read by the scene fragment shader, set 2 binding 0 (std140).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowUniforms {
+    /// World to shadow map clip space for each layer.
+    pub matrices: [Mat4; SHADOW_LAYERS],
+    /// The view space distance at which each cascade ends.
+    pub cascade_splits: Vec4,
+    pub cascade_count: u32,
+    /// The size of a shadow map texel in texture coordinates.
+    pub texel_size: f32,
+    pub _padding: [u32; 2],
+}
+
+/// A shadow map layer to render, with the light's view projection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowView {
+    pub layer: usize,
+    pub view_projection: Mat4,
+}
+
+/// Which lights get which shadow map layers this frame.
+///
+/// The first shadow casting directional light gets the cascades and the
+/// first `MAX_SPOT_SHADOWS` shadow casting spot lights get a layer each;
+/// other lights are unshadowed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShadowPlan {
+    pub views: Vec<ShadowView>,
+    pub uniforms: ShadowUniforms,
+    /// For each light, its first layer or -1, see `GpuLight::shadow_layer`.
+    pub light_layers: Vec<i32>,
+}
+
+impl ShadowPlan {
+    pub fn new(lights: &[LightItem], camera: &Camera, settings: &ShadowSettings) -> Self {
+        let cascade_count = settings.cascade_count.clamp(1, MAX_CASCADES);
+        let mut plan = Self {
+            views: Vec::new(),
+            uniforms: ShadowUniforms {
+                matrices: [Matrix4::identity(); SHADOW_LAYERS],
+                cascade_splits: vec4(0.0, 0.0, 0.0, 0.0),
+                cascade_count: 0,
+                texel_size: 1.0 / settings.resolution as f32,
+                _padding: [0; 2],
+            },
+            light_layers: vec![-1; lights.len()],
+        };
+
+        let mut next_spot_layer = MAX_CASCADES;
+        for (index, item) in lights.iter().enumerate() {
+            if !item.light.cast_shadows {
+                continue;
+            }
+
+            let direction = world_direction(item.world);
+            match item.light.kind {
+                LightKind::Directional if plan.uniforms.cascade_count == 0 => {
+                    let (near, far) = camera.projection.depth_range();
+                    let far = far.min(settings.max_distance);
+                    let splits = cascade_splits(near, far, cascade_count, settings.split_lambda);
+
+                    let mut cascade_near = near;
+                    for (layer, split) in splits.iter().enumerate() {
+                        let corners = frustum_corners(camera, cascade_near, *split);
+                        let matrix = directional_light_matrix(direction, &corners, settings.resolution, settings.caster_distance);
+                        plan.add_view(layer, matrix);
+                        plan.uniforms.cascade_splits[layer] = *split;
+                        cascade_near = *split;
+                    }
+                    plan.uniforms.cascade_count = cascade_count as u32;
+                    plan.light_layers[index] = 0;
+                }
+                LightKind::Spot { outer_cone, .. } if next_spot_layer < SHADOW_LAYERS => {
+                    let position = Point3::from_vec(item.world.w.truncate());
+                    let range = item.light.range.unwrap_or(settings.max_distance);
+                    plan.add_view(next_spot_layer, spot_light_matrix(position, direction, outer_cone, range));
+                    plan.light_layers[index] = next_spot_layer as i32;
+                    next_spot_layer += 1;
+                }
+                _ => {}
+            }
+        }
+
+        plan
+    }
+
+    fn add_view(&mut self, layer: usize, view_projection: Mat4) {
+        self.uniforms.matrices[layer] = view_projection;
+        self.views.push(ShadowView { layer, view_projection });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, point3, Deg, Quaternion, Rotation3};
+
+    use crate::camera::Projection;
+    use crate::light::Light;
+    use crate::scene::NodeId;
+
+    fn project(matrix: Mat4, point: Point3) -> Vec3 {
+        let clip = matrix * point.to_homogeneous();
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn uniform_and_logarithmic_splits() {
+        assert_abs_diff_eq!(&cascade_splits(1.0, 101.0, 4, 0.0)[..], &[26.0, 51.0, 76.0, 101.0][..], epsilon = 1e-4);
+        assert_abs_diff_eq!(&cascade_splits(1.0, 16.0, 4, 1.0)[..], &[2.0, 4.0, 8.0, 16.0][..], epsilon = 1e-4);
+
+        let blended = cascade_splits(0.1, 200.0, 4, 0.8);
+        assert!(blended.windows(2).all(|w| w[0] < w[1]));
+        assert_abs_diff_eq!(blended[3], 200.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn frustum_corners_span_the_slice() {
+        let camera = Camera {
+            eye: point3(0.0, 0.0, 0.0),
+            target: point3(0.0, 0.0, -1.0),
+            projection: Projection::Perspective { fovy: Deg(90.0), near: 0.1, far: 100.0 },
+            aspect: 2.0,
+            ..Camera::default()
+        };
+        let corners = frustum_corners(&camera, 1.0, 3.0);
+
+        // tan(45°) = 1, so the half height is the distance and the half width
+        // twice that. Clip space Y points down, so -1 is the top.
+        assert_abs_diff_eq!(corners[0], point3(-2.0, 1.0, -1.0), epsilon = 1e-4);
+        assert_abs_diff_eq!(corners[3], point3(2.0, -1.0, -1.0), epsilon = 1e-4);
+        assert_abs_diff_eq!(corners[4], point3(-6.0, 3.0, -3.0), epsilon = 1e-3);
+        assert_abs_diff_eq!(corners[7], point3(6.0, -3.0, -3.0), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn directional_matrix_covers_the_corners() {
+        let camera = Camera { eye: point3(10.0, 5.0, 20.0), ..Camera::default() };
+        let corners = frustum_corners(&camera, 5.0, 40.0);
+        let direction = vec3(-1.0, -2.0, 0.5);
+        let matrix = directional_light_matrix(direction, &corners, 1024, 50.0);
+
+        for corner in corners {
+            let p = project(matrix, corner);
+            assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0, "{:?} is outside the map", p);
+            assert!((0.0..=1.0).contains(&p.z), "{:?} is outside the depth range", p);
+        }
+
+        // Further along the light is deeper.
+        let center = Point3::centroid(&corners);
+        assert!(project(matrix, center + direction).z > project(matrix, center).z);
+        // Occluders within the caster distance towards the light are kept.
+        assert!(project(matrix, center - direction.normalize() * 60.0).z >= 0.0);
+    }
+
+    #[test]
+    fn directional_matrix_snaps_to_texels() {
+        let direction = vec3(0.3, -1.0, 0.2);
+        for offset in [0.0, 0.013, 0.377] {
+            let camera = Camera { eye: point3(offset, 2.0, 10.0 + offset), ..Camera::default() };
+            let corners = frustum_corners(&camera, 0.1, 30.0);
+            let matrix = directional_light_matrix(direction, &corners, 512, 10.0);
+
+            let origin = project(matrix, Point3::origin()) * 256.0;
+            assert_abs_diff_eq!(origin.x, origin.x.round(), epsilon = 1e-2);
+            assert_abs_diff_eq!(origin.y, origin.y.round(), epsilon = 1e-2);
+        }
+    }
+
+    #[test]
+    fn spot_matrix_covers_the_cone() {
+        let position = point3(1.0, 10.0, 2.0);
+        let down = vec3(0.0, -1.0, 0.0);
+        let matrix = spot_light_matrix(position, down, Deg(30.0).into(), 20.0);
+
+        let center = project(matrix, position + down * 5.0);
+        assert_abs_diff_eq!(center.x, 0.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(center.y, 0.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(project(matrix, position + down * 20.0).z, 1.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(project(matrix, position + down * SPOT_NEAR).z, 0.0, epsilon = 1e-4);
+
+        // A point on the outer cone lands on the edge of the map.
+        let edge = position + down * 5.0 + vec3(5.0 * Rad::from(Deg(30.0f32)).0.tan(), 0.0, 0.0);
+        let edge = project(matrix, edge);
+        assert_abs_diff_eq!(edge.x.abs().max(edge.y.abs()), 1.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn plan_assigns_layers_within_budget() {
+        let item = |light: Light| LightItem {
+            node: NodeId(0),
+            light,
+            world: Matrix4::from(Quaternion::from_angle_x(Deg(-90.0))),
+        };
+        let white = vec3(1.0, 1.0, 1.0);
+        let spot = Light::spot(white, 1.0, Some(10.0), Deg(10.0).into(), Deg(20.0).into()).with_shadows();
+
+        let mut lights = vec![
+            item(Light::directional(white, 1.0)),
+            item(Light::directional(white, 1.0).with_shadows()),
+            item(Light::directional(white, 1.0).with_shadows()),
+            item(Light::point(white, 1.0, None).with_shadows()),
+        ];
+        lights.extend((0..MAX_SPOT_SHADOWS + 1).map(|_| item(spot)));
+
+        let settings = ShadowSettings { cascade_count: 3, ..ShadowSettings::default() };
+        let plan = ShadowPlan::new(&lights, &Camera::default(), &settings);
+
+        let expected_spots = (MAX_CASCADES..SHADOW_LAYERS).map(|l| l as i32);
+        let expected = [-1, 0, -1, -1].into_iter().chain(expected_spots).chain([-1]).collect::<Vec<_>>();
+        assert_eq!(plan.light_layers, expected);
+
+        let layers = plan.views.iter().map(|v| v.layer).collect::<Vec<_>>();
+        assert_eq!(layers, [0, 1, 2, 4, 5, 6, 7]);
+        assert_eq!(plan.uniforms.cascade_count, 3);
+        assert_eq!(plan.uniforms.cascade_splits.w, 0.0);
+        assert_abs_diff_eq!(plan.uniforms.cascade_splits.z, settings.max_distance, epsilon = 1e-3);
+        assert_eq!(plan.uniforms.matrices[5], plan.views[4].view_projection);
+    }
+
+    #[test]
+    fn uniforms_match_std140() {
+        assert_eq!(std::mem::size_of::<ShadowUniforms>(), 64 * SHADOW_LAYERS + 32);
+    }
+}
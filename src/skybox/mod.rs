@@ -0,0 +1,138 @@
+use anyhow::Result;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_name;
+use crate::descriptor;
+use crate::pipeline::graphics::{create_graphics_pipeline, GraphicsPipelineInfo};
+use crate::texture::{self, Texture};
+use crate::texture::cubemap::{self, CubemapData};
+
+/// An environment cubemap drawn behind everything else, whose descriptor set
+/// other pipelines can bind to reflect it.
+#[derive(Clone, Debug, Default)]
+pub struct Skybox {
+    pub environment: Texture,
+    /// The cubemap at binding 0 and its sampler at binding 1. Set 1 of the
+    /// skybox pipeline and set 3 of the scene pipelines.
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub set: vk::DescriptorSet,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+/// Draws a fullscreen triangle on the far plane, so it only covers pixels
+/// nothing else was drawn to and has to come after opaque geometry.
+unsafe fn create_skybox_pipeline(device: &Device, data: &AppData, layout: vk::PipelineLayout) -> Result<vk::Pipeline> {
+    let info = GraphicsPipelineInfo {
+        cull_mode: vk::CullModeFlags::NONE,
+        depth_test: true,
+        depth_write: false,
+        // The depth buffer is cleared to the far plane, which has to pass.
+        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        samples: data.msaa_samples,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/skybox_vert.spv")[..],
+            &include_bytes!("../shaders/skybox_frag.spv")[..],
+            layout,
+            data.render_pass,
+        )
+    };
+    create_graphics_pipeline(device, &info)
+}
+
+/// Creates the skybox with `environment` (sRGB) as its cubemap.
+pub unsafe fn create_skybox(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    environment: &CubemapData,
+) -> Result<()> {
+    let stages = vk::ShaderStageFlags::FRAGMENT;
+    let bindings = &[
+        descriptor::sampled_image_binding(0, stages),
+        descriptor::sampler_binding(1, stages),
+    ];
+    let set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+
+    let size = |type_| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(1)
+            .build()
+    };
+    let sizes = &[size(vk::DescriptorType::SAMPLED_IMAGE), size(vk::DescriptorType::SAMPLER)];
+    let descriptor_pool = descriptor::create_descriptor_pool(device, sizes, 1)?;
+    let set = descriptor::allocate_descriptor_set(device, descriptor_pool, set_layout)?;
+
+    let set_layouts = &[data.frame_set_layout, set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts);
+    let pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let pipeline = create_skybox_pipeline(device, data, pipeline_layout)?;
+
+    data.skybox = Skybox {
+        environment: Texture::default(),
+        set_layout,
+        descriptor_pool,
+        set,
+        pipeline_layout,
+        pipeline,
+    };
+    set_object_name(device, data, pipeline_layout, "skybox pipeline layout");
+    set_object_name(device, data, pipeline, "skybox pipeline");
+
+    set_environment(instance, device, data, environment)
+}
+
+/// Replaces the cubemap. The old one must no longer be in use.
+pub unsafe fn set_environment(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    environment: &CubemapData,
+) -> Result<()> {
+    let cubemap = cubemap::create_cubemap(instance, device, data, environment, vk::Format::R8G8B8A8_SRGB)?;
+    let skybox = &mut data.skybox;
+    texture::destroy_texture(device, &mut skybox.environment);
+    skybox.environment = cubemap;
+
+    descriptor::write_sampled_image(device, skybox.set, 0, cubemap.image_view);
+    descriptor::write_sampler(device, skybox.set, 1, cubemap.sampler);
+
+    set_object_name(device, data, cubemap.image, "skybox environment");
+    set_object_name(device, data, cubemap.image_view, "skybox environment view");
+
+    Ok(())
+}
+
+/// Records drawing the skybox with the frame's uniforms in `frame_set`.
+pub unsafe fn cmd_draw_skybox(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    skybox: &Skybox,
+    frame_set: vk::DescriptorSet,
+) {
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, skybox.pipeline);
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        skybox.pipeline_layout,
+        0,
+        &[frame_set, skybox.set],
+        &[],
+    );
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+}
+
+pub unsafe fn destroy_skybox(device: &Device, skybox: &mut Skybox) {
+    device.destroy_pipeline(skybox.pipeline, None);
+    device.destroy_pipeline_layout(skybox.pipeline_layout, None);
+    device.destroy_descriptor_pool(skybox.descriptor_pool, None);
+    device.destroy_descriptor_set_layout(skybox.set_layout, None);
+    texture::destroy_texture(device, &mut skybox.environment);
+    *skybox = Skybox::default();
+}
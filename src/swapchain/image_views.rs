@@ -3,6 +3,7 @@ use anyhow::Result;
 use vulkanalia::prelude::v1_4::*;
 
 use crate::app::data::AppData;
+use crate::core::debug_utils::set_object_names;
 
 pub unsafe fn create_swapchain_image_views(
     device: &Device,
@@ -35,6 +36,7 @@ pub unsafe fn create_swapchain_image_views(
             device.create_image_view(&info, None)
         })
         .collect::<Result<Vec<_>, _>>()?;
+    set_object_names(device, data, &data.swapchain_image_views, "swapchain image view");
 
     Ok(())
 }
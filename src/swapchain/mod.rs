@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod swapchain;
 pub mod support;
 pub mod image_views;
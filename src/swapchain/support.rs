@@ -1,4 +1,6 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
 use winit::window::Window;
 
 use vulkanalia::vk::KhrSurfaceExtension;
@@ -33,28 +35,218 @@ impl SwapchainSupport {
     }
 }
 
+const fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+    vk::SurfaceFormatKHR { format, color_space }
+}
+
+/// Surface formats that can be asked for by name. The HDR10 and scRGB color
+/// spaces are only offered with `VK_EXT_swapchain_colorspace`.
+pub const SURFACE_FORMATS: &[(&str, vk::SurfaceFormatKHR)] = &[
+    ("bgra8_srgb", surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR)),
+    ("rgba8_srgb", surface_format(vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR)),
+    ("bgra8_unorm", surface_format(vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR)),
+    ("rgba8_unorm", surface_format(vk::Format::R8G8B8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR)),
+    ("rgb10a2", surface_format(vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::SRGB_NONLINEAR)),
+    ("bgr10a2", surface_format(vk::Format::A2R10G10B10_UNORM_PACK32, vk::ColorSpaceKHR::SRGB_NONLINEAR)),
+    ("hdr10", surface_format(vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT)),
+    ("hdr10_bgr", surface_format(vk::Format::A2R10G10B10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT)),
+    ("scrgb", surface_format(vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT)),
+];
+
+/// 8-bit sRGB, which every desktop surface offers in one of these orders.
+pub const DEFAULT_SURFACE_FORMATS: &[vk::SurfaceFormatKHR] = &[
+    surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+    surface_format(vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+];
+
+/// Parses a comma separated list of `SURFACE_FORMATS` names, e.g. `hdr10,rgb10a2,bgra8_srgb`.
+pub fn parse_surface_formats(list: &str) -> Result<Vec<vk::SurfaceFormatKHR>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            SURFACE_FORMATS
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, f)| *f)
+                .ok_or_else(|| anyhow!(
+                    "Unknown surface format `{}`, expected one of {:?}.",
+                    name, SURFACE_FORMATS.iter().map(|(n, _)| *n).collect::<Vec<_>>(),
+                ))
+        })
+        .collect()
+}
+
+/// How the final post-processing pass has to encode linear colors for a surface format.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// An `_SRGB` format, which encodes on write.
+    #[default]
+    Hardware,
+    /// A `_UNORM` format in the sRGB color space; the shader applies the sRGB curve.
+    Srgb,
+    /// HDR10: BT.2020 primaries with the PQ (ST 2084) curve.
+    Pq,
+    /// scRGB: linear BT.709 in a float format, where 1 is 80 nits.
+    ScRgb,
+}
+
+impl OutputEncoding {
+    /// The encoding for `format`, or `None` if colors can't be encoded for it.
+    pub fn of(format: vk::SurfaceFormatKHR) -> Option<Self> {
+        let srgb_format = matches!(
+            format.format,
+            vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+        );
+        match format.color_space {
+            vk::ColorSpaceKHR::SRGB_NONLINEAR if srgb_format => Some(Self::Hardware),
+            vk::ColorSpaceKHR::SRGB_NONLINEAR => Some(Self::Srgb),
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => Some(Self::Pq),
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => Some(Self::ScRgb),
+            _ => None,
+        }
+    }
+
+    pub fn is_hdr(self) -> bool {
+        matches!(self, Self::Pq | Self::ScRgb)
+    }
+}
+
+/// The first of `preferences` that is `available`, otherwise the first
+/// available format colors can be encoded for.
 pub fn get_swapchain_surface_format(
-        formats: &[vk::SurfaceFormatKHR]
-) -> vk::SurfaceFormatKHR {
-    *formats
+    available: &[vk::SurfaceFormatKHR],
+    preferences: &[vk::SurfaceFormatKHR],
+) -> Result<vk::SurfaceFormatKHR> {
+    preferences
         .iter()
-        // .cloned()
-        .find(|f| {
-            f.format == vk::Format::B8G8R8_SRGB &&
-            f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-        }).unwrap_or_else(|| &formats[0])
+        .find(|p| available.contains(p))
+        .or_else(|| available.iter().find(|f| OutputEncoding::of(**f).is_some()))
+        .copied()
+        .ok_or_else(|| anyhow!("The surface offers no usable formats: {:?}.", available))
+}
+
+/// How frames are synchronized with the display.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PresentPolicy {
+    /// Wait for vertical blank, never tearing.
+    Vsync,
+    /// Present immediately, which may tear.
+    Off,
+    /// Wait for vertical blank unless a frame is late, which then tears.
+    Adaptive,
+    /// Replace the queued frame with the newest one, never tearing.
+    #[default]
+    LowLatency,
 }
 
-pub fn get_swapchain_present_mode (
-    present_mode: &[vk::PresentModeKHR]
+impl PresentPolicy {
+    pub const ALL: &'static [PresentPolicy] = &[
+        PresentPolicy::Vsync,
+        PresentPolicy::Off,
+        PresentPolicy::Adaptive,
+        PresentPolicy::LowLatency,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PresentPolicy::Vsync => "vsync",
+            PresentPolicy::Off => "off",
+            PresentPolicy::Adaptive => "adaptive",
+            PresentPolicy::LowLatency => "low_latency",
+        }
+    }
+
+    /// The policy after this one in `ALL`, wrapping around.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|p| *p == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Present modes that implement the policy, best first. `FIFO` is always
+    /// supported and used if none of these are.
+    pub fn present_modes(self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentPolicy::Vsync => &[vk::PresentModeKHR::FIFO],
+            PresentPolicy::Off => &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX],
+            PresentPolicy::Adaptive => &[vk::PresentModeKHR::FIFO_RELAXED],
+            PresentPolicy::LowLatency => &[vk::PresentModeKHR::MAILBOX],
+        }
+    }
+}
+
+impl FromStr for PresentPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| anyhow!(
+                "Unknown present mode `{}`, expected one of {:?}.",
+                s, Self::ALL.iter().map(|p| p.name()).collect::<Vec<_>>(),
+            ))
+    }
+}
+
+pub fn get_swapchain_present_mode(
+    available: &[vk::PresentModeKHR],
+    policy: PresentPolicy,
 ) -> vk::PresentModeKHR {
-    present_mode
+    policy
+        .present_modes()
         .iter()
-        .cloned()
-        .find(|p| *p == vk::PresentModeKHR::MAILBOX)
+        .copied()
+        .find(|m| available.contains(m))
         .unwrap_or(vk::PresentModeKHR::FIFO)
 }
 
+/// How many swapchain images to ask for.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ImageCountStrategy {
+    /// The fewest the surface allows.
+    Minimum,
+    /// One more than the minimum, so rendering rarely waits for an image.
+    #[default]
+    OneExtra,
+    /// A fixed count, e.g. 2 for double or 3 for triple buffering.
+    Exact(u32),
+}
+
+impl ImageCountStrategy {
+    /// The count for a surface, within its limits.
+    pub fn image_count(self, capabilities: &vk::SurfaceCapabilitiesKHR) -> u32 {
+        let count = match self {
+            ImageCountStrategy::Minimum => capabilities.min_image_count,
+            ImageCountStrategy::OneExtra => capabilities.min_image_count + 1,
+            ImageCountStrategy::Exact(count) => count.max(capabilities.min_image_count),
+        };
+        // A maximum of 0 means there is no limit.
+        if capabilities.max_image_count != 0 {
+            count.min(capabilities.max_image_count)
+        } else {
+            count
+        }
+    }
+}
+
+impl FromStr for ImageCountStrategy {
+    type Err = anyhow::Error;
+
+    /// Parses `min`, `extra` or a count.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "min" => Ok(ImageCountStrategy::Minimum),
+            "extra" => Ok(ImageCountStrategy::OneExtra),
+            _ => s
+                .parse()
+                .map(ImageCountStrategy::Exact)
+                .map_err(|_| anyhow!("Invalid swapchain image count `{}`, expected `min`, `extra` or a number.", s)),
+        }
+    }
+}
+
 pub fn get_swapchain_extent(
     window: &Window,
     capabilities: vk::SurfaceCapabilitiesKHR
@@ -75,3 +267,60 @@ pub fn get_swapchain_extent(
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferences_are_ranked_and_fall_back_to_encodable_formats() {
+        let available = [
+            surface_format(vk::Format::R8G8B8A8_UNORM, vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+            surface_format(vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            surface_format(vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT),
+        ];
+
+        let preferences = parse_surface_formats("scrgb, hdr10,bgra8_unorm").unwrap();
+        let chosen = get_swapchain_surface_format(&available, &preferences).unwrap();
+        assert_eq!(chosen.color_space, vk::ColorSpaceKHR::HDR10_ST2084_EXT);
+        assert_eq!(OutputEncoding::of(chosen), Some(OutputEncoding::Pq));
+
+        // Display P3 can't be encoded for, so the sRGB UNORM format is next.
+        let chosen = get_swapchain_surface_format(&available, DEFAULT_SURFACE_FORMATS).unwrap();
+        assert_eq!(chosen.format, vk::Format::B8G8R8A8_UNORM);
+        assert_eq!(OutputEncoding::of(chosen), Some(OutputEncoding::Srgb));
+
+        assert!(get_swapchain_surface_format(&[], DEFAULT_SURFACE_FORMATS).is_err());
+    }
+
+    #[test]
+    fn present_policies_fall_back_to_fifo() {
+        let available = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX];
+        assert_eq!(get_swapchain_present_mode(&available, PresentPolicy::Off), vk::PresentModeKHR::MAILBOX);
+        assert_eq!(get_swapchain_present_mode(&available, PresentPolicy::Adaptive), vk::PresentModeKHR::FIFO);
+        assert_eq!(get_swapchain_present_mode(&available, PresentPolicy::LowLatency), vk::PresentModeKHR::MAILBOX);
+
+        assert_eq!("adaptive".parse::<PresentPolicy>().unwrap(), PresentPolicy::Adaptive);
+        assert!("fast".parse::<PresentPolicy>().is_err());
+        assert_eq!(PresentPolicy::LowLatency.next(), PresentPolicy::Vsync);
+    }
+
+    #[test]
+    fn image_counts_stay_within_surface_limits() {
+        let capabilities = vk::SurfaceCapabilitiesKHR { min_image_count: 2, max_image_count: 3, ..Default::default() };
+        assert_eq!(ImageCountStrategy::Minimum.image_count(&capabilities), 2);
+        assert_eq!(ImageCountStrategy::OneExtra.image_count(&capabilities), 3);
+        assert_eq!("8".parse::<ImageCountStrategy>().unwrap().image_count(&capabilities), 3);
+        assert_eq!(ImageCountStrategy::Exact(1).image_count(&capabilities), 2);
+
+        let unlimited = vk::SurfaceCapabilitiesKHR { max_image_count: 0, ..capabilities };
+        assert_eq!(ImageCountStrategy::Exact(8).image_count(&unlimited), 8);
+        assert!("lots".parse::<ImageCountStrategy>().is_err());
+    }
+
+    #[test]
+    fn unknown_surface_format_names_are_errors() {
+        let error = parse_surface_formats("bgra8_srgb,hdr12").unwrap_err();
+        assert!(error.to_string().contains("hdr12"), "{}", error);
+        assert_eq!(OutputEncoding::of(DEFAULT_SURFACE_FORMATS[0]), Some(OutputEncoding::Hardware));
+    }
+}
@@ -7,6 +7,7 @@ use vulkanalia::vk::{self, KhrSwapchainExtension};
 use vulkanalia::prelude::v1_4::*;
 
 use crate::app::data::AppData;
+use crate::core::debug_utils::{set_object_name, set_object_names};
 use crate::device;
 use crate::swapchain::support;
 
@@ -20,15 +21,20 @@ pub unsafe fn create_swapchain (
     let indices = device::queues::QueueFamilyIndices::get(instance, data, data.physical_device)?;
     let support = support::SwapchainSupport::get(instance, data, data.physical_device)?;
 
-    let surface_format = support::get_swapchain_surface_format(&support.formats);
-    let present_mode = support::get_swapchain_present_mode(&support.present_modes);
+    let preferences = if data.surface_format_preferences.is_empty() {
+        support::DEFAULT_SURFACE_FORMATS
+    } else {
+        &data.surface_format_preferences
+    };
+    let surface_format = support::get_swapchain_surface_format(&support.formats, preferences)?;
+    let encoding = support::OutputEncoding::of(surface_format).unwrap_or_default();
+    if !preferences.contains(&surface_format) {
+        warn!("None of the preferred surface formats are available, using {:?}.", surface_format);
+    }
+    let present_mode = support::get_swapchain_present_mode(&support.present_modes, data.present_policy);
     let surface_extent = support::get_swapchain_extent(window, support.capabilities);
 
-    let mut image_count = support.capabilities.min_image_count + 1;
-    if support.capabilities.max_image_count != 0 
-        && support.capabilities.max_image_count < image_count {
-        image_count = support.capabilities.max_image_count;
-    }
+    let image_count = data.image_count_strategy.image_count(&support.capabilities);
 
     let mut queue_family_indices = vec![];
     let image_sharing_mode = if indices.graphics != indices.present {
@@ -42,6 +48,10 @@ pub unsafe fn create_swapchain (
     info!("image sharing mode: {}", if image_sharing_mode == vk::SharingMode::EXCLUSIVE {"Exclusive"} else {"Concurrent"});
     info!("queue family indices: {:?}", queue_family_indices);
 
+    // Transfers out of swapchain images are only used for screenshots.
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (support.capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
     let info = vk::SwapchainCreateInfoKHR::builder()
         .surface(data.surface)
         .min_image_count(image_count)
@@ -49,7 +59,7 @@ pub unsafe fn create_swapchain (
         .image_color_space(surface_format.color_space)
         .image_extent(surface_extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage)
         .image_sharing_mode(image_sharing_mode)
         .queue_family_indices(&queue_family_indices)
         .pre_transform(support.capabilities.current_transform)
@@ -58,10 +68,30 @@ pub unsafe fn create_swapchain (
         .clipped(true)
         .old_swapchain(vk::SwapchainKHR::null());
 
+    let first_swapchain = data.swapchain.is_null();
     data.swapchain = device.create_swapchain_khr(&info, None)?;
     data.swapchain_images = device.get_swapchain_images_khr(data.swapchain)?;
+    set_object_name(device, data, data.swapchain, "swapchain");
+    set_object_names(device, data, &data.swapchain_images, "swapchain image");
+
+    if first_swapchain || data.present_mode != present_mode {
+        info!(
+            "Presenting with {:?} ({} policy) and {} images.",
+            present_mode, data.present_policy.name(), data.swapchain_images.len(),
+        );
+    }
+    data.present_mode = present_mode;
 
+    if data.swapchain_format != surface_format.format || data.swapchain_color_space != surface_format.color_space {
+        info!(
+            "Swapchain format {:?} in color space {:?} ({:?} output encoding).",
+            surface_format.format, surface_format.color_space, encoding,
+        );
+    }
     data.swapchain_format = surface_format.format;
+    data.swapchain_color_space = surface_format.color_space;
+    data.output_encoding = encoding;
+    data.swapchain_usage = image_usage;
     data.swapchain_extent = surface_extent;
 
     Ok(())
@@ -0,0 +1,479 @@
+pub mod renderer;
+
+use anyhow::{anyhow, Context, Result};
+
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::path::Path;
+
+use cgmath::{vec2, vec3, vec4, SquareMatrix, Transform};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::texture::TextureData;
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Point3 = cgmath::Point3<f32>;
+type Vec2 = cgmath::Vector2<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// DejaVu Sans at 20 pixels, built in so text works from any directory.
+pub const DEFAULT_FONT: &str = include_str!("../fonts/dejavu-sans-20.fnt");
+pub const DEFAULT_FONT_ATLAS: &[u8] = include_bytes!("../fonts/dejavu-sans-20.png");
+
+/// Drawn for characters the font doesn't have.
+pub const REPLACEMENT_CHAR: char = '?';
+
+/// Where a glyph is in the atlas and how it's placed, in pixels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Glyph {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// From the pen position to the glyph's top left, with y down from the
+    /// line's top.
+    pub x_offset: i32,
+    pub y_offset: i32,
+    /// How far the pen moves after the glyph.
+    pub x_advance: i32,
+}
+
+/// A bitmap font's metrics, read from a BMFont text file (`.fnt`).
+///
+/// Only single page fonts are supported; the glyphs' coverage is read from
+/// the page's alpha channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Font {
+    pub face: String,
+    /// The distance between lines, in pixels.
+    pub line_height: f32,
+    /// From the top of a line to its baseline.
+    pub base: f32,
+    pub atlas_width: u32,
+    pub atlas_height: u32,
+    /// The atlas file, relative to the metrics file.
+    pub page: String,
+    pub glyphs: HashMap<char, Glyph>,
+    /// How much closer (if negative) a pair of characters is drawn.
+    pub kerning: HashMap<(char, char), i32>,
+}
+
+/// A `tag key=value key="quoted value" ...` line.
+fn parse_line(line: &str) -> (&str, HashMap<&str, &str>) {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let mut attributes = HashMap::new();
+    while let Some((key, after)) = rest.trim_start().split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        attributes.insert(key.trim(), value);
+        rest = after;
+    }
+    (tag, attributes)
+}
+
+impl Font {
+    /// Parses a BMFont text file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut font = Font::default();
+        for (number, line) in text.lines().enumerate() {
+            let (tag, attributes) = parse_line(line);
+            font.apply_line(tag, &attributes)
+                .with_context(|| format!("Invalid font line {}: `{}`.", number + 1, line.trim()))?;
+        }
+
+        if font.atlas_width == 0 || font.atlas_height == 0 {
+            return Err(anyhow!("The font has no `common` line with its atlas size."));
+        }
+        Ok(font)
+    }
+
+    fn apply_line(&mut self, tag: &str, attributes: &HashMap<&str, &str>) -> Result<()> {
+        let get = |key: &str| -> Result<i32> {
+            let value = attributes.get(key).ok_or_else(|| anyhow!("missing `{}`", key))?;
+            value.parse().map_err(|_| anyhow!("`{}` is not a number: `{}`", key, value))
+        };
+        match tag {
+            "info" => self.face = attributes.get("face").unwrap_or(&"").to_string(),
+            "common" => {
+                if get("pages")? != 1 {
+                    return Err(anyhow!("fonts with more than one page aren't supported"));
+                }
+                self.line_height = get("lineHeight")? as f32;
+                self.base = get("base")? as f32;
+                self.atlas_width = get("scaleW")? as u32;
+                self.atlas_height = get("scaleH")? as u32;
+            }
+            "page" => self.page = attributes.get("file").ok_or_else(|| anyhow!("missing `file`"))?.to_string(),
+            "char" => {
+                let id = get("id")?;
+                let c = char::from_u32(id as u32).ok_or_else(|| anyhow!("invalid character {}", id))?;
+                self.glyphs.insert(c, Glyph {
+                    x: get("x")? as u32,
+                    y: get("y")? as u32,
+                    width: get("width")? as u32,
+                    height: get("height")? as u32,
+                    x_offset: get("xoffset")?,
+                    y_offset: get("yoffset")?,
+                    x_advance: get("xadvance")?,
+                });
+            }
+            "kerning" => {
+                let first = char::from_u32(get("first")? as u32);
+                let second = char::from_u32(get("second")? as u32);
+                if let (Some(first), Some(second)) = (first, second) {
+                    self.kerning.insert((first, second), get("amount")?);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Loads a BMFont text file and its atlas PNG.
+    pub fn load(path: impl AsRef<Path>) -> Result<(Self, TextureData)> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read `{}`.", path.display()))?;
+        let font = Self::parse(&text).with_context(|| format!("Invalid font `{}`.", path.display()))?;
+        let atlas = TextureData::load_png(path.with_file_name(&font.page))?;
+        font.check_atlas(&atlas)?;
+        Ok((font, atlas))
+    }
+
+    /// The built-in `DEFAULT_FONT`.
+    pub fn default_font() -> Result<(Self, TextureData)> {
+        let font = Self::parse(DEFAULT_FONT)?;
+        let atlas = TextureData::from_png(DEFAULT_FONT_ATLAS)?;
+        font.check_atlas(&atlas)?;
+        Ok((font, atlas))
+    }
+
+    fn check_atlas(&self, atlas: &TextureData) -> Result<()> {
+        if (atlas.width, atlas.height) != (self.atlas_width, self.atlas_height) {
+            return Err(anyhow!(
+                "The font atlas is {}x{} but the font expects {}x{}.",
+                atlas.width, atlas.height, self.atlas_width, self.atlas_height,
+            ));
+        }
+        Ok(())
+    }
+
+    /// `c`'s glyph, or `REPLACEMENT_CHAR`'s if the font doesn't have it.
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&REPLACEMENT_CHAR))
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> i32 {
+        self.kerning.get(&(first, second)).copied().unwrap_or_default()
+    }
+
+    /// How far the pen moves over `line`, in pixels.
+    pub fn line_width(&self, line: &str) -> f32 {
+        let mut width = 0;
+        let mut previous = None;
+        for c in line.chars() {
+            let Some(glyph) = self.glyph(c) else { continue };
+            if let Some(previous) = previous {
+                width += self.kerning(previous, c);
+            }
+            width += glyph.x_advance;
+            previous = Some(c);
+        }
+        width as f32
+    }
+
+    /// Places `text`'s glyphs, breaking lines at `\n`. The anchor is at the
+    /// top of the first line, on the left, middle or right of each line.
+    pub fn layout(&self, text: &str, align: Align) -> TextLayout {
+        let mut layout = TextLayout::default();
+        let texel = vec2(1.0 / self.atlas_width as f32, 1.0 / self.atlas_height as f32);
+        for (row, line) in text.split('\n').enumerate() {
+            let line = line.trim_end_matches('\r');
+            let width = self.line_width(line);
+            layout.width = layout.width.max(width);
+
+            // Whole pixels, so glyphs map to texels.
+            let mut pen = match align {
+                Align::Left => 0.0,
+                Align::Center => (-width / 2.0).round(),
+                Align::Right => -width,
+            };
+            let top = row as f32 * self.line_height;
+            let mut previous = None;
+            for c in line.chars() {
+                let Some(glyph) = self.glyph(c) else { continue };
+                if let Some(previous) = previous {
+                    pen += self.kerning(previous, c) as f32;
+                }
+                previous = Some(c);
+
+                if glyph.width > 0 && glyph.height > 0 {
+                    let min = vec2(pen + glyph.x_offset as f32, top + glyph.y_offset as f32);
+                    let size = vec2(glyph.width as f32, glyph.height as f32);
+                    let uv_min = vec2(glyph.x as f32 * texel.x, glyph.y as f32 * texel.y);
+                    layout.quads.push(GlyphQuad {
+                        min,
+                        max: min + size,
+                        uv_min,
+                        uv_max: uv_min + vec2(size.x * texel.x, size.y * texel.y),
+                    });
+                }
+                pen += glyph.x_advance as f32;
+            }
+            layout.height += self.line_height;
+        }
+        layout
+    }
+}
+
+/// Where each line of text starts relative to the anchor.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    /// Lines start at the anchor.
+    #[default]
+    Left,
+    /// Lines are centered on the anchor.
+    Center,
+    /// Lines end at the anchor.
+    Right,
+}
+
+/// A glyph's rectangle in pixels from the anchor (with y down), and in the
+/// atlas in texture coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphQuad {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout {
+    pub quads: Vec<GlyphQuad>,
+    /// The widest line's width, in pixels.
+    pub width: f32,
+    pub height: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextVertex {
+    pub position: Vec3,
+    pub uv: Vec2,
+    /// Linear RGB and alpha.
+    pub color: Vec4,
+}
+
+impl TextVertex {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<TextVertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        let position = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+        let uv = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(size_of::<Vec3>() as u32)
+            .build();
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((size_of::<Vec3>() + size_of::<Vec2>()) as u32)
+            .build();
+        [position, uv, color]
+    }
+}
+
+/// How text added to a `TextBatch` looks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub color: Vec4,
+    /// Multiplies the font's pixel size.
+    pub scale: f32,
+    pub align: Align,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self { color: vec4(1.0, 1.0, 1.0, 1.0), scale: 1.0, align: Align::Left }
+    }
+}
+
+/// Glyph quads to draw this frame, four vertices each (top left, top right,
+/// bottom right, bottom left). Like `DebugDraw`, text is added every frame
+/// it should be seen, then cleared.
+#[derive(Clone, Debug, Default)]
+pub struct TextBatch {
+    /// In pixels from the window's top left.
+    pub screen: Vec<TextVertex>,
+    /// In world space, drawn in the scene.
+    pub world: Vec<TextVertex>,
+}
+
+impl TextBatch {
+    /// Adds `text` anchored at `position`, in pixels from the window's top
+    /// left.
+    pub fn screen(&mut self, font: &Font, text: &str, position: Vec2, style: TextStyle) {
+        let layout = font.layout(text, style.align);
+        push_quads(&mut self.screen, &layout, style, |p| (position + p * style.scale).extend(0.0));
+    }
+
+    /// Adds `text` on the plane `transform` maps it to: the anchor at the
+    /// origin, x right and y up, one unit per font pixel. See `billboard`.
+    pub fn world(&mut self, font: &Font, text: &str, transform: Mat4, style: TextStyle) {
+        let layout = font.layout(text, style.align);
+        push_quads(&mut self.world, &layout, style, |p| {
+            let p = p * style.scale;
+            transform.transform_point(Point3::new(p.x, -p.y, 0.0)).to_homogeneous().truncate()
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.screen.clear();
+        self.world.clear();
+    }
+
+    /// How many quads there are in the world and on the screen.
+    pub fn quad_counts(&self) -> (usize, usize) {
+        (self.world.len() / 4, self.screen.len() / 4)
+    }
+}
+
+fn push_quads(vertices: &mut Vec<TextVertex>, layout: &TextLayout, style: TextStyle, position: impl Fn(Vec2) -> Vec3) {
+    for quad in &layout.quads {
+        let corners = [
+            (vec2(quad.min.x, quad.min.y), vec2(quad.uv_min.x, quad.uv_min.y)),
+            (vec2(quad.max.x, quad.min.y), vec2(quad.uv_max.x, quad.uv_min.y)),
+            (vec2(quad.max.x, quad.max.y), vec2(quad.uv_max.x, quad.uv_max.y)),
+            (vec2(quad.min.x, quad.max.y), vec2(quad.uv_min.x, quad.uv_max.y)),
+        ];
+        for (p, uv) in corners {
+            vertices.push(TextVertex { position: position(p), uv, color: style.color });
+        }
+    }
+}
+
+/// A transform for `TextBatch::world` facing a camera with `view`, with
+/// the anchor at `position` and each font pixel `pixel_size` world units.
+pub fn billboard(position: Point3, view: Mat4, pixel_size: f32) -> Mat4 {
+    let mut transform = view.invert().unwrap_or_else(Mat4::identity) * Mat4::from_scale(pixel_size);
+    transform.w = position.to_homogeneous();
+    transform
+}
+
+/// Maps pixels from the top left of an `extent` sized target to Vulkan clip
+/// space, for `TextBatch::screen` text.
+pub fn screen_projection(extent: vk::Extent2D) -> Mat4 {
+    Mat4::from_translation(vec3(-1.0, -1.0, 0.0))
+        * Mat4::from_nonuniform_scale(2.0 / extent.width as f32, 2.0 / extent.height as f32, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{assert_abs_diff_eq, point3, Matrix4};
+
+    const FONT: &str = r#"info face="Test Sans" size=10 bold=0
+common lineHeight=12 base=9 scaleW=64 scaleH=32 pages=1 packed=0
+page id=0 file="test.png"
+chars count=3
+char id=65   x=0     y=0     width=6     height=8     xoffset=0     yoffset=1     xadvance=7     page=0  chnl=15
+char id=86   x=8     y=0     width=6     height=8     xoffset=1     yoffset=1     xadvance=6     page=0  chnl=15
+char id=63   x=16    y=0     width=4     height=8     xoffset=0     yoffset=1     xadvance=5     page=0  chnl=15
+char id=32   x=0     y=0     width=0     height=0     xoffset=0     yoffset=0     xadvance=3     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-2
+"#;
+
+    #[test]
+    fn bmfont_text_is_parsed() {
+        let font = Font::parse(FONT).unwrap();
+        assert_eq!(font.face, "Test Sans");
+        assert_eq!((font.line_height, font.base), (12.0, 9.0));
+        assert_eq!((font.atlas_width, font.atlas_height), (64, 32));
+        assert_eq!(font.page, "test.png");
+        assert_eq!(font.glyphs.len(), 4);
+        assert_eq!(font.glyphs[&'V'], Glyph { x: 8, y: 0, width: 6, height: 8, x_offset: 1, y_offset: 1, x_advance: 6 });
+        assert_eq!(font.kerning('A', 'V'), -2);
+        assert_eq!(font.kerning('V', 'A'), 0);
+
+        let error = Font::parse(&FONT.replace("pages=1", "pages=2")).unwrap_err();
+        assert!(format!("{:#}", error).contains("line 2"), "{:#}", error);
+        assert!(Font::parse("char id=65 x=oops").is_err());
+        assert!(Font::default_font().is_ok());
+    }
+
+    #[test]
+    fn layout_applies_kerning_and_line_breaks() {
+        let font = Font::parse(FONT).unwrap();
+        assert_eq!(font.line_width("AV"), 7.0 - 2.0 + 6.0);
+        // Missing characters are drawn as `?`.
+        assert_eq!(font.line_width("AxA"), 7.0 + 5.0 + 7.0);
+
+        let layout = font.layout("AV\nA A", Align::Left);
+        assert_eq!(layout.quads.len(), 4);
+        assert_eq!((layout.width, layout.height), (17.0, 24.0));
+        assert_eq!(layout.quads[1].min, vec2(7.0 - 2.0 + 1.0, 1.0));
+        assert_eq!(layout.quads[1].max, vec2(12.0, 9.0));
+        assert_eq!(layout.quads[1].uv_min, vec2(8.0 / 64.0, 0.0));
+        assert_eq!(layout.quads[1].uv_max, vec2(14.0 / 64.0, 8.0 / 32.0));
+        // The second line, after the space's advance.
+        assert_eq!(layout.quads[3].min, vec2(10.0, 13.0));
+    }
+
+    #[test]
+    fn lines_are_aligned_to_the_anchor() {
+        let font = Font::parse(FONT).unwrap();
+        let left = |layout: &TextLayout, i: usize| layout.quads[i].min.x;
+
+        let layout = font.layout("A\nAAA", Align::Center);
+        assert_eq!((left(&layout, 0), left(&layout, 1)), (-4.0, -11.0));
+        let layout = font.layout("A\nAAA", Align::Right);
+        assert_eq!((left(&layout, 0), left(&layout, 1)), (-7.0, -21.0));
+    }
+
+    #[test]
+    fn batches_place_quads_on_screen_and_in_the_world() {
+        let font = Font::parse(FONT).unwrap();
+        let mut batch = TextBatch::default();
+        let style = TextStyle { scale: 2.0, ..TextStyle::default() };
+        batch.screen(&font, "A", vec2(100.0, 50.0), style);
+        batch.world(&font, "AV", Matrix4::from_translation(vec3(0.0, 10.0, 0.0)), style);
+        assert_eq!(batch.quad_counts(), (2, 1));
+        assert_eq!(batch.screen[0].position, vec3(100.0, 52.0, 0.0));
+        assert_eq!(batch.screen[2].position, vec3(112.0, 68.0, 0.0));
+        assert_eq!(batch.screen[2].uv, vec2(6.0 / 64.0, 8.0 / 32.0));
+        // World text has y up.
+        assert_eq!(batch.world[0].position, vec3(0.0, 8.0, 0.0));
+
+        let clip = screen_projection(vk::Extent2D { width: 200, height: 100 });
+        assert_abs_diff_eq!(clip.transform_point(point3(100.0, 50.0, 0.0)), point3(0.0, 0.0, 0.0));
+        assert_abs_diff_eq!(clip.transform_point(point3(200.0, 0.0, 0.0)), point3(1.0, -1.0, 0.0));
+
+        let view = Matrix4::look_at_rh(point3(0.0, 0.0, 5.0), point3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+        let transform = billboard(point3(1.0, 2.0, 3.0), view, 0.5);
+        assert_abs_diff_eq!(transform.transform_point(point3(2.0, 0.0, 0.0)), point3(2.0, 2.0, 3.0));
+
+        batch.clear();
+        assert_eq!(batch.quad_counts(), (0, 0));
+    }
+}
@@ -0,0 +1,316 @@
+use anyhow::Result;
+use log::*;
+
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use cgmath::vec4;
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::{create_buffer, create_device_local_buffer, slice_as_bytes, UploadTarget};
+use crate::core::debug_utils::{set_object_name, set_object_names};
+use crate::descriptor;
+use crate::frame::sync::MAX_FRAMES_IN_FLIGHT;
+use crate::pipeline::graphics::{create_graphics_pipeline, BlendMode, GraphicsPipelineInfo};
+use crate::pipeline::push_constants::{cmd_push_constants, push_constant_range, PushConstants};
+use crate::post;
+use crate::text::{screen_projection, Font, TextBatch, TextVertex};
+use crate::texture::{create_texture, destroy_texture, SamplerInfo, Texture, TextureData};
+
+type Mat4 = cgmath::Matrix4<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// How many glyphs a frame can draw, in the world and on the screen
+/// together. Glyphs past this are dropped. Their vertices are indexed with
+/// 16 bits.
+pub const MAX_TEXT_QUADS: usize = 16_384;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TextPushConstants {
+    transform: Mat4,
+    /// See `post::output_params`.
+    output_params: Vec4,
+}
+
+const _: () = assert!(size_of::<TextPushConstants>() == 80);
+
+// SAFETY: `#[repr(C)]` and `Mat4` and `Vec4` are all `f32`s, so there is no padding.
+unsafe impl PushConstants for TextPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_bits_truncate(
+        vk::ShaderStageFlags::VERTEX.bits() | vk::ShaderStageFlags::FRAGMENT.bits(),
+    );
+}
+
+/// Draws a `TextBatch` with alpha blending: world text in the scene render
+/// pass and screen text in the post-processor's overlay pass, so it isn't
+/// tonemapped.
+#[derive(Clone, Debug, Default)]
+pub struct TextRenderer {
+    pub font: Font,
+    pub atlas: Texture,
+    /// The atlas at binding 0 and its sampler at binding 1.
+    pub set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub set: vk::DescriptorSet,
+    /// Two triangles for each of `MAX_TEXT_QUADS` quads.
+    pub index_buffer: vk::Buffer,
+    pub index_buffer_memory: vk::DeviceMemory,
+    pub vertex_buffers: Vec<vk::Buffer>,
+    pub vertex_buffers_memory: Vec<vk::DeviceMemory>,
+    /// How many world and screen quads each frame's buffer holds, in that
+    /// order.
+    pub quad_counts: Vec<(u32, u32)>,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub world_pipeline: vk::Pipeline,
+    pub screen_pipeline: vk::Pipeline,
+    /// The swapchain format `screen_pipeline` was created for.
+    screen_format: vk::Format,
+    /// Whether dropping glyphs was already logged.
+    warned: bool,
+}
+
+/// Creates the text renderer for `font` and its atlas. Needs the scene
+/// render pass and the post-processor.
+pub unsafe fn create_text_renderer(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    font: Font,
+    atlas: &TextureData,
+) -> Result<()> {
+    let mut renderer = TextRenderer { font, ..TextRenderer::default() };
+
+    let atlas = TextureData {
+        sampler: SamplerInfo {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..SamplerInfo::default()
+        },
+        ..atlas.clone()
+    };
+    renderer.atlas = create_texture(instance, device, data, &atlas, vk::Format::R8G8B8A8_UNORM)?;
+
+    let stages = vk::ShaderStageFlags::FRAGMENT;
+    let bindings = &[
+        descriptor::sampled_image_binding(0, stages),
+        descriptor::sampler_binding(1, stages),
+    ];
+    renderer.set_layout = descriptor::create_descriptor_set_layout(device, bindings)?;
+    let size = |type_| {
+        vk::DescriptorPoolSize::builder()
+            .type_(type_)
+            .descriptor_count(1)
+            .build()
+    };
+    let sizes = &[size(vk::DescriptorType::SAMPLED_IMAGE), size(vk::DescriptorType::SAMPLER)];
+    renderer.descriptor_pool = descriptor::create_descriptor_pool(device, sizes, 1)?;
+    renderer.set = descriptor::allocate_descriptor_set(device, renderer.descriptor_pool, renderer.set_layout)?;
+    descriptor::write_sampled_image(device, renderer.set, 0, renderer.atlas.image_view);
+    descriptor::write_sampler(device, renderer.set, 1, renderer.atlas.sampler);
+
+    let indices = (0..MAX_TEXT_QUADS as u16)
+        .flat_map(|quad| [0, 1, 2, 2, 3, 0].map(|i| quad * 4 + i))
+        .collect::<Vec<u16>>();
+    (renderer.index_buffer, renderer.index_buffer_memory) = create_device_local_buffer(
+        instance,
+        device,
+        data,
+        // SAFETY: `u16` has no padding.
+        slice_as_bytes(&indices),
+        vk::BufferUsageFlags::INDEX_BUFFER,
+        UploadTarget::graphics(data, vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::INDEX_READ),
+    )?;
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            data,
+            (MAX_TEXT_QUADS * 4 * size_of::<TextVertex>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        renderer.vertex_buffers.push(buffer);
+        renderer.vertex_buffers_memory.push(buffer_memory);
+        renderer.quad_counts.push((0, 0));
+    }
+
+    let set_layouts = &[renderer.set_layout];
+    let push_constant_ranges = &[push_constant_range::<TextPushConstants>(instance, data)?];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    renderer.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    // Drawn after the scene without writing depth, so text behind other
+    // text still shows.
+    let bindings = &[TextVertex::binding_description()];
+    let attributes = &TextVertex::attribute_descriptions();
+    renderer.world_pipeline = create_graphics_pipeline(device, &GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        depth_test: true,
+        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        samples: data.msaa_samples,
+        ..text_pipeline_info(renderer.pipeline_layout, data.render_pass)
+    })?;
+
+    data.text = renderer;
+    create_text_screen_pipeline(device, data)?;
+
+    let renderer = &data.text;
+    set_object_name(device, data, renderer.atlas.image, "font atlas");
+    set_object_name(device, data, renderer.atlas.image_view, "font atlas view");
+    set_object_name(device, data, renderer.set, "font atlas set");
+    set_object_name(device, data, renderer.index_buffer, "text indices");
+    set_object_names(device, data, &renderer.vertex_buffers, "text vertices");
+    set_object_name(device, data, renderer.pipeline_layout, "text pipeline layout");
+    set_object_name(device, data, renderer.world_pipeline, "world text pipeline");
+
+    Ok(())
+}
+
+fn text_pipeline_info<'a>(layout: vk::PipelineLayout, render_pass: vk::RenderPass) -> GraphicsPipelineInfo<'a> {
+    GraphicsPipelineInfo {
+        cull_mode: vk::CullModeFlags::NONE,
+        blend_mode: BlendMode::Alpha,
+        ..GraphicsPipelineInfo::new(
+            &include_bytes!("../shaders/text_vert.spv")[..],
+            &include_bytes!("../shaders/text_frag.spv")[..],
+            layout,
+            render_pass,
+        )
+    }
+}
+
+/// Creates the screen text pipeline for the overlay render pass, unless the
+/// swapchain format is the same as last time. Call after the post-processor's
+/// targets are recreated.
+pub unsafe fn create_text_screen_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let renderer = &mut data.text;
+    if renderer.screen_format == data.post.present_format && !renderer.screen_pipeline.is_null() {
+        return Ok(());
+    }
+    device.destroy_pipeline(renderer.screen_pipeline, None);
+
+    let bindings = &[TextVertex::binding_description()];
+    let attributes = &TextVertex::attribute_descriptions();
+    renderer.screen_pipeline = create_graphics_pipeline(device, &GraphicsPipelineInfo {
+        vertex_bindings: bindings,
+        vertex_attributes: attributes,
+        ..text_pipeline_info(renderer.pipeline_layout, data.post.overlay_render_pass)
+    })?;
+    renderer.screen_format = data.post.present_format;
+
+    set_object_name(device, data, data.text.screen_pipeline, "screen text pipeline");
+
+    Ok(())
+}
+
+/// Writes `batch`'s glyphs for `frame`, whose previous submission must have
+/// completed.
+pub unsafe fn update_text(device: &Device, renderer: &mut TextRenderer, frame: usize, batch: &TextBatch) -> Result<()> {
+    let (mut world, mut screen) = (&batch.world[..], &batch.screen[..]);
+    if world.len() + screen.len() > MAX_TEXT_QUADS * 4 {
+        if !renderer.warned {
+            warn!("Too much text to draw, dropping some.");
+            renderer.warned = true;
+        }
+        world = &world[..world.len().min(MAX_TEXT_QUADS * 4)];
+        screen = &screen[..MAX_TEXT_QUADS * 4 - world.len()];
+    }
+
+    renderer.quad_counts[frame] = ((world.len() / 4) as u32, (screen.len() / 4) as u32);
+    if world.is_empty() && screen.is_empty() {
+        return Ok(());
+    }
+
+    let memory = renderer.vertex_buffers_memory[frame];
+    let size = (world.len() + screen.len()) * size_of::<TextVertex>();
+    let mapped = device.map_memory(memory, 0, size as vk::DeviceSize, vk::MemoryMapFlags::empty())?;
+    let mapped = mapped.cast::<TextVertex>();
+    memcpy(world.as_ptr(), mapped, world.len());
+    memcpy(screen.as_ptr(), mapped.add(world.len()), screen.len());
+    device.unmap_memory(memory);
+
+    Ok(())
+}
+
+/// Records drawing the world text written for `frame`. Must be recorded in
+/// the scene render pass, after the scene so text blends over it.
+pub unsafe fn cmd_draw_world_text(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &TextRenderer,
+    frame: usize,
+    view_proj: Mat4,
+) {
+    let (world, _) = renderer.quad_counts[frame];
+    let push_constants = TextPushConstants { transform: view_proj, output_params: vec4(0.0, 1.0, 0.0, 0.0) };
+    cmd_draw_quads(device, command_buffer, renderer, frame, renderer.world_pipeline, &push_constants, world, 0);
+}
+
+/// Records drawing the screen text written for `frame`, encoded for the
+/// swapchain like `post`'s output. Must be recorded in the overlay render
+/// pass, see `post::processor::cmd_begin_overlay`.
+pub unsafe fn cmd_draw_screen_text(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &TextRenderer,
+    frame: usize,
+    post: &post::processor::PostProcessor,
+    extent: vk::Extent2D,
+) {
+    let (world, screen) = renderer.quad_counts[frame];
+    let push_constants = TextPushConstants {
+        transform: screen_projection(extent),
+        output_params: post::output_params(post.output_encoding, post.paper_white_nits),
+    };
+    cmd_draw_quads(device, command_buffer, renderer, frame, renderer.screen_pipeline, &push_constants, screen, world);
+}
+
+unsafe fn cmd_draw_quads(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    renderer: &TextRenderer,
+    frame: usize,
+    pipeline: vk::Pipeline,
+    push_constants: &TextPushConstants,
+    count: u32,
+    first: u32,
+) {
+    if count == 0 {
+        return;
+    }
+
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        renderer.pipeline_layout,
+        0,
+        &[renderer.set],
+        &[],
+    );
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[renderer.vertex_buffers[frame]], &[0]);
+    device.cmd_bind_index_buffer(command_buffer, renderer.index_buffer, 0, vk::IndexType::UINT16);
+    cmd_push_constants(device, command_buffer, renderer.pipeline_layout, push_constants);
+    device.cmd_draw_indexed(command_buffer, count * 6, 1, 0, (first * 4) as i32, 0);
+}
+
+pub unsafe fn destroy_text_renderer(device: &Device, renderer: &mut TextRenderer) {
+    device.destroy_pipeline(renderer.screen_pipeline, None);
+    device.destroy_pipeline(renderer.world_pipeline, None);
+    device.destroy_pipeline_layout(renderer.pipeline_layout, None);
+    renderer.vertex_buffers.iter().for_each(|b| device.destroy_buffer(*b, None));
+    renderer.vertex_buffers_memory.iter().for_each(|m| device.free_memory(*m, None));
+    device.destroy_buffer(renderer.index_buffer, None);
+    device.free_memory(renderer.index_buffer_memory, None);
+    device.destroy_descriptor_pool(renderer.descriptor_pool, None);
+    device.destroy_descriptor_set_layout(renderer.set_layout, None);
+    destroy_texture(device, &mut renderer.atlas);
+    *renderer = TextRenderer::default();
+}
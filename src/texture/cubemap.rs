@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+
+use std::f32::consts::PI;
+use std::path::Path;
+
+use cgmath::{vec3, InnerSpace};
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::UploadTarget;
+use crate::image::{create_device_local_layered_image, create_layer_view};
+use crate::texture::{create_sampler, SamplerInfo, Texture, TextureData};
+
+type Vec3 = cgmath::Vector3<f32>;
+
+/// The faces of a cubemap in layer order.
+pub const CUBE_FACES: [&str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+
+/// Six square 8-bit RGBA faces on the CPU.
+///
+/// Faces are in Vulkan's layer order (+X, -X, +Y, -Y, +Z, -Z) and oriented
+/// the way Vulkan samples them, see `face_direction`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CubemapData {
+    /// Width and height of each face.
+    pub size: u32,
+    /// `size * size` RGBA pixels per face, row by row from the top.
+    pub faces: [Vec<u8>; 6],
+}
+
+impl CubemapData {
+    /// A cubemap from six faces of the same square size, in layer order.
+    pub fn from_faces(faces: [TextureData; 6]) -> Result<Self> {
+        let size = faces[0].width;
+        for (face, name) in faces.iter().zip(CUBE_FACES) {
+            if face.width != size || face.height != size {
+                return Err(anyhow!(
+                    "Cubemap face {} is {}x{} but faces must be {}x{}.",
+                    name, face.width, face.height, size, size,
+                ));
+            }
+        }
+        Ok(Self { size, faces: faces.map(|f| f.pixels) })
+    }
+
+    /// Loads six PNG faces, in layer order.
+    pub fn load_faces<P: AsRef<Path>>(paths: [P; 6]) -> Result<Self> {
+        let [px, nx, py, ny, pz, nz] = paths;
+        Self::from_faces([
+            TextureData::load_png(px)?,
+            TextureData::load_png(nx)?,
+            TextureData::load_png(py)?,
+            TextureData::load_png(ny)?,
+            TextureData::load_png(pz)?,
+            TextureData::load_png(nz)?,
+        ])
+    }
+
+    /// A cubemap with `size`x`size` faces whose texels are `f` of the
+    /// (normalized) direction through their center.
+    pub fn from_fn(size: u32, mut f: impl FnMut(Vec3) -> [u8; 4]) -> Self {
+        let mut faces: [Vec<u8>; 6] = Default::default();
+        for (face, pixels) in faces.iter_mut().enumerate() {
+            pixels.reserve((size * size * 4) as usize);
+            for y in 0..size {
+                for x in 0..size {
+                    pixels.extend(f(face_direction(face, x, y, size)));
+                }
+            }
+        }
+        Self { size, faces }
+    }
+
+    /// Resamples an equirectangular (longitude-latitude) panorama, whose
+    /// center looks down -Z with +Y up, into faces of `size`x`size`.
+    pub fn from_equirectangular(panorama: &TextureData, size: u32) -> Self {
+        Self::from_fn(size, |direction| {
+            let (u, v) = equirectangular_uv(direction);
+            sample_bilinear(panorama, u, v)
+        })
+    }
+
+    pub fn load_equirectangular(path: impl AsRef<Path>, size: u32) -> Result<Self> {
+        Ok(Self::from_equirectangular(&TextureData::load_png(path)?, size))
+    }
+}
+
+/// The direction through the center of texel (`x`, `y`) of `face`, matching
+/// how Vulkan picks a face and texel for a sampled direction.
+pub fn face_direction(face: usize, x: u32, y: u32, size: u32) -> Vec3 {
+    let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+    let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+    let direction = match face {
+        0 => vec3(1.0, -t, -s),
+        1 => vec3(-1.0, -t, s),
+        2 => vec3(s, 1.0, t),
+        3 => vec3(s, -1.0, -t),
+        4 => vec3(s, -t, 1.0),
+        _ => vec3(-s, -t, -1.0),
+    };
+    direction.normalize()
+}
+
+/// Where `direction` lands in an equirectangular image: U goes around from
+/// +X through -Z (the center) and V from +Y (the top) to -Y.
+pub fn equirectangular_uv(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize();
+    let longitude = direction.x.atan2(-direction.z);
+    let latitude = direction.y.clamp(-1.0, 1.0).asin();
+    (0.5 + longitude / (2.0 * PI), 0.5 - latitude / PI)
+}
+
+/// Bilinearly samples `texture`, wrapping around horizontally and clamping vertically.
+fn sample_bilinear(texture: &TextureData, u: f32, v: f32) -> [u8; 4] {
+    let (width, height) = (texture.width as i64, texture.height as i64);
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: i64, y: i64| {
+        let index = (y.clamp(0, height - 1) * width + x.rem_euclid(width)) as usize * 4;
+        &texture.pixels[index..index + 4]
+    };
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let (a, b) = (texel(x0, y0), texel(x0 + 1, y0));
+    let (c, d) = (texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+
+    let mut color = [0; 4];
+    for i in 0..4 {
+        let top = a[i] as f32 * (1.0 - fx) + b[i] as f32 * fx;
+        let bottom = c[i] as f32 * (1.0 - fx) + d[i] as f32 * fx;
+        color[i] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    color
+}
+
+/// Uploads a cubemap with a `CUBE` view and a clamped linear sampler. `format`
+/// must be an 8-bit RGBA format, `R8G8B8A8_SRGB` for colors.
+pub unsafe fn create_cubemap(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    cubemap: &CubemapData,
+    format: vk::Format,
+) -> Result<Texture> {
+    let (image, image_memory) = create_device_local_layered_image(
+        instance,
+        device,
+        data,
+        cubemap.size,
+        cubemap.size,
+        6,
+        vk::ImageCreateFlags::CUBE_COMPATIBLE,
+        format,
+        &cubemap.faces.concat(),
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_READ,
+        ),
+    )?;
+    let image_view = create_layer_view(device, image, format, vk::ImageAspectFlags::COLOR, vk::ImageViewType::CUBE, 0, 6)?;
+    let sampler = create_sampler(device, &SamplerInfo {
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        ..SamplerInfo::default()
+    })?;
+
+    Ok(Texture { image, image_memory, image_view, sampler })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::assert_abs_diff_eq;
+
+    #[test]
+    fn face_centers_point_along_the_axes() {
+        let axes = [
+            vec3(1.0, 0.0, 0.0),
+            vec3(-1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 0.0, -1.0),
+        ];
+        for (face, axis) in axes.into_iter().enumerate() {
+            // The center of a 1x1 face is the center of the face.
+            assert_abs_diff_eq!(face_direction(face, 0, 0, 1), axis);
+        }
+
+        // The top row of a side face looks up, the top row of +Y looks towards -Z.
+        assert!(face_direction(4, 1, 0, 2).y > 0.0);
+        assert!(face_direction(2, 1, 0, 2).z < 0.0);
+    }
+
+    #[test]
+    fn equirectangular_center_looks_down_negative_z() {
+        let (u, v) = equirectangular_uv(vec3(0.0, 0.0, -1.0));
+        assert_abs_diff_eq!(u, 0.5);
+        assert_abs_diff_eq!(v, 0.5);
+        assert_abs_diff_eq!(equirectangular_uv(vec3(1.0, 0.0, 0.0)).0, 0.75);
+        assert_abs_diff_eq!(equirectangular_uv(vec3(0.0, 1.0, 0.0)).1, 0.0);
+        assert_abs_diff_eq!(equirectangular_uv(vec3(0.0, -1.0, 0.0)).1, 1.0);
+    }
+
+    #[test]
+    fn equirectangular_resampling_fills_faces_in_layer_order() {
+        // Top half red, bottom half blue.
+        let (width, height) = (8, 4);
+        let pixels = (0..width * height)
+            .flat_map(|i| if i / width < height / 2 { [255, 0, 0, 255] } else { [0, 0, 255, 255] })
+            .collect();
+        let panorama = TextureData { width, height, pixels, ..TextureData::default() };
+        let cubemap = CubemapData::from_equirectangular(&panorama, 4);
+
+        assert_eq!(cubemap.size, 4);
+        assert!(cubemap.faces[2].chunks(4).all(|p| p == [255, 0, 0, 255]));
+        assert!(cubemap.faces[3].chunks(4).all(|p| p == [0, 0, 255, 255]));
+        // Side faces are red above the horizon.
+        assert_eq!(&cubemap.faces[5][..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn faces_must_match() {
+        let face = |size| TextureData { width: size, height: size, pixels: vec![0; (size * size * 4) as usize], ..TextureData::default() };
+        let cubemap = CubemapData::from_faces([face(2), face(2), face(2), face(2), face(2), face(2)]).unwrap();
+        assert_eq!(cubemap.size, 2);
+        assert_eq!(cubemap.faces[5].len(), 16);
+
+        let error = CubemapData::from_faces([face(2), face(2), face(2), face(1), face(2), face(2)]).unwrap_err();
+        assert!(error.to_string().contains("-Y"), "{}", error);
+    }
+}
@@ -0,0 +1,158 @@
+pub mod cubemap;
+
+use anyhow::{anyhow, Context, Result};
+
+use std::path::Path;
+
+use vulkanalia::vk;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::data::AppData;
+use crate::buffer::UploadTarget;
+use crate::image::{create_device_local_image, create_image_view};
+
+/// Index of a texture in a model's or renderer's texture list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub usize);
+
+/// How a texture is filtered and wrapped when sampled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SamplerInfo {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerInfo {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+/// An 8-bit RGBA image on the CPU and how to sample it.
+///
+/// Whether the pixels are sRGB or linear depends on what they are used for
+/// (e.g. base color vs. normals), so it is chosen when the texture is uploaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    /// `width * height` RGBA pixels, row by row from the top.
+    pub pixels: Vec<u8>,
+    pub sampler: SamplerInfo,
+}
+
+impl TextureData {
+    /// Decodes a PNG of any color type and bit depth to 8-bit RGBA.
+    pub fn from_png(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let buffer = &buffer[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer.to_vec(),
+            png::ColorType::Rgb => buffer.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+            png::ColorType::Indexed => return Err(anyhow!("Indexed PNG was not expanded.")),
+        };
+
+        Ok(Self { width: info.width, height: info.height, pixels, sampler: SamplerInfo::default() })
+    }
+
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read `{}`.", path.display()))?;
+        Self::from_png(&bytes).with_context(|| format!("Invalid PNG `{}`.", path.display()))
+    }
+}
+
+/// A texture uploaded to a device local image, with its view and sampler.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Texture {
+    pub image: vk::Image,
+    pub image_memory: vk::DeviceMemory,
+    pub image_view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+/// Uploads a texture for sampling in fragment shaders. `format` must be an
+/// 8-bit RGBA format, `R8G8B8A8_SRGB` for colors or `R8G8B8A8_UNORM` for data.
+pub unsafe fn create_texture(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    texture: &TextureData,
+    format: vk::Format,
+) -> Result<Texture> {
+    let (image, image_memory) = create_device_local_image(
+        instance,
+        device,
+        data,
+        texture.width,
+        texture.height,
+        format,
+        &texture.pixels,
+        UploadTarget::graphics(
+            data,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_READ,
+        ),
+    )?;
+    let image_view = create_image_view(device, image, format, vk::ImageAspectFlags::COLOR)?;
+    let sampler = create_sampler(device, &texture.sampler)?;
+
+    Ok(Texture { image, image_memory, image_view, sampler })
+}
+
+/// A 1x1 texture of a single color, e.g. to stand in for a missing map.
+pub unsafe fn create_solid_texture(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    rgba: [u8; 4],
+    format: vk::Format,
+) -> Result<Texture> {
+    let texture = TextureData { width: 1, height: 1, pixels: rgba.to_vec(), sampler: SamplerInfo::default() };
+    create_texture(instance, device, data, &texture, format)
+}
+
+/// Textures have a single mip level, so only level 0 is ever sampled.
+pub unsafe fn create_sampler(device: &Device, info: &SamplerInfo) -> Result<vk::Sampler> {
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(info.mag_filter)
+        .min_filter(info.min_filter)
+        .mipmap_mode(info.mipmap_mode)
+        .address_mode_u(info.address_mode_u)
+        .address_mode_v(info.address_mode_v)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .compare_enable(false)
+        .min_lod(0.0)
+        .max_lod(0.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false);
+
+    Ok(device.create_sampler(&info, None)?)
+}
+
+pub unsafe fn destroy_texture(device: &Device, texture: &mut Texture) {
+    device.destroy_sampler(texture.sampler, None);
+    device.destroy_image_view(texture.image_view, None);
+    device.destroy_image(texture.image, None);
+    device.free_memory(texture.image_memory, None);
+    *texture = Texture::default();
+}